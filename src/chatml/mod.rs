@@ -2,9 +2,18 @@
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::OnceLock;
 
 use tiktoken_rs::cl100k_base;
 
+/// Maximum length of a message `name` accepted by the OpenAI API.
+const MAX_NAME_LEN: usize = 64;
+
+/// Check if a character is allowed in a message `name` (`[a-zA-Z0-9_-]`).
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
 /// ChatML message roles.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -26,6 +35,28 @@ impl std::fmt::Display for MessageRole {
     }
 }
 
+impl From<crate::MessageRole> for MessageRole {
+    fn from(role: crate::MessageRole) -> Self {
+        match role {
+            crate::MessageRole::System => MessageRole::System,
+            crate::MessageRole::User => MessageRole::User,
+            crate::MessageRole::Assistant => MessageRole::Assistant,
+            crate::MessageRole::Tool => MessageRole::Tool,
+        }
+    }
+}
+
+impl From<MessageRole> for crate::MessageRole {
+    fn from(role: MessageRole) -> Self {
+        match role {
+            MessageRole::System => crate::MessageRole::System,
+            MessageRole::User => crate::MessageRole::User,
+            MessageRole::Assistant => crate::MessageRole::Assistant,
+            MessageRole::Tool => crate::MessageRole::Tool,
+        }
+    }
+}
+
 /// Represents a single ChatML message.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatMLMessage {
@@ -37,6 +68,8 @@ pub struct ChatMLMessage {
     pub tool_call_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_calls: Option<Vec<crate::ToolCall>>,
+    #[serde(default, skip_serializing_if = "crate::is_false")]
+    pub is_error: bool,
 }
 
 impl ChatMLMessage {
@@ -53,6 +86,7 @@ impl ChatMLMessage {
             name,
             tool_call_id: None,
             tool_calls: None,
+            is_error: false,
         }
     }
 
@@ -69,6 +103,7 @@ impl ChatMLMessage {
             name: Some(name),
             tool_call_id: Some(tool_call_id),
             tool_calls: None,
+            is_error: false,
         }
     }
 
@@ -87,6 +122,7 @@ impl ChatMLMessage {
             name: None,
             tool_call_id: None,
             tool_calls: Some(tool_calls),
+            is_error: false,
         }
     }
 
@@ -97,10 +133,13 @@ impl ChatMLMessage {
             "role".to_string(),
             serde_json::Value::String(self.role.to_string()),
         );
-        message.insert(
-            "content".to_string(),
-            serde_json::Value::String(self.content.clone()),
-        );
+        // OpenAI expects `content: null` on tool-call-only assistant messages
+        let content = if self.content.is_empty() && self.tool_calls.is_some() {
+            serde_json::Value::Null
+        } else {
+            serde_json::Value::String(self.content.clone())
+        };
+        message.insert("content".to_string(), content);
 
         if let Some(name) = &self.name {
             message.insert("name".to_string(), serde_json::Value::String(name.clone()));
@@ -122,6 +161,61 @@ impl ChatMLMessage {
         message
     }
 
+    /// Validate tool calls before sending to the OpenAI API.
+    ///
+    /// # Returns
+    /// Ok if tool call IDs are unique and every `function.arguments` is valid
+    /// JSON, otherwise a list of problems found.
+    pub fn validate_tool_calls(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        let mut seen = std::collections::HashSet::new();
+
+        for tool_call in self.tool_calls.iter().flatten() {
+            if !seen.insert(tool_call.id.as_str()) {
+                errors.push(format!("duplicate tool call id `{}`", tool_call.id));
+            }
+            let arguments = &tool_call.function.arguments;
+            if let Err(err) = serde_json::from_str::<serde_json::Value>(arguments) {
+                errors.push(format!(
+                    "tool call `{}` has invalid arguments: {}",
+                    tool_call.id, err
+                ));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Check that the name is accepted by the OpenAI API.
+    ///
+    /// # Returns
+    /// True if there is no name, or the name is 1 to 64 characters matching
+    /// `^[a-zA-Z0-9_-]+$`.
+    pub fn valid_name(&self) -> bool {
+        self.name.as_deref().map_or(true, |name| {
+            !name.is_empty() && name.len() <= MAX_NAME_LEN && name.chars().all(is_name_char)
+        })
+    }
+
+    /// Make the name acceptable to the OpenAI API.
+    ///
+    /// Replaces every character outside `[a-zA-Z0-9_-]` with `_` and
+    /// truncates to 64 characters. An empty name is removed.
+    pub fn sanitize_name(&mut self) {
+        if let Some(name) = &self.name {
+            let sanitized: String = name
+                .chars()
+                .map(|c| if is_name_char(c) { c } else { '_' })
+                .take(MAX_NAME_LEN)
+                .collect();
+            self.name = (!sanitized.is_empty()).then_some(sanitized);
+        }
+    }
+
     /// Convert message to ChatML string format.
     pub fn to_chatml_string(&self) -> String {
         let name_part = if let Some(name) = &self.name {
@@ -137,10 +231,126 @@ impl ChatMLMessage {
     }
 }
 
+impl From<&crate::InternalMessage> for ChatMLMessage {
+    /// Convert an internal message to ChatML.
+    ///
+    /// Text and tool result blocks are joined with newlines; tool use blocks
+    /// become OpenAI-style `tool_calls`. Error tool results are marked as in
+    /// [`crate::InternalMessage::to_text`]. Tool messages are flattened with
+    /// an `[image i/n]` placeholder for each image, so structured tool
+    /// results are not silently dropped.
+    fn from(msg: &crate::InternalMessage) -> Self {
+        let mut tool_calls = Vec::new();
+        let content = match &msg.content {
+            _ if msg.role == crate::MessageRole::Tool => msg.to_text_with_placeholders(),
+            crate::MessageContent::Text(text) => text.clone(),
+            crate::MessageContent::Blocks(blocks) => {
+                let mut parts = Vec::new();
+                for block in blocks {
+                    match block {
+                        crate::ContentBlock::Text { text, .. } => parts.push(text.as_str()),
+                        crate::ContentBlock::ToolResult { content, .. } => {
+                            parts.push(content.as_str())
+                        }
+                        crate::ContentBlock::AudioOutput { transcript, .. } => {
+                            parts.extend(transcript.as_deref())
+                        }
+                        crate::ContentBlock::ToolUse {
+                            id, name, input, ..
+                        } => {
+                            tool_calls.push(crate::ToolCall {
+                                id: id.clone(),
+                                r#type: "function".to_string(),
+                                function: crate::FunctionCall {
+                                    name: name.clone(),
+                                    arguments: input.to_string(),
+                                },
+                            });
+                        }
+                        crate::ContentBlock::Image { .. }
+                        | crate::ContentBlock::Attachment { .. }
+                        | crate::ContentBlock::Raw { .. } => {}
+                    }
+                }
+                parts.join("\n")
+            }
+        };
+
+        Self {
+            role: msg.role.into(),
+            content,
+            name: msg.name.clone(),
+            tool_call_id: msg.tool_call_id.clone(),
+            tool_calls: if tool_calls.is_empty() {
+                None
+            } else {
+                Some(tool_calls)
+            },
+            is_error: msg.role == crate::MessageRole::Tool && msg.is_error_result(),
+        }
+    }
+}
+
+impl From<&ChatMLMessage> for crate::InternalMessage {
+    /// Convert a ChatML message to an internal message.
+    ///
+    /// Assistant `tool_calls` become tool use blocks after the text (if any),
+    /// with arguments parsed as JSON or kept as a raw string. Tool messages
+    /// flagged with `is_error` become error results, with the error marker
+    /// stripped from their content.
+    fn from(msg: &ChatMLMessage) -> Self {
+        if msg.role == MessageRole::Tool {
+            let tool_call_id = msg.tool_call_id.clone().unwrap_or_default();
+            let name = msg.name.clone().unwrap_or_default();
+            if !msg.is_error {
+                return crate::InternalMessage::tool_result(tool_call_id, name, msg.content.clone());
+            }
+            let content = match msg.content.strip_prefix(crate::ERROR_MARKER) {
+                Some(rest) => rest.strip_prefix(' ').unwrap_or(rest),
+                None => msg.content.as_str(),
+            };
+            return crate::InternalMessage::tool_error(tool_call_id, name, content);
+        }
+
+        let content = match &msg.tool_calls {
+            Some(tool_calls) if !tool_calls.is_empty() => {
+                let mut blocks = Vec::with_capacity(tool_calls.len() + 1);
+                if !msg.content.is_empty() {
+                    blocks.push(crate::ContentBlock::text(msg.content.clone()));
+                }
+                for call in tool_calls {
+                    let arguments = &call.function.arguments;
+                    let input = serde_json::from_str(arguments)
+                        .unwrap_or_else(|_| serde_json::Value::String(arguments.clone()));
+                    blocks.push(crate::ContentBlock::tool_use(
+                        call.id.clone(),
+                        call.function.name.clone(),
+                        input,
+                    ));
+                }
+                crate::MessageContent::Blocks(blocks)
+            }
+            _ => crate::MessageContent::Text(msg.content.clone()),
+        };
+
+        Self {
+            role: msg.role.clone().into(),
+            content,
+            metadata: HashMap::new(),
+            tool_call_id: msg.tool_call_id.clone(),
+            name: msg.name.clone(),
+            created_at: None,
+            hints: None,
+        }
+    }
+}
+
 /// Formats messages in ChatML format for simpaticoder.
 #[derive(Debug, Clone)]
 pub struct ChatMLFormatter {
     messages: Vec<ChatMLMessage>,
+    /// Token count of the current messages, reset by every mutation
+    token_count: OnceLock<usize>,
 }
 
 impl ChatMLFormatter {
@@ -148,16 +358,36 @@ impl ChatMLFormatter {
     pub fn new() -> Self {
         Self {
             messages: Vec::new(),
+            token_count: OnceLock::new(),
+        }
+    }
+
+    /// Create a formatter holding the ChatML form of internal messages.
+    ///
+    /// # Arguments
+    /// * `messages` - Messages to convert, in order.
+    pub fn from_internal(messages: &[crate::InternalMessage]) -> Self {
+        Self {
+            messages: messages.iter().map(ChatMLMessage::from).collect(),
+            token_count: OnceLock::new(),
         }
     }
 
+    /// Convert all messages back to internal messages.
+    ///
+    /// # Returns
+    /// One internal message per ChatML message, in order.
+    pub fn to_internal_messages(&self) -> Vec<crate::InternalMessage> {
+        self.messages.iter().map(crate::InternalMessage::from).collect()
+    }
+
     /// Add system message.
     ///
     /// # Arguments
     /// * `content` - System message content.
     /// * `name` - Optional name for the system.
     pub fn add_system_message(&mut self, content: String, name: Option<String>) -> &mut Self {
-        self.messages
+        self.messages_mut()
             .push(ChatMLMessage::new(MessageRole::System, content, name));
         self
     }
@@ -168,7 +398,7 @@ impl ChatMLFormatter {
     /// * `content` - User message content.
     /// * `name` - Optional name for the user.
     pub fn add_user_message(&mut self, content: String, name: Option<String>) -> &mut Self {
-        self.messages
+        self.messages_mut()
             .push(ChatMLMessage::new(MessageRole::User, content, name));
         self
     }
@@ -179,7 +409,7 @@ impl ChatMLFormatter {
     /// * `content` - Assistant message content.
     /// * `name` - Optional name for the assistant.
     pub fn add_assistant_message(&mut self, content: String, name: Option<String>) -> &mut Self {
-        self.messages
+        self.messages_mut()
             .push(ChatMLMessage::new(MessageRole::Assistant, content, name));
         self
     }
@@ -194,7 +424,7 @@ impl ChatMLFormatter {
         content: String,
         tool_calls: Vec<crate::ToolCall>,
     ) -> &mut Self {
-        self.messages
+        self.messages_mut()
             .push(ChatMLMessage::new_assistant_with_tool_calls(
                 content, tool_calls,
             ));
@@ -213,7 +443,7 @@ impl ChatMLFormatter {
         tool_call_id: String,
         name: String,
     ) -> &mut Self {
-        self.messages
+        self.messages_mut()
             .push(ChatMLMessage::new_tool(content, tool_call_id, name));
         self
     }
@@ -227,7 +457,7 @@ impl ChatMLFormatter {
     pub fn add_tool_results_message(&mut self, content: String, name: Option<String>) -> &mut Self {
         // For now, we'll use a generic tool_call_id for combined results
         // This should be refactored to use individual tool messages in the future
-        self.messages.push(ChatMLMessage::new_tool(
+        self.messages_mut().push(ChatMLMessage::new_tool(
             content,
             "combined_tool_results".to_string(),
             name.unwrap_or_else(|| "tool_results".to_string()),
@@ -248,16 +478,74 @@ impl ChatMLFormatter {
     /// # Returns
     /// Full conversation in ChatML format.
     pub fn to_chatml_string(&self) -> String {
-        self.messages
-            .iter()
-            .map(|msg| msg.to_chatml_string())
-            .collect::<Vec<_>>()
-            .join("\n")
+        self.chatml_chunks().collect()
+    }
+
+    /// Format messages as ChatML one message block at a time.
+    ///
+    /// Every chunk after the first starts with the newline separating it from
+    /// the previous block, so the chunks concatenate to
+    /// [`ChatMLFormatter::to_chatml_string`].
+    ///
+    /// # Returns
+    /// An iterator yielding one formatted message block per message.
+    pub fn chatml_chunks(&self) -> impl Iterator<Item = String> + '_ {
+        self.messages.iter().enumerate().map(|(i, msg)| {
+            if i == 0 {
+                msg.to_chatml_string()
+            } else {
+                format!("\n{}", msg.to_chatml_string())
+            }
+        })
+    }
+
+    /// Insert a message at a position.
+    ///
+    /// # Arguments
+    /// * `index` - Position to insert at (may equal the message count to append).
+    /// * `msg` - Message to insert.
+    ///
+    /// # Returns
+    /// True if inserted, false if `index` is out of bounds.
+    pub fn insert_at(&mut self, index: usize, msg: ChatMLMessage) -> bool {
+        if index > self.messages.len() {
+            return false;
+        }
+        self.messages_mut().insert(index, msg);
+        true
+    }
+
+    /// Remove the message at a position.
+    ///
+    /// # Arguments
+    /// * `index` - Position of the message to remove.
+    ///
+    /// # Returns
+    /// The removed message, or None if `index` is out of bounds.
+    pub fn remove_at(&mut self, index: usize) -> Option<ChatMLMessage> {
+        if index >= self.messages.len() {
+            return None;
+        }
+        Some(self.messages_mut().remove(index))
+    }
+
+    /// Replace the message at a position.
+    ///
+    /// # Arguments
+    /// * `index` - Position of the message to replace.
+    /// * `msg` - Replacement message.
+    ///
+    /// # Returns
+    /// The previous message, or None if `index` is out of bounds.
+    pub fn replace_at(&mut self, index: usize, msg: ChatMLMessage) -> Option<ChatMLMessage> {
+        self.messages_mut()
+            .get_mut(index)
+            .map(|slot| std::mem::replace(slot, msg))
     }
 
     /// Clear all messages.
     pub fn clear(&mut self) -> &mut Self {
-        self.messages.clear();
+        self.messages_mut().clear();
         self
     }
 
@@ -278,7 +566,7 @@ impl ChatMLFormatter {
                 .cloned()
                 .collect::<Vec<_>>();
 
-            self.messages = if let Some(system) = system_message {
+            *self.messages_mut() = if let Some(system) = system_message {
                 std::iter::once(system).chain(recent_messages).collect()
             } else {
                 recent_messages
@@ -302,6 +590,34 @@ impl ChatMLFormatter {
         &self.messages
     }
 
+    /// Export the formatter state as JSON.
+    ///
+    /// # Returns
+    /// An object `{"messages": [...]}` that [`ChatMLFormatter::from_json`] restores.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({ "messages": self.messages })
+    }
+
+    /// Restore a formatter from state exported by [`ChatMLFormatter::to_json`].
+    ///
+    /// # Arguments
+    /// * `value` - Object with a `messages` array.
+    ///
+    /// # Returns
+    /// The restored formatter, or an error if the state is malformed.
+    pub fn from_json(value: serde_json::Value) -> Result<Self, crate::UmfError> {
+        let messages = match value {
+            serde_json::Value::Object(mut obj) => obj.remove("messages"),
+            _ => None,
+        }
+        .ok_or_else(|| crate::UmfError::InvalidInput("missing field `messages`".to_string()))?;
+
+        Ok(Self {
+            messages: serde_json::from_value(messages)?,
+            token_count: OnceLock::new(),
+        })
+    }
+
     /// Format a thought and command in the expected format.
     ///
     /// # Arguments
@@ -357,17 +673,31 @@ impl ChatMLFormatter {
     /// # Returns
     /// True if all messages are valid, false otherwise.
     pub fn validate_messages(&self) -> bool {
+        self.validate_messages_with_names(false)
+    }
+
+    /// Validate that all messages have required fields, optionally also
+    /// requiring names the OpenAI API accepts.
+    ///
+    /// # Arguments
+    /// * `enforce_valid_names` - Also fail on names rejected by
+    ///   [`ChatMLMessage::valid_name`].
+    ///
+    /// # Returns
+    /// True if all messages are valid, false otherwise.
+    pub fn validate_messages_with_names(&self, enforce_valid_names: bool) -> bool {
         for message in &self.messages {
+            if enforce_valid_names && !message.valid_name() {
+                return false;
+            }
             // Allow empty content for assistant messages with tool calls (OpenAI API requirement)
             if message.content.is_empty() && message.tool_calls.is_none() {
                 return false;
             }
             // System messages should have names for simpaticoder
             // Assistant messages should have names UNLESS they have tool_calls (OpenAI API pattern)
-            if message.role == MessageRole::System {
-                if message.name.is_none() {
-                    return false;
-                }
+            if message.role == MessageRole::System && message.name.is_none() {
+                return false;
             }
             if message.role == MessageRole::Assistant {
                 // Assistant messages with tool_calls don't need names (per OpenAI API spec)
@@ -376,28 +706,41 @@ impl ChatMLFormatter {
                 }
             }
             // Tool messages must have tool_call_id and name
-            if matches!(message.role, MessageRole::Tool) {
-                if message.tool_call_id.is_none() || message.name.is_none() {
-                    return false;
-                }
+            if matches!(message.role, MessageRole::Tool)
+                && (message.tool_call_id.is_none() || message.name.is_none())
+            {
+                return false;
             }
         }
         true
     }
+
     /// Count the number of tokens in the current conversation.
     ///
+    /// The count is cached until the messages are next modified, so repeated
+    /// calls on an unchanged formatter do not re-tokenize.
+    ///
     /// # Returns
     /// Number of tokens, or 0 if tokenization fails.
     pub fn count_tokens(&self) -> usize {
+        if let Some(count) = self.token_count.get() {
+            return *count;
+        }
         match cl100k_base() {
             Ok(bpe) => {
                 let chatml_string = self.to_chatml_string();
                 let tokens = bpe.encode_with_special_tokens(&chatml_string);
-                tokens.len()
+                *self.token_count.get_or_init(|| tokens.len())
             }
             Err(_) => 0,
         }
     }
+
+    /// Get the messages for mutation, invalidating the cached token count.
+    fn messages_mut(&mut self) -> &mut Vec<ChatMLMessage> {
+        self.token_count.take();
+        &mut self.messages
+    }
 }
 
 impl Default for ChatMLFormatter {
@@ -143,3 +143,254 @@ fn test_broken_resume_behavior_validation() {
         "Old behavior should fail validation due to missing names"
     );
 }
+
+#[test]
+fn test_insert_and_remove_at() {
+    let mut formatter = ChatMLFormatter::new();
+    formatter.add_system_message("System prompt".to_string(), Some("system".to_string()));
+    formatter.add_user_message("First".to_string(), None);
+    formatter.add_user_message("Second".to_string(), None);
+
+    let reminder = ChatMLMessage::new(
+        MessageRole::System,
+        "Remember to be brief".to_string(),
+        Some("reminder".to_string()),
+    );
+    assert!(formatter.insert_at(1, reminder));
+    assert_eq!(formatter.get_message_count(), 4);
+
+    let contents: Vec<_> = formatter
+        .get_messages()
+        .iter()
+        .map(|m| m.content.as_str())
+        .collect();
+    assert_eq!(
+        contents,
+        vec!["System prompt", "Remember to be brief", "First", "Second"]
+    );
+
+    let removed = formatter.remove_at(1).unwrap();
+    assert_eq!(removed.content, "Remember to be brief");
+    assert_eq!(formatter.get_message_count(), 3);
+    assert_eq!(formatter.get_messages()[1].content, "First");
+
+    // Out of bounds
+    let extra = ChatMLMessage::new(MessageRole::User, "x".to_string(), None);
+    assert!(!formatter.insert_at(4, extra));
+    assert!(formatter.remove_at(3).is_none());
+    assert_eq!(formatter.get_message_count(), 3);
+}
+
+#[test]
+fn test_replace_at() {
+    let mut formatter = ChatMLFormatter::new();
+    formatter.add_user_message("Original".to_string(), None);
+
+    let replacement = ChatMLMessage::new(MessageRole::User, "Edited".to_string(), None);
+    let previous = formatter.replace_at(0, replacement.clone()).unwrap();
+
+    assert_eq!(previous.content, "Original");
+    assert_eq!(formatter.get_messages()[0].content, "Edited");
+    assert!(formatter.replace_at(1, replacement).is_none());
+    assert_eq!(formatter.get_message_count(), 1);
+}
+
+#[test]
+fn test_to_dict_null_content_with_tool_calls() {
+    let tool_call = crate::ToolCall {
+        id: "call_1".to_string(),
+        r#type: "function".to_string(),
+        function: crate::FunctionCall {
+            name: "search".to_string(),
+            arguments: "{}".to_string(),
+        },
+    };
+    let msg = ChatMLMessage::new_assistant_with_tool_calls(String::new(), vec![tool_call]);
+    let dict = msg.to_dict();
+    assert_eq!(dict["content"], serde_json::Value::Null);
+    assert!(dict["tool_calls"].is_array());
+
+    let msg = ChatMLMessage::new(MessageRole::User, "Hello".to_string(), None);
+    assert_eq!(msg.to_dict()["content"], serde_json::json!("Hello"));
+}
+
+#[test]
+fn test_validate_tool_calls() {
+    let tool_call = |id: &str, arguments: &str| crate::ToolCall {
+        id: id.to_string(),
+        r#type: "function".to_string(),
+        function: crate::FunctionCall {
+            name: "search".to_string(),
+            arguments: arguments.to_string(),
+        },
+    };
+
+    let msg = ChatMLMessage::new_assistant_with_tool_calls(
+        String::new(),
+        vec![tool_call("call_1", "{}"), tool_call("call_2", "{\"q\": 1}")],
+    );
+    assert!(msg.validate_tool_calls().is_ok());
+
+    let msg = ChatMLMessage::new_assistant_with_tool_calls(
+        String::new(),
+        vec![tool_call("call_1", "{}"), tool_call("call_1", "{not json")],
+    );
+    let errors = msg.validate_tool_calls().unwrap_err();
+    assert_eq!(errors.len(), 2);
+    assert!(errors[0].contains("duplicate tool call id `call_1`"));
+    assert!(errors[1].contains("invalid arguments"));
+}
+
+#[test]
+fn test_formatter_json_round_trip() {
+    let mut formatter = ChatMLFormatter::new();
+    formatter
+        .add_system_message("You are a coder".to_string(), Some("simpaticoder".to_string()))
+        .add_user_message("List files".to_string(), None)
+        .add_assistant_message_with_tool_calls(
+            String::new(),
+            vec![crate::ToolCall {
+                id: "call_1".to_string(),
+                r#type: "function".to_string(),
+                function: crate::FunctionCall {
+                    name: "bash".to_string(),
+                    arguments: r#"{"command": "ls"}"#.to_string(),
+                },
+            }],
+        )
+        .add_tool_message("main.rs".to_string(), "call_1".to_string(), "bash".to_string());
+    assert!(formatter.validate_messages());
+
+    let state = formatter.to_json();
+    let restored = ChatMLFormatter::from_json(state.clone()).unwrap();
+
+    assert!(restored.validate_messages());
+    assert_eq!(restored.get_message_count(), formatter.get_message_count());
+    assert_eq!(restored.to_chatml_string(), formatter.to_chatml_string());
+    assert_eq!(restored.to_json(), state);
+    let tool = restored.get_last_message().unwrap();
+    assert_eq!(tool.tool_call_id.as_deref(), Some("call_1"));
+    assert_eq!(tool.name.as_deref(), Some("bash"));
+
+    assert!(ChatMLFormatter::from_json(serde_json::json!({})).is_err());
+}
+
+#[test]
+fn test_from_internal_tool_result_blocks() {
+    let msg = crate::InternalMessage {
+        role: crate::MessageRole::Tool,
+        content: crate::MessageContent::Blocks(vec![
+            crate::ContentBlock::text("Screenshot captured"),
+            crate::ContentBlock::image(crate::ImageSource::Base64 {
+                media_type: "image/png".to_string(),
+                data: "iVBORw0".to_string(),
+            }),
+        ]),
+        metadata: Default::default(),
+        tool_call_id: Some("call_1".to_string()),
+        name: Some("screenshot".to_string()),
+        created_at: None,
+        hints: None,
+    };
+
+    let chatml = ChatMLMessage::from(&msg);
+    assert_eq!(chatml.role, MessageRole::Tool);
+    assert_eq!(chatml.content, "Screenshot captured\n[image 1/1]");
+    assert_eq!(chatml.tool_call_id.as_deref(), Some("call_1"));
+    assert!(chatml.to_chatml_string().contains("Screenshot captured"));
+}
+
+#[test]
+fn test_tool_error_flag_round_trip() {
+    let error = crate::InternalMessage::tool_error("call_1", "search", "Timed out");
+    let chatml = ChatMLMessage::from(&error);
+    assert!(chatml.is_error);
+    assert_eq!(chatml.content, "[error] Timed out");
+    assert!(!chatml.to_dict().contains_key("is_error"));
+
+    let back = crate::InternalMessage::from(&chatml);
+    assert!(back.is_error_result());
+    assert_eq!(back.text(), Some("Timed out"));
+
+    // A successful result whose output happens to start with the marker stays a success
+    let ok = crate::InternalMessage::tool_result("call_2", "grep", "[error] not found in log");
+    let chatml = ChatMLMessage::from(&ok);
+    assert!(!chatml.is_error);
+    let json = serde_json::to_value(&chatml).unwrap();
+    assert!(json.get("is_error").is_none());
+
+    let back = crate::InternalMessage::from(&chatml);
+    assert!(!back.is_error_result());
+    assert_eq!(back.to_text(), "[error] not found in log");
+}
+
+#[test]
+fn test_chatml_chunks() {
+    let mut formatter = ChatMLFormatter::new();
+    assert_eq!(formatter.chatml_chunks().count(), 0);
+
+    formatter
+        .add_system_message("You are a coder".to_string(), Some("simpaticoder".to_string()))
+        .add_user_message("Hello".to_string(), None)
+        .add_assistant_message("Hi!".to_string(), Some("simpaticoder".to_string()));
+
+    let chunks: Vec<String> = formatter.chatml_chunks().collect();
+    assert_eq!(chunks.len(), 3);
+    assert!(chunks[1].starts_with("\n<|im_start|>user"));
+    assert_eq!(chunks.concat(), formatter.to_chatml_string());
+    assert_eq!(
+        formatter.to_chatml_string(),
+        formatter
+            .get_messages()
+            .iter()
+            .map(|m| m.to_chatml_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    );
+}
+
+#[test]
+fn test_count_tokens_cache_invalidation() {
+    let mut formatter = ChatMLFormatter::new();
+    formatter.add_system_message("You are a coder".to_string(), None);
+
+    let initial = formatter.count_tokens();
+    assert!(initial > 0);
+    assert_eq!(formatter.count_tokens(), initial);
+
+    formatter.add_user_message("Write a function that reverses a string".to_string(), None);
+    let with_user = formatter.count_tokens();
+    assert!(with_user > initial);
+    assert_eq!(formatter.count_tokens(), with_user);
+
+    formatter.replace_at(1, ChatMLMessage::new(MessageRole::User, "Hi".to_string(), None));
+    assert!(formatter.count_tokens() < with_user);
+
+    formatter.remove_at(1);
+    assert_eq!(formatter.count_tokens(), initial);
+
+    formatter.clear();
+    assert_eq!(formatter.count_tokens(), 0);
+}
+
+#[test]
+fn test_sanitize_name() {
+    let name = Some("my agent!".to_string());
+    let mut msg = ChatMLMessage::new(MessageRole::User, "Hi".to_string(), name);
+    assert!(!msg.valid_name());
+
+    let mut formatter = ChatMLFormatter::new();
+    formatter.add_system_message("Be brief".to_string(), Some("system".to_string()));
+    formatter.insert_at(1, msg.clone());
+    assert!(formatter.validate_messages());
+    assert!(!formatter.validate_messages_with_names(true));
+
+    msg.sanitize_name();
+    assert_eq!(msg.name.as_deref(), Some("my_agent_"));
+    assert!(msg.valid_name());
+
+    let mut long = ChatMLMessage::new(MessageRole::User, "Hi".to_string(), Some("a".repeat(80)));
+    long.sanitize_name();
+    assert_eq!(long.name.as_deref().map(str::len), Some(64));
+    assert!(long.valid_name());
+}
@@ -0,0 +1,512 @@
+//! Conversation container and context helpers.
+//!
+//! [`Conversation`] is an ordered list of [`InternalMessage`]s with helpers
+//! that operate on the conversation as a whole, such as fitting it into a
+//! token budget.
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use umf::{Conversation, InternalMessage};
+//!
+//! let mut conversation = Conversation::new();
+//! conversation
+//!     .push(InternalMessage::system("You are a helpful assistant"))
+//!     .push(InternalMessage::user("Hello!"));
+//! assert_eq!(conversation.len(), 2);
+//! ```
+
+#[cfg(feature = "events")]
+use crate::events::{EventEnvelope, MessageEvent};
+use crate::providers::{from_openai_messages, to_openai_message, ConversionError};
+use crate::{ContentBlock, InternalMessage, MessageContent, MessageRole, TokenCounter, UmfError};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "events")]
+use std::path::Path;
+
+/// Name used for synthesized tool calls whose tool name is unknown
+const UNKNOWN_TOOL: &str = "unknown_tool";
+
+/// How [`Conversation::repair_tool_pairing`] fixes orphaned tool results
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepairMode {
+    /// Remove tool results that answer no earlier tool call
+    DropOrphanResults,
+    /// Insert a placeholder assistant tool call before each orphaned result
+    SynthesizeToolCalls,
+}
+
+/// Error applying a JSON Patch with [`Conversation::apply_patch`]
+#[cfg(feature = "json-patch")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchError {
+    /// The patch is not a valid RFC 6902 operation array
+    InvalidPatch(String),
+    /// An operation could not be applied (e.g. a bad path or a failed `test`)
+    Apply(String),
+    /// The patched document is no longer a valid message list
+    InvalidResult(String),
+}
+
+#[cfg(feature = "json-patch")]
+impl std::fmt::Display for PatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidPatch(msg) => write!(f, "invalid patch: {}", msg),
+            Self::Apply(msg) => write!(f, "patch failed: {}", msg),
+            Self::InvalidResult(msg) => write!(f, "patched conversation is invalid: {}", msg),
+        }
+    }
+}
+
+#[cfg(feature = "json-patch")]
+impl std::error::Error for PatchError {}
+
+/// An ordered conversation of messages
+///
+/// Serializes as a plain JSON array of messages.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Conversation {
+    /// Messages in conversation order
+    pub messages: Vec<InternalMessage>,
+}
+
+impl Conversation {
+    /// Create an empty conversation
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a message
+    pub fn push(&mut self, message: InternalMessage) -> &mut Self {
+        self.messages.push(message);
+        self
+    }
+
+    /// Get all messages
+    pub fn messages(&self) -> &[InternalMessage] {
+        &self.messages
+    }
+
+    /// Get number of messages
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    /// Check if the conversation has no messages
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// Total [`InternalMessage::char_count`] over all messages
+    pub fn char_count(&self) -> usize {
+        self.messages.iter().map(InternalMessage::char_count).sum()
+    }
+
+    /// Total [`InternalMessage::word_count`] over all messages
+    pub fn word_count(&self) -> usize {
+        self.messages.iter().map(InternalMessage::word_count).sum()
+    }
+
+    /// Export as the `messages` array of an OpenAI chat request body
+    pub fn to_openai_request_messages(&self) -> serde_json::Value {
+        serde_json::Value::Array(self.messages.iter().map(to_openai_message).collect())
+    }
+
+    /// Export as one line of an OpenAI fine-tuning JSONL file
+    ///
+    /// The line is a `{"messages": [...]}` object in OpenAI chat format
+    /// (see [`to_openai_request_messages`](Self::to_openai_request_messages)),
+    /// without a trailing newline.
+    pub fn to_training_line(&self) -> Result<String, UmfError> {
+        let line = serde_json::json!({ "messages": self.to_openai_request_messages() });
+        Ok(serde_json::to_string(&line)?)
+    }
+
+    /// Import from an OpenAI chat request body (`{"messages": [...]}`)
+    pub fn from_openai_request(value: &serde_json::Value) -> Result<Self, ConversionError> {
+        let messages = value
+            .get("messages")
+            .ok_or_else(|| ConversionError::missing("messages", "openai request"))?;
+        Ok(Self::from(from_openai_messages(messages)?))
+    }
+
+    /// Apply an RFC 6902 JSON Patch to the serialized conversation
+    ///
+    /// The patch is applied to the conversation's JSON array form (paths
+    /// such as `/-` or `/2/content`), and the result is parsed back into
+    /// messages. The conversation is left unchanged if the patch is
+    /// malformed, any operation fails, or the result is not a valid message
+    /// list.
+    #[cfg(feature = "json-patch")]
+    pub fn apply_patch(&mut self, patch: &serde_json::Value) -> Result<(), PatchError> {
+        let patch: json_patch::Patch = serde_json::from_value(patch.clone())
+            .map_err(|e| PatchError::InvalidPatch(e.to_string()))?;
+        let mut document = serde_json::to_value(&self.messages)
+            .map_err(|e| PatchError::InvalidResult(e.to_string()))?;
+        json_patch::patch(&mut document, &patch).map_err(|e| PatchError::Apply(e.to_string()))?;
+        self.messages = serde_json::from_value(document)
+            .map_err(|e| PatchError::InvalidResult(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Load a conversation from a JSONL event log
+    ///
+    /// Requires the `events` feature. Convenience wrapper around
+    /// [`from_jsonl_path_with_warnings`](Self::from_jsonl_path_with_warnings)
+    /// that discards the warnings.
+    #[cfg(feature = "events")]
+    pub fn from_jsonl_path(path: &Path) -> Result<Self, UmfError> {
+        Self::from_jsonl_path_with_warnings(path).map(|(conversation, _)| conversation)
+    }
+
+    /// Load a conversation from a JSONL event log, collecting warnings
+    ///
+    /// Each line is an [`EventEnvelope`] (full or compact). Message events
+    /// are ordered by sequence number and their messages form the
+    /// conversation; other event types are skipped. A partial message event
+    /// (see [`MessageEvent::partial`]) is dropped in favour of a later event
+    /// with the same sequence number. Malformed lines do not abort loading:
+    /// each yields a `line N: ...` warning instead. Only failing to read the
+    /// file is an error. Requires the `events` feature.
+    #[cfg(feature = "events")]
+    pub fn from_jsonl_path_with_warnings(path: &Path) -> Result<(Self, Vec<String>), UmfError> {
+        let contents = std::fs::read_to_string(path)?;
+        let mut warnings = Vec::new();
+        let mut events = Vec::new();
+
+        for (i, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let envelope = match EventEnvelope::from_json_line_compact(line) {
+                Ok(envelope) => envelope,
+                Err(err) => {
+                    warnings.push(format!("line {}: {}", i + 1, err));
+                    continue;
+                }
+            };
+            if envelope.event_type != crate::EventType::Message {
+                continue;
+            }
+            match envelope.as_message_event() {
+                Some(event) => events.push(event),
+                None => warnings.push(format!("line {}: invalid message event payload", i + 1)),
+            }
+        }
+
+        events.sort_by_key(|event| event.sequence);
+        let mut latest: Vec<MessageEvent> = Vec::with_capacity(events.len());
+        for event in events {
+            match latest.last_mut() {
+                // A partial is superseded by any later event of its sequence
+                Some(last) if last.sequence == event.sequence && !last.complete => *last = event,
+                Some(last) if last.sequence == event.sequence && !event.complete => {}
+                _ => latest.push(event),
+            }
+        }
+        let conversation = latest.into_iter().map(|event| event.message).collect();
+        Ok((conversation, warnings))
+    }
+
+    /// Split into two conversations at `index`, cloning the messages
+    ///
+    /// The first holds messages `[0, index)`, the second the rest. An index
+    /// past the end yields an empty second half.
+    pub fn split_at(&self, index: usize) -> (Conversation, Conversation) {
+        let (head, tail) = self.messages.split_at(index.min(self.messages.len()));
+        (Self::from(head.to_vec()), Self::from(tail.to_vec()))
+    }
+
+    /// Split right after the leading run of system messages
+    ///
+    /// Separates the system prompt and any few-shot examples encoded as
+    /// system messages (e.g. named `example_user`/`example_assistant`) from
+    /// the live dialogue.
+    pub fn split_after_last_system(&self) -> (Conversation, Conversation) {
+        let leading = self
+            .messages
+            .iter()
+            .take_while(|m| m.role == MessageRole::System)
+            .count();
+        self.split_at(leading)
+    }
+
+    /// Separate the leading system prompt from the remaining messages
+    ///
+    /// The text of the leading run of system messages is joined with
+    /// newlines, for providers that take the system prompt as a top-level
+    /// field. System messages after the first non-system message stay in
+    /// the returned list. Yields `None` when the conversation does not
+    /// start with a system message.
+    pub fn hoist_system(&self) -> (Option<String>, Vec<&InternalMessage>) {
+        let leading = self
+            .messages
+            .iter()
+            .take_while(|m| m.role == MessageRole::System)
+            .count();
+        let system = (leading > 0).then(|| {
+            self.messages[..leading]
+                .iter()
+                .map(InternalMessage::to_text)
+                .collect::<Vec<_>>()
+                .join("\n")
+        });
+        (system, self.messages[leading..].iter().collect())
+    }
+
+    /// Iterate over the conversation as turns
+    ///
+    /// The leading system messages form the first group. After that, each
+    /// turn starts at a user message and runs through the assistant and tool
+    /// messages up to the next user message. Messages between the system
+    /// prompt and the first user message form a group of their own.
+    pub fn iter_turns(&self) -> impl Iterator<Item = &[InternalMessage]> {
+        let leading = self
+            .messages
+            .iter()
+            .take_while(|m| m.role == MessageRole::System)
+            .count();
+        let starts: Vec<usize> = (0..self.messages.len())
+            .filter(|&i| i == 0 || i == leading || self.messages[i].role == MessageRole::User)
+            .collect();
+
+        (0..starts.len()).map(move |k| {
+            let end = starts.get(k + 1).copied().unwrap_or(self.messages.len());
+            &self.messages[starts[k]..end]
+        })
+    }
+
+    /// Remove leading system messages that repeat the first system prompt
+    ///
+    /// Only the run of consecutive system messages at the start of the
+    /// conversation is examined; a message is removed when its text exactly
+    /// equals the first one. Later system messages are left alone.
+    pub fn dedup_leading_system(&mut self) -> &mut Self {
+        let leading = self
+            .messages
+            .iter()
+            .take_while(|m| m.role == MessageRole::System)
+            .count();
+        if leading < 2 {
+            return self;
+        }
+
+        let first = self.messages[0].to_text();
+        let mut index = 0;
+        self.messages.retain(|m| {
+            index += 1;
+            !(index > 1 && index <= leading && m.to_text() == first)
+        });
+        self
+    }
+
+    /// Keep only messages whose role is in `roles`
+    ///
+    /// Tool pairing is not maintained: dropping [`MessageRole::Tool`] while
+    /// keeping assistant messages leaves their tool use blocks without
+    /// results, and the reverse orphans results. Use
+    /// [`repair_tool_pairing`](Self::repair_tool_pairing) if needed.
+    pub fn retain_roles(&mut self, roles: &[MessageRole]) -> &mut Self {
+        self.messages.retain(|m| roles.contains(&m.role));
+        self
+    }
+
+    /// Clone the messages whose role is in `roles` into a new conversation
+    ///
+    /// Non-mutating form of [`retain_roles`](Self::retain_roles), with the
+    /// same caveat about tool pairing.
+    pub fn filtered_by_roles(&self, roles: &[MessageRole]) -> Conversation {
+        self.messages
+            .iter()
+            .filter(|m| roles.contains(&m.role))
+            .cloned()
+            .collect()
+    }
+
+    /// Repair tool results that answer no earlier tool call
+    ///
+    /// An orphan is a tool message whose `tool_call_id` matches no earlier
+    /// tool use block (or that has none), or a tool result block with such
+    /// an ID. With [`RepairMode::DropOrphanResults`] orphaned tool messages
+    /// and blocks are removed, along with messages left empty. With
+    /// [`RepairMode::SynthesizeToolCalls`] an assistant message with a
+    /// placeholder tool use (empty input, metadata `synthesized: "true"`) is
+    /// inserted before each message holding orphans; tool messages without
+    /// a `tool_call_id` cannot be paired and are dropped. Returns the number
+    /// of orphaned results found.
+    pub fn repair_tool_pairing(&mut self, mode: RepairMode) -> usize {
+        let mut seen_calls = std::collections::HashSet::new();
+        let mut repaired = Vec::with_capacity(self.messages.len());
+        let mut orphans = 0;
+
+        for mut msg in std::mem::take(&mut self.messages) {
+            let mut orphan_ids = Vec::new();
+            let mut unpaired = false;
+            if msg.role == MessageRole::Tool {
+                match &msg.tool_call_id {
+                    Some(id) if !seen_calls.contains(id) => orphan_ids.push(id.clone()),
+                    Some(_) => {}
+                    None => unpaired = true,
+                }
+            }
+            for block in msg.blocks().unwrap_or_default() {
+                if let Some((id, _, _)) = block.as_tool_use() {
+                    seen_calls.insert(id.to_string());
+                }
+                if let Some((id, _)) = block.as_tool_result() {
+                    if !seen_calls.contains(id) {
+                        orphan_ids.push(id.to_string());
+                    }
+                }
+            }
+            orphans += orphan_ids.len() + usize::from(unpaired);
+
+            if unpaired {
+                continue;
+            }
+            if orphan_ids.is_empty() {
+                repaired.push(msg);
+                continue;
+            }
+
+            match mode {
+                RepairMode::DropOrphanResults => {
+                    if msg.role == MessageRole::Tool && msg.blocks().is_none() {
+                        continue;
+                    }
+                    if let MessageContent::Blocks(blocks) = &mut msg.content {
+                        blocks.retain(|b| {
+                            !b.as_tool_result()
+                                .is_some_and(|(id, _)| orphan_ids.iter().any(|o| o == id))
+                        });
+                        if blocks.is_empty() {
+                            continue;
+                        }
+                    }
+                    repaired.push(msg);
+                }
+                RepairMode::SynthesizeToolCalls => {
+                    let name = match msg.role {
+                        MessageRole::Tool => msg.name.as_deref().unwrap_or(UNKNOWN_TOOL),
+                        _ => UNKNOWN_TOOL,
+                    };
+                    let calls = orphan_ids
+                        .iter()
+                        .map(|id| ContentBlock::tool_use(id, name, serde_json::json!({})))
+                        .collect();
+                    let mut call = InternalMessage::assistant_blocks(calls);
+                    call.metadata
+                        .insert("synthesized".to_string(), "true".to_string());
+                    seen_calls.extend(orphan_ids);
+                    repaired.push(call);
+                    repaired.push(msg);
+                }
+            }
+        }
+
+        self.messages = repaired;
+        orphans
+    }
+
+    /// Select the leading system prompt plus the most recent messages that fit
+    ///
+    /// Leading system messages are always included and count against
+    /// `max_tokens`. The remaining budget is filled from the end of the
+    /// conversation, keeping an assistant tool call together with the tool
+    /// results that answer it so pairs are never split. Returns borrowed
+    /// messages in conversation order.
+    pub fn window(&self, max_tokens: usize, counter: &TokenCounter) -> Vec<&InternalMessage> {
+        self.window_indices(max_tokens, counter)
+            .into_iter()
+            .map(|i| &self.messages[i])
+            .collect()
+    }
+
+    /// Indices of the messages [`window`](Self::window) would keep, in order
+    pub fn window_indices(&self, max_tokens: usize, counter: &TokenCounter) -> Vec<usize> {
+        window_indices(&self.messages, max_tokens, counter)
+    }
+}
+
+/// Truncate a conversation to `max_tokens`, dropping whole messages from the
+/// oldest
+///
+/// Keeps the same messages as [`Conversation::window`]: leading system
+/// messages always stay, and an assistant tool call is only kept together
+/// with the tool results answering it, so no result is left orphaned. Unlike
+/// [`ChatMLFormatter::limit_history`](crate::ChatMLFormatter::limit_history),
+/// which trims by message count, the budget is measured in tokens.
+pub fn truncate_conversation(
+    messages: &[InternalMessage],
+    max_tokens: usize,
+    counter: &TokenCounter,
+) -> Vec<InternalMessage> {
+    window_indices(messages, max_tokens, counter)
+        .into_iter()
+        .map(|i| messages[i].clone())
+        .collect()
+}
+
+/// Indices of the messages a token window of `max_tokens` keeps, in order
+fn window_indices(
+    messages: &[InternalMessage],
+    max_tokens: usize,
+    counter: &TokenCounter,
+) -> Vec<usize> {
+    let system_len = messages
+        .iter()
+        .take_while(|m| m.role == MessageRole::System)
+        .count();
+    let (system, dialogue) = messages.split_at(system_len);
+
+    let mut remaining =
+        max_tokens.saturating_sub(system.iter().map(|m| counter.count_message(m)).sum());
+
+    // Walk groups from the end; a group is a message plus the tool
+    // results directly following it
+    let mut start = dialogue.len();
+    let mut group_tokens = 0;
+    for (i, msg) in dialogue.iter().enumerate().rev() {
+        group_tokens += counter.count_message(msg);
+        if is_tool_result(msg) && i > 0 {
+            continue;
+        }
+        if group_tokens > remaining {
+            break;
+        }
+        remaining -= group_tokens;
+        group_tokens = 0;
+        start = i;
+    }
+
+    (0..system_len)
+        .chain(system_len + start..messages.len())
+        .collect()
+}
+
+impl From<Vec<InternalMessage>> for Conversation {
+    fn from(messages: Vec<InternalMessage>) -> Self {
+        Self { messages }
+    }
+}
+
+impl FromIterator<InternalMessage> for Conversation {
+    fn from_iter<I: IntoIterator<Item = InternalMessage>>(iter: I) -> Self {
+        Self {
+            messages: iter.into_iter().collect(),
+        }
+    }
+}
+
+/// Check if a message carries tool results answering an earlier tool call
+fn is_tool_result(msg: &InternalMessage) -> bool {
+    msg.role == MessageRole::Tool
+        || msg
+            .blocks()
+            .is_some_and(|blocks| blocks.iter().any(|b| b.as_tool_result().is_some()))
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,420 @@
+//! Tests for conversations
+
+use super::*;
+use crate::ContentBlock;
+
+fn tool_conversation() -> Conversation {
+    vec![
+        InternalMessage::system("You are a helpful assistant"),
+        InternalMessage::user("What is the weather in Paris today?"),
+        InternalMessage::assistant("It is sunny and warm in Paris today."),
+        InternalMessage::user("And in London?"),
+        InternalMessage::assistant_with_tools(
+            "Let me check",
+            vec![ContentBlock::tool_use(
+                "call_1",
+                "weather",
+                serde_json::json!({"city": "London"}),
+            )],
+        ),
+        InternalMessage::tool_result("call_1", "weather", "Rainy, 12C"),
+        InternalMessage::assistant("It is rainy in London."),
+    ]
+    .into()
+}
+
+#[test]
+fn test_push_and_len() {
+    let mut conversation = Conversation::new();
+    assert!(conversation.is_empty());
+
+    conversation
+        .push(InternalMessage::user("Hi"))
+        .push(InternalMessage::assistant("Hello"));
+    assert_eq!(conversation.len(), 2);
+
+    // Serializes as a plain array
+    let json = serde_json::to_value(&conversation).unwrap();
+    assert_eq!(json.as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn test_window_keeps_system_and_tail() {
+    let counter = TokenCounter::new().unwrap();
+    let conversation = tool_conversation();
+    let messages = conversation.messages();
+
+    let cost = |range: std::ops::Range<usize>| -> usize {
+        messages[range].iter().map(|m| counter.count_message(m)).sum()
+    };
+
+    // Budget fits the system prompt and the last message only
+    let window = conversation.window(cost(0..1) + cost(6..7), &counter);
+    assert_eq!(window.len(), 2);
+    assert!(std::ptr::eq(window[0], &messages[0]));
+    assert!(std::ptr::eq(window[1], &messages[6]));
+
+    // One token short of the tool group: the tool result is never kept alone
+    let window = conversation.window(cost(0..1) + cost(4..7) - 1, &counter);
+    assert_eq!(window.len(), 2);
+
+    // Exactly enough for the whole tool call/result group
+    let window = conversation.window(cost(0..1) + cost(4..7), &counter);
+    assert_eq!(window.len(), 4);
+    assert_eq!(window[1].role, MessageRole::Assistant);
+    assert_eq!(window[2].role, MessageRole::Tool);
+
+    // Large budget keeps everything
+    assert_eq!(conversation.window(usize::MAX, &counter).len(), 7);
+}
+
+#[test]
+fn test_truncate_conversation_never_orphans_tool_results() {
+    let counter = TokenCounter::new().unwrap();
+    let city = serde_json::json!({"city": "Paris"});
+    let messages = vec![
+        InternalMessage::system("You are a helpful assistant"),
+        InternalMessage::user("What is the weather in Paris?"),
+        InternalMessage::assistant_with_tools(
+            "",
+            vec![
+                ContentBlock::tool_use("call_1", "get_weather", city.clone()),
+                ContentBlock::tool_use("call_2", "get_time", city),
+            ],
+        ),
+        InternalMessage::tool_result("call_1", "get_weather", "Sunny, 24C"),
+        InternalMessage::tool_result("call_2", "get_time", "14:00"),
+        InternalMessage::assistant("It is sunny and 24C in Paris at 14:00."),
+        InternalMessage::user("Thanks!"),
+    ];
+    let total: usize = messages.iter().map(|m| counter.count_message(m)).sum();
+
+    for max_tokens in 0..=total {
+        let truncated = truncate_conversation(&messages, max_tokens, &counter);
+        assert_eq!(truncated[0].role, MessageRole::System);
+        assert!(
+            crate::validate_conversation(&truncated).is_empty(),
+            "orphaned tool result with a budget of {} tokens",
+            max_tokens
+        );
+        let has_results = truncated.iter().any(|m| m.role == MessageRole::Tool);
+        let has_call = truncated.iter().any(|m| m.blocks().is_some());
+        assert_eq!(has_results, has_call);
+    }
+    assert_eq!(truncate_conversation(&messages, total, &counter).len(), messages.len());
+}
+
+#[test]
+fn test_to_training_line() {
+    let conversation = tool_conversation();
+    let line = conversation.to_training_line().unwrap();
+    assert!(!line.contains('\n'));
+
+    let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(value.as_object().map(|obj| obj.len()), Some(1));
+    assert_eq!(value["messages"][4]["tool_calls"][0]["type"], "function");
+
+    let restored = Conversation::from_openai_request(&value).unwrap();
+    assert_eq!(restored.len(), conversation.len());
+    for (restored, original) in restored.messages().iter().zip(conversation.messages()) {
+        assert!(restored.eq_ignoring_metadata(original));
+    }
+}
+
+#[test]
+fn test_openai_request_roundtrip() {
+    let body = serde_json::json!({
+        "model": "gpt-4o",
+        "messages": [
+            {"role": "system", "content": "You are a helpful assistant"},
+            {"role": "user", "content": "What's the weather in London?"},
+            {
+                "role": "assistant",
+                "content": null,
+                "tool_calls": [{
+                    "id": "call_1",
+                    "type": "function",
+                    "function": {"name": "weather", "arguments": "{\"city\":\"London\"}"}
+                }]
+            },
+            {"role": "tool", "tool_call_id": "call_1", "name": "weather", "content": "Rainy, 12C"},
+            {"role": "assistant", "content": "It is rainy in London."}
+        ]
+    });
+
+    let conversation = Conversation::from_openai_request(&body).unwrap();
+    assert_eq!(conversation.len(), 5);
+    assert!(crate::validate_conversation(conversation.messages()).is_empty());
+
+    assert_eq!(conversation.to_openai_request_messages(), body["messages"]);
+}
+
+#[test]
+fn test_from_openai_request_missing_messages() {
+    let err = Conversation::from_openai_request(&serde_json::json!({"model": "gpt-4o"}))
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        ConversionError::MissingField { ref field, .. } if field == "messages"
+    ));
+}
+
+#[test]
+fn test_dedup_leading_system() {
+    let mut conversation: Conversation = vec![
+        InternalMessage::system("You are a helpful assistant"),
+        InternalMessage::system("You are a helpful assistant"),
+        InternalMessage::user("Hi"),
+        InternalMessage::system("You are a helpful assistant"),
+    ]
+    .into();
+    conversation.dedup_leading_system();
+
+    assert_eq!(conversation.len(), 3);
+    assert_eq!(conversation.messages()[0].role, MessageRole::System);
+    assert_eq!(conversation.messages()[1].role, MessageRole::User);
+    // A later identical system message is not part of the leading run
+    assert_eq!(conversation.messages()[2].role, MessageRole::System);
+
+    let mut conversation: Conversation = vec![
+        InternalMessage::system("You are a helpful assistant"),
+        InternalMessage::system("Session: answer in French"),
+        InternalMessage::user("Hi"),
+    ]
+    .into();
+    conversation.dedup_leading_system();
+    assert_eq!(conversation.len(), 3);
+}
+
+#[test]
+fn test_split_at() {
+    let mut example = InternalMessage::system("What is 2 + 2?");
+    example.name = Some("example_user".to_string());
+    let conversation: Conversation = vec![
+        InternalMessage::system("You are a calculator"),
+        example,
+        InternalMessage::user("What is 3 + 3?"),
+        InternalMessage::assistant("6"),
+    ]
+    .into();
+
+    let (head, tail) = conversation.split_at(1);
+    assert_eq!(head.len(), 1);
+    assert_eq!(head.messages()[0].text(), Some("You are a calculator"));
+    assert_eq!(tail.len(), 3);
+    assert_eq!(tail.messages()[0].name.as_deref(), Some("example_user"));
+
+    let (examples, live) = conversation.split_after_last_system();
+    assert_eq!(examples.len(), 2);
+    assert_eq!(live.messages()[0].text(), Some("What is 3 + 3?"));
+    assert_eq!(live.messages()[1].text(), Some("6"));
+
+    let (all, none) = conversation.split_at(10);
+    assert_eq!((all.len(), none.len()), (4, 0));
+    assert_eq!(conversation.len(), 4);
+}
+
+#[test]
+fn test_hoist_system() {
+    let conversation: Conversation = vec![
+        InternalMessage::system("You are a calculator"),
+        InternalMessage::system("Answer with a number"),
+        InternalMessage::user("What is 3 + 3?"),
+        InternalMessage::system("The user prefers words"),
+        InternalMessage::assistant("six"),
+    ]
+    .into();
+
+    let (system, rest) = conversation.hoist_system();
+    assert_eq!(
+        system.as_deref(),
+        Some("You are a calculator\nAnswer with a number")
+    );
+    assert_eq!(rest.len(), 3);
+    assert_eq!(rest[1].role, MessageRole::System);
+    assert_eq!(rest[1].text(), Some("The user prefers words"));
+
+    let no_system = Conversation::from(vec![InternalMessage::user("Hi")]);
+    let (system, rest) = no_system.hoist_system();
+    assert_eq!((system, rest.len()), (None, 1));
+}
+
+#[test]
+fn test_char_and_word_count() {
+    let conversation: Conversation = vec![
+        InternalMessage::user("How are you?"),
+        InternalMessage::assistant("Fine, thanks."),
+    ]
+    .into();
+    assert_eq!(conversation.char_count(), 12 + 13);
+    assert_eq!(conversation.word_count(), 3 + 2);
+}
+
+#[cfg(feature = "events")]
+#[test]
+fn test_from_jsonl_path() {
+    use crate::events::{EventEnvelope, MessageEvent, ToolCall, ToolCallEvent};
+
+    let lines = [
+        EventEnvelope::message(MessageEvent::assistant("session_1", 2, "Hi there!"))
+            .to_json_line(),
+        EventEnvelope::message(MessageEvent::user("session_1", 1, "Hello")).to_json_line(),
+        "{not json".to_string(),
+        EventEnvelope::tool_call(ToolCallEvent::new(
+            "session_1",
+            3,
+            "evt_1",
+            ToolCall::new("call_1", "search", serde_json::json!({})),
+        ))
+        .to_json_line(),
+        String::new(),
+        EventEnvelope::message(MessageEvent::user("session_1", 4, "Thanks"))
+            .to_json_line_compact(),
+    ];
+
+    let path =
+        std::env::temp_dir().join(format!("umf_conversation_{}.jsonl", std::process::id()));
+    std::fs::write(&path, lines.join("\n")).unwrap();
+    let result = Conversation::from_jsonl_path_with_warnings(&path);
+    let plain = Conversation::from_jsonl_path(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    let (conversation, warnings) = result.unwrap();
+    let texts: Vec<_> = conversation.messages().iter().map(|m| m.to_text()).collect();
+    assert_eq!(texts, ["Hello", "Hi there!", "Thanks"]);
+    assert_eq!(conversation.messages()[0].role, MessageRole::User);
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].starts_with("line 3:"));
+    assert_eq!(plain.unwrap().len(), 3);
+
+    let missing = Conversation::from_jsonl_path(Path::new("/nonexistent/umf.jsonl"));
+    assert!(matches!(missing, Err(UmfError::Io(_))));
+}
+
+#[cfg(feature = "events")]
+#[test]
+fn test_from_jsonl_path_partial_messages() {
+    use crate::events::{EventEnvelope, MessageEvent};
+
+    let mut streaming = MessageEvent::partial("session_1", 2, "The answer");
+    let mut lines = vec![
+        EventEnvelope::message(MessageEvent::user("session_1", 1, "Question?")).to_json_line(),
+        EventEnvelope::message(streaming.clone()).to_json_line(),
+    ];
+    streaming.set_message(InternalMessage::assistant("The answer is"));
+    lines.push(EventEnvelope::message(streaming.clone()).to_json_line());
+    streaming.finalize(InternalMessage::assistant("The answer is 42."));
+    assert!(streaming.complete);
+    lines.push(EventEnvelope::message(streaming).to_json_line());
+    lines.push(
+        EventEnvelope::message(MessageEvent::partial("session_1", 3, "Also")).to_json_line(),
+    );
+
+    let path =
+        std::env::temp_dir().join(format!("umf_partial_{}.jsonl", std::process::id()));
+    std::fs::write(&path, lines.join("\n")).unwrap();
+    let result = Conversation::from_jsonl_path(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    // The interrupted partial at sequence 3 is kept rather than lost
+    let texts: Vec<_> = result.unwrap().messages().iter().map(|m| m.to_text()).collect();
+    assert_eq!(texts, ["Question?", "The answer is 42.", "Also"]);
+}
+
+#[test]
+fn test_retain_roles() {
+    let mut conversation = tool_conversation();
+    let roles = [MessageRole::User, MessageRole::Assistant];
+
+    let filtered = conversation.filtered_by_roles(&roles);
+    assert_eq!(conversation.len(), 7);
+    conversation.retain_roles(&roles);
+    assert_eq!((filtered.len(), conversation.len()), (5, 5));
+    // The tool use block is now orphaned; filtering does not repair it
+    assert!(matches!(
+        &conversation.messages()[3].content,
+        MessageContent::Blocks(blocks) if blocks.iter().any(|b| b.as_tool_use().is_some())
+    ));
+    assert!(conversation
+        .messages()
+        .iter()
+        .all(|m| matches!(m.role, MessageRole::User | MessageRole::Assistant)));
+
+    conversation.retain_roles(&[]);
+    assert!(conversation.is_empty());
+}
+
+#[test]
+fn test_repair_tool_pairing() {
+    let orphaned = || -> Conversation {
+        vec![
+            InternalMessage::user("What is the weather?"),
+            InternalMessage::tool_result("call_lost", "weather", "Sunny"),
+            InternalMessage::assistant("It is sunny."),
+        ]
+        .into()
+    };
+
+    let mut dropped = orphaned();
+    assert_eq!(dropped.repair_tool_pairing(RepairMode::DropOrphanResults), 1);
+    assert_eq!(dropped.len(), 2);
+    assert!(dropped.messages().iter().all(|m| m.role != MessageRole::Tool));
+
+    let mut synthesized = orphaned();
+    assert_eq!(synthesized.repair_tool_pairing(RepairMode::SynthesizeToolCalls), 1);
+    assert_eq!(synthesized.len(), 4);
+    let call = &synthesized.messages()[1];
+    assert_eq!(call.role, MessageRole::Assistant);
+    let (id, name, _) = call.blocks().unwrap()[0].as_tool_use().unwrap();
+    assert_eq!((id, name), ("call_lost", "weather"));
+    assert_eq!(synthesized.messages()[2].tool_call_id.as_deref(), Some("call_lost"));
+    assert!(crate::validate_conversation(synthesized.messages()).is_empty());
+
+    // Paired conversations are left untouched
+    let mut paired = tool_conversation();
+    let before = paired.len();
+    assert_eq!(paired.repair_tool_pairing(RepairMode::DropOrphanResults), 0);
+    assert_eq!(paired.len(), before);
+}
+
+#[test]
+fn test_iter_turns() {
+    let conversation = tool_conversation();
+    let turns: Vec<_> = conversation.iter_turns().collect();
+    let lens: Vec<_> = turns.iter().map(|turn| turn.len()).collect();
+    assert_eq!(lens, [1, 2, 4]);
+    assert_eq!(turns[0][0].role, MessageRole::System);
+    assert_eq!(turns[1][0].text(), Some("What is the weather in Paris today?"));
+    assert_eq!(turns[2][0].text(), Some("And in London?"));
+    assert_eq!(turns[2][3].text(), Some("It is rainy in London."));
+
+    // No system prompt: the first group is the first turn
+    let (_, dialogue) = conversation.split_at(1);
+    assert_eq!(dialogue.iter_turns().count(), 2);
+    assert_eq!(Conversation::new().iter_turns().count(), 0);
+}
+
+#[cfg(feature = "json-patch")]
+#[test]
+fn test_apply_patch() {
+    let mut conversation = tool_conversation();
+    conversation
+        .apply_patch(&serde_json::json!([
+            {"op": "add", "path": "/-", "value": {"role": "user", "content": "Thanks!"}}
+        ]))
+        .unwrap();
+    assert_eq!(conversation.len(), 8);
+    assert_eq!(conversation.messages()[7].role, MessageRole::User);
+    assert_eq!(conversation.messages()[7].text(), Some("Thanks!"));
+
+    // A result that is not a message list is rejected and nothing changes
+    let err = conversation
+        .apply_patch(&serde_json::json!([{"op": "add", "path": "/-", "value": 42}]))
+        .unwrap_err();
+    assert!(matches!(err, PatchError::InvalidResult(_)));
+    let err = conversation
+        .apply_patch(&serde_json::json!([{"op": "remove", "path": "/99"}]))
+        .unwrap_err();
+    assert!(matches!(err, PatchError::Apply(_)));
+    assert_eq!(conversation.len(), 8);
+}
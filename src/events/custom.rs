@@ -0,0 +1,95 @@
+//! Application-defined event type
+
+use super::traits::{Event, EventType};
+use super::{generate_id, now_ms};
+use serde::{Deserialize, Serialize};
+
+/// An application-defined event (e.g. a `checkpoint` or `approval`)
+///
+/// Its event type is [`EventType::Custom`] with the event's `kind`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CustomEvent {
+    /// Unique event ID
+    pub event_id: String,
+
+    /// Session this event belongs to
+    pub session_id: String,
+
+    /// Project hash (for storage routing)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project_hash: Option<String>,
+
+    /// Event timestamp (Unix milliseconds)
+    pub timestamp_ms: u64,
+
+    /// Sequence number for ordering
+    pub sequence: u32,
+
+    /// Event kind, used as the event type name
+    pub kind: String,
+
+    /// Arbitrary event data
+    pub data: serde_json::Value,
+}
+
+impl CustomEvent {
+    /// Create a new custom event
+    pub fn new(
+        session_id: impl Into<String>,
+        sequence: u32,
+        kind: impl Into<String>,
+        data: serde_json::Value,
+    ) -> Self {
+        Self {
+            event_id: generate_id(),
+            session_id: session_id.into(),
+            project_hash: None,
+            timestamp_ms: now_ms(),
+            sequence,
+            kind: kind.into(),
+            data,
+        }
+    }
+
+    /// Set project hash
+    pub fn with_project(mut self, project_hash: impl Into<String>) -> Self {
+        self.project_hash = Some(project_hash.into());
+        self
+    }
+
+    /// Set a specific event ID (useful for testing or migration)
+    pub fn with_event_id(mut self, event_id: impl Into<String>) -> Self {
+        self.event_id = event_id.into();
+        self
+    }
+}
+
+impl Event for CustomEvent {
+    fn event_id(&self) -> &str {
+        &self.event_id
+    }
+
+    fn event_type(&self) -> EventType {
+        EventType::Custom(self.kind.clone())
+    }
+
+    fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    fn timestamp_ms(&self) -> u64 {
+        self.timestamp_ms
+    }
+
+    fn sequence(&self) -> u32 {
+        self.sequence
+    }
+
+    fn project_hash(&self) -> Option<&str> {
+        self.project_hash.as_deref()
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
+}
@@ -1,14 +1,27 @@
 //! Event envelope for type-erased event storage
 
 use super::traits::EventType;
-use super::{MessageEvent, ToolCallEvent, ToolResultEvent};
+use super::{CustomEvent, MessageEvent, ToolCallEvent, ToolResultEvent};
+use crate::UmfError;
 use serde::{Deserialize, Serialize};
 
+/// Header fields duplicated inside every payload
+const HEADER_FIELDS: [&str; 5] = [
+    "event_id",
+    "session_id",
+    "project_hash",
+    "timestamp_ms",
+    "sequence",
+];
+
+/// Maximum number of characters of an offending line kept in parse errors
+const SNIPPET_MAX_CHARS: usize = 80;
+
 /// Event envelope for storage and serialization
 ///
 /// This provides a uniform wrapper for any event type, suitable for
 /// JSONL storage where each line is a single envelope.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct EventEnvelope {
     /// Unique event ID
     pub event_id: String,
@@ -73,6 +86,19 @@ impl EventEnvelope {
         }
     }
 
+    /// Create a custom event envelope
+    pub fn custom(event: CustomEvent) -> Self {
+        Self {
+            event_id: event.event_id.clone(),
+            event_type: EventType::Custom(event.kind.clone()),
+            session_id: event.session_id.clone(),
+            project_hash: event.project_hash.clone(),
+            timestamp_ms: event.timestamp_ms,
+            sequence: event.sequence,
+            payload: serde_json::to_value(&event).unwrap(),
+        }
+    }
+
     /// Extract as message event
     pub fn as_message_event(&self) -> Option<MessageEvent> {
         if self.event_type == EventType::Message {
@@ -100,13 +126,89 @@ impl EventEnvelope {
         }
     }
 
+    /// Extract as custom event
+    pub fn as_custom_event(&self) -> Option<CustomEvent> {
+        if matches!(self.event_type, EventType::Custom(_)) {
+            serde_json::from_value(self.payload.clone()).ok()
+        } else {
+            None
+        }
+    }
+
     /// Serialize to JSON string (for JSONL storage)
     pub fn to_json_line(&self) -> String {
         serde_json::to_string(self).unwrap()
     }
 
     /// Parse from JSON string (for JSONL reading)
-    pub fn from_json_line(line: &str) -> Result<Self, serde_json::Error> {
-        serde_json::from_str(line)
+    ///
+    /// A leading UTF-8 BOM and surrounding whitespace (including CRLF line
+    /// endings) are ignored. On failure the error carries a truncated snippet
+    /// of the line.
+    pub fn from_json_line(line: &str) -> Result<Self, UmfError> {
+        let trimmed = line.trim_start_matches('\u{feff}').trim();
+        serde_json::from_str(trimmed).map_err(|error| UmfError::JsonLine {
+            error,
+            snippet: snippet(trimmed),
+        })
+    }
+
+    /// Serialize to a compact JSON string
+    ///
+    /// Header fields duplicated inside the payload (`event_id`, `session_id`,
+    /// `project_hash`, `timestamp_ms`, `sequence`) are stripped from the
+    /// payload. Read back with [`from_json_line_compact`](Self::from_json_line_compact).
+    pub fn to_json_line_compact(&self) -> String {
+        let mut compact = self.clone();
+        if let Some(payload) = compact.payload.as_object_mut() {
+            for field in HEADER_FIELDS {
+                payload.remove(field);
+            }
+        }
+        compact.to_json_line()
+    }
+
+    /// Parse a line written by [`to_json_line_compact`](Self::to_json_line_compact)
+    ///
+    /// Restores the stripped payload fields from the header. Lines written by
+    /// [`to_json_line`](Self::to_json_line) are accepted too.
+    pub fn from_json_line_compact(line: &str) -> Result<Self, UmfError> {
+        let mut envelope = Self::from_json_line(line)?;
+        let header = serde_json::json!({
+            "event_id": envelope.event_id,
+            "session_id": envelope.session_id,
+            "project_hash": envelope.project_hash,
+            "timestamp_ms": envelope.timestamp_ms,
+            "sequence": envelope.sequence,
+        });
+        if let Some(payload) = envelope.payload.as_object_mut() {
+            for field in HEADER_FIELDS {
+                let value = &header[field];
+                if !value.is_null() && !payload.contains_key(field) {
+                    payload.insert(field.to_string(), value.clone());
+                }
+            }
+        }
+        Ok(envelope)
+    }
+}
+
+/// Select the envelopes with `start_ms <= timestamp_ms < end_ms`, in order
+pub fn filter_time_range(
+    envelopes: &[EventEnvelope],
+    start_ms: u64,
+    end_ms: u64,
+) -> Vec<&EventEnvelope> {
+    envelopes
+        .iter()
+        .filter(|envelope| (start_ms..end_ms).contains(&envelope.timestamp_ms))
+        .collect()
+}
+
+/// Truncate a line for inclusion in an error message
+fn snippet(line: &str) -> String {
+    match line.char_indices().nth(SNIPPET_MAX_CHARS) {
+        Some((idx, _)) => format!("{}...", &line[..idx]),
+        None => line.to_string(),
     }
 }
@@ -1,25 +1,17 @@
 //! Message event type
 
 use super::traits::{Event, EventType};
-use crate::InternalMessage;
+use super::{generate_id, now_ms};
+use crate::{InternalMessage, Provider, TokenCounter};
 use serde::{Deserialize, Serialize};
-use std::time::{SystemTime, UNIX_EPOCH};
-
-/// Generate a simple UUID-like ID
-fn generate_id() -> String {
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_nanos();
-    format!("evt_{:x}", now)
+use std::path::Path;
+
+fn default_complete() -> bool {
+    true
 }
 
-/// Get current timestamp in milliseconds
-fn now_ms() -> u64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_millis() as u64
+fn is_complete(complete: &bool) -> bool {
+    *complete
 }
 
 /// Information about the model that generated a message
@@ -32,6 +24,18 @@ pub struct ModelInfo {
     pub provider: Option<String>,
 }
 
+impl ModelInfo {
+    /// Get the normalized provider
+    ///
+    /// A missing provider yields an empty [`Provider::Other`].
+    pub fn provider_enum(&self) -> Provider {
+        match &self.provider {
+            Some(provider) => provider.parse().unwrap_or_else(|never| match never {}),
+            None => Provider::Other(String::new()),
+        }
+    }
+}
+
 /// A message event in a conversation
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MessageEvent {
@@ -61,6 +65,19 @@ pub struct MessageEvent {
     /// Model information (for assistant messages)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub model_info: Option<ModelInfo>,
+
+    /// Time to first token in milliseconds (for assistant messages)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttft_ms: Option<u64>,
+
+    /// Total generation duration in milliseconds (for assistant messages)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generation_ms: Option<u64>,
+
+    /// Whether the message is final; `false` for a partial, still streaming
+    /// message that a later event with the same sequence number supersedes
+    #[serde(default = "default_complete", skip_serializing_if = "is_complete")]
+    pub complete: bool,
 }
 
 impl MessageEvent {
@@ -75,6 +92,9 @@ impl MessageEvent {
             message,
             token_count: None,
             model_info: None,
+            ttft_ms: None,
+            generation_ms: None,
+            complete: true,
         }
     }
 
@@ -101,18 +121,56 @@ impl MessageEvent {
         Self::new(session_id, sequence, InternalMessage::system(content))
     }
 
+    /// Create a partial assistant message event holding the text streamed so far
+    ///
+    /// Persist it repeatedly under the same sequence number while streaming,
+    /// then [`finalize`](Self::finalize) it; reconstruction keeps the final
+    /// version, or the latest partial if generation never finished.
+    pub fn partial(
+        session_id: impl Into<String>,
+        sequence: u32,
+        text_so_far: impl Into<String>,
+    ) -> Self {
+        Self::assistant(session_id, sequence, text_so_far).with_complete(false)
+    }
+
+    /// Set whether the message is final
+    pub fn with_complete(mut self, complete: bool) -> Self {
+        self.complete = complete;
+        self
+    }
+
+    /// Replace a partial message with the final one and mark it complete
+    pub fn finalize(&mut self, final_message: InternalMessage) {
+        self.set_message(final_message);
+        self.complete = true;
+    }
+
     /// Set project hash
     pub fn with_project(mut self, project_hash: impl Into<String>) -> Self {
         self.project_hash = Some(project_hash.into());
         self
     }
 
+    /// Set project hash computed from a project directory (see [`super::project_hash`])
+    pub fn with_project_path(mut self, path: impl AsRef<Path>) -> Self {
+        self.project_hash = Some(super::project_hash(path.as_ref()));
+        self
+    }
+
     /// Set token count
     pub fn with_token_count(mut self, count: usize) -> Self {
         self.token_count = Some(count);
         self
     }
 
+    /// Set generation latency (time to first token and total duration)
+    pub fn with_latency(mut self, ttft_ms: u64, generation_ms: u64) -> Self {
+        self.ttft_ms = Some(ttft_ms);
+        self.generation_ms = Some(generation_ms);
+        self
+    }
+
     /// Set model info
     pub fn with_model_info(mut self, model: impl Into<String>, provider: Option<String>) -> Self {
         self.model_info = Some(ModelInfo {
@@ -122,6 +180,19 @@ impl MessageEvent {
         self
     }
 
+    /// Replace the message, clearing the now-stale cached token count
+    pub fn set_message(&mut self, message: InternalMessage) {
+        self.message = message;
+        self.token_count = None;
+    }
+
+    /// Recompute and cache the token count of the current message
+    pub fn recount_tokens(&mut self, counter: &TokenCounter) -> usize {
+        let count = counter.count_message(&self.message);
+        self.token_count = Some(count);
+        count
+    }
+
     /// Set a specific event ID (useful for testing or migration)
     pub fn with_event_id(mut self, event_id: impl Into<String>) -> Self {
         self.event_id = event_id.into();
@@ -150,6 +221,10 @@ impl Event for MessageEvent {
         self.sequence
     }
 
+    fn project_hash(&self) -> Option<&str> {
+        self.project_hash.as_deref()
+    }
+
     fn to_json(&self) -> serde_json::Value {
         serde_json::to_value(self).unwrap()
     }
@@ -9,8 +9,12 @@
 //! - [`MessageEvent`]: A message in a conversation (user, assistant, system)
 //! - [`ToolCallEvent`]: A tool call requested by the assistant
 //! - [`ToolResultEvent`]: The result of executing a tool
+//! - [`CustomEvent`]: An application-defined event kind
 //! - [`EventEnvelope`]: A wrapper that can hold any event type
 //!
+//! Envelopes are stored one per line in JSONL logs; [`EventLogWriter`]
+//! appends them to a file.
+//!
 //! ## Usage
 //!
 //! ```rust
@@ -25,17 +29,48 @@
 //! let json_line = serde_json::to_string(&envelope).unwrap();
 //! ```
 
+mod custom;
 mod envelope;
 mod message;
+mod project;
+mod summary;
 mod tool_call;
 mod tool_result;
 mod traits;
+mod turns;
+mod writer;
 
-pub use envelope::EventEnvelope;
+pub use custom::CustomEvent;
+pub use envelope::{filter_time_range, EventEnvelope};
 pub use message::{MessageEvent, ModelInfo};
-pub use tool_call::{McpContext, ToolCall, ToolCallEvent, ToolCallStatus};
+pub use project::project_hash;
+pub use summary::{summarize, LogSummary};
+pub use tool_call::{
+    parse_mcp_tool_name, McpContext, McpTransport, ToolCall, ToolCallEvent, ToolCallStatus,
+};
 pub use tool_result::{ToolResult, ToolResultEvent};
 pub use traits::{Event, EventType};
+pub use turns::{group_into_turns, Turn};
+pub use writer::EventLogWriter;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Generate a simple UUID-like ID
+pub(crate) fn generate_id() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    format!("evt_{:x}", now)
+}
+
+/// Get current timestamp in milliseconds
+pub(crate) fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
 
 #[cfg(test)]
 mod tests;
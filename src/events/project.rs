@@ -0,0 +1,22 @@
+//! Project hash computation for storage routing
+
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+/// Number of hex characters kept from the SHA-256 digest
+const PROJECT_HASH_LEN: usize = 12;
+
+/// Compute a short, stable hash identifying a project directory
+///
+/// The path is canonicalized first (falling back to the path as given if it
+/// cannot be resolved), so different spellings of the same directory map to
+/// the same hash. Returns the first 12 hex characters of the SHA-256 digest.
+pub fn project_hash(path: &Path) -> String {
+    let canonical = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let digest = Sha256::digest(canonical.to_string_lossy().as_bytes());
+    digest
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>()[..PROJECT_HASH_LEN]
+        .to_string()
+}
@@ -0,0 +1,43 @@
+//! Event log statistics
+
+use super::{EventEnvelope, EventType};
+use crate::MessageRole;
+use std::collections::{HashMap, HashSet};
+
+/// Counts over an event log
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogSummary {
+    /// Total number of events
+    pub total: usize,
+    /// Number of events per event type
+    pub by_type: HashMap<EventType, usize>,
+    /// Number of message events per message role
+    pub by_role: HashMap<MessageRole, usize>,
+    /// Distinct session IDs
+    pub sessions: HashSet<String>,
+}
+
+/// Summarize an event log
+///
+/// Roles are read from the payload of message events; message events whose
+/// payload has no valid role are counted by type only.
+pub fn summarize(envelopes: &[EventEnvelope]) -> LogSummary {
+    let mut summary = LogSummary {
+        total: envelopes.len(),
+        ..LogSummary::default()
+    };
+
+    for envelope in envelopes {
+        *summary.by_type.entry(envelope.event_type.clone()).or_default() += 1;
+        summary.sessions.insert(envelope.session_id.clone());
+
+        if envelope.event_type == EventType::Message {
+            let role = envelope.payload.pointer("/message/role").cloned();
+            if let Some(role) = role.and_then(|r| serde_json::from_value(r).ok()) {
+                *summary.by_role.entry(role).or_default() += 1;
+            }
+        }
+    }
+
+    summary
+}
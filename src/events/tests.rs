@@ -33,6 +33,69 @@ fn test_message_event_with_model_info() {
     let model = event.model_info.unwrap();
     assert_eq!(model.model_name, "gpt-4o");
     assert_eq!(model.provider, Some("openai".to_string()));
+    assert_eq!(model.provider_enum(), crate::Provider::OpenAI);
+}
+
+#[test]
+fn test_model_info_provider_enum() {
+    let info = |provider: Option<&str>| ModelInfo {
+        model_name: "model".to_string(),
+        provider: provider.map(str::to_string),
+    };
+
+    assert_eq!(info(Some("OpenAI")).provider_enum(), crate::Provider::OpenAI);
+    assert_eq!(info(Some("azure")).provider_enum(), crate::Provider::Azure);
+    assert_eq!(info(Some("azure-openai")).provider_enum(), crate::Provider::Azure);
+    assert_eq!(
+        info(Some("Acme AI")).provider_enum(),
+        crate::Provider::Other("Acme AI".to_string())
+    );
+    assert_eq!(info(None).provider_enum(), crate::Provider::Other(String::new()));
+}
+
+#[test]
+fn test_message_event_latency_round_trip() {
+    let event = MessageEvent::assistant("session_1", 2, "Done").with_latency(180, 2400);
+    let line = EventEnvelope::message(event).to_json_line();
+    let parsed = EventEnvelope::from_json_line(&line)
+        .unwrap()
+        .as_message_event()
+        .unwrap();
+    assert_eq!(parsed.ttft_ms, Some(180));
+    assert_eq!(parsed.generation_ms, Some(2400));
+
+    let line = EventEnvelope::message(MessageEvent::user("session_1", 1, "Hi")).to_json_line();
+    assert!(!line.contains("ttft_ms"));
+    assert!(!line.contains("generation_ms"));
+}
+
+#[test]
+fn test_message_event_set_message_invalidates_token_count() {
+    let counter = crate::TokenCounter::new().unwrap();
+    let mut event = MessageEvent::user("session_1", 1, "Hello").with_token_count(1);
+
+    event.set_message(crate::InternalMessage::user("Hello world, how are you?"));
+    assert_eq!(event.token_count, None);
+    assert_eq!(event.message.text(), Some("Hello world, how are you?"));
+
+    let count = event.recount_tokens(&counter);
+    assert_eq!(count, counter.count_text("Hello world, how are you?"));
+    assert_eq!(event.token_count, Some(count));
+}
+
+#[test]
+fn test_project_hash() {
+    let dir = std::env::temp_dir();
+    let hash = project_hash(&dir);
+
+    assert_eq!(hash.len(), 12);
+    assert!(hash.chars().all(|c| c.is_ascii_hexdigit()));
+    assert_eq!(project_hash(&dir), hash);
+    assert_eq!(project_hash(&dir.join(".")), hash);
+    assert_ne!(project_hash(&dir.join("umf-other-project")), hash);
+
+    let event = MessageEvent::user("session_1", 1, "Hi").with_project_path(&dir);
+    assert_eq!(event.project_hash, Some(hash));
 }
 
 #[test]
@@ -65,7 +128,7 @@ fn test_tool_call_event_with_mcp() {
         .with_mcp_context(McpContext {
             server_name: "my_server".to_string(),
             server_url: Some("http://localhost:9000".to_string()),
-            transport: Some("stdio".to_string()),
+            transport: Some(McpTransport::Stdio),
         })
         .with_status(ToolCallStatus::Executing);
 
@@ -76,6 +139,81 @@ fn test_tool_call_event_with_mcp() {
     assert_eq!(ctx.server_name, "my_server");
 }
 
+#[test]
+fn test_tool_call_status_history() {
+    let tool_call = ToolCall::new("call_1", "search", serde_json::json!({}));
+    let mut event = ToolCallEvent::new("session_1", 1, "msg_1", tool_call);
+
+    event.transition_to(ToolCallStatus::Executing);
+    event.transition_to(ToolCallStatus::Completed);
+
+    assert_eq!(event.status, ToolCallStatus::Completed);
+    let statuses: Vec<_> = event.status_history.iter().map(|(_, s)| *s).collect();
+    assert_eq!(
+        statuses,
+        vec![
+            ToolCallStatus::Pending,
+            ToolCallStatus::Executing,
+            ToolCallStatus::Completed
+        ]
+    );
+    assert!(event
+        .status_history
+        .windows(2)
+        .all(|w| w[0].0 <= w[1].0 && w[0].0 > 0));
+
+    // History survives a JSONL roundtrip
+    let envelope = EventEnvelope::tool_call(event);
+    let parsed = EventEnvelope::from_json_line(&envelope.to_json_line()).unwrap();
+    let extracted = parsed.as_tool_call_event().unwrap();
+    assert_eq!(extracted.status_history.len(), 3);
+}
+
+#[test]
+fn test_tool_call_parsed_arguments() {
+    let call = ToolCall::new("call_1", "search", serde_json::json!({"query": "rust"}));
+    assert_eq!(call.parsed_arguments().unwrap(), serde_json::json!({"query": "rust"}));
+
+    let raw = ToolCall::new("call_2", "search", serde_json::json!(r#"{"query": "rust"}"#));
+    assert_eq!(raw.parsed_arguments().unwrap(), serde_json::json!({"query": "rust"}));
+
+    let invalid = ToolCall::new("call_3", "search", serde_json::json!("{oops"));
+    assert!(invalid.parsed_arguments().is_err());
+}
+
+#[test]
+fn test_mcp_transport_normalization() {
+    let parse = |s: &str| s.parse::<McpTransport>().unwrap();
+    assert_eq!(parse("STDIO"), McpTransport::Stdio);
+    assert_eq!(parse("Streamable_HTTP"), McpTransport::Http);
+    assert_eq!(parse("ws"), McpTransport::WebSocket);
+    assert_eq!(parse("grpc"), McpTransport::Other("grpc".to_string()));
+
+    // Old free-form string values still deserialize, and serialize normalized
+    let ctx: McpContext =
+        serde_json::from_value(serde_json::json!({"server_name": "fs", "transport": "STDIO"}))
+            .unwrap();
+    assert_eq!(ctx.transport, Some(McpTransport::Stdio));
+    assert_eq!(serde_json::to_value(&ctx).unwrap()["transport"], "stdio");
+}
+
+#[test]
+fn test_mcp_tool_namespacing() {
+    let ctx = McpContext {
+        server_name: "fs".to_string(),
+        server_url: None,
+        transport: Some(McpTransport::Stdio),
+    };
+
+    let name = ctx.qualified_name("read_file");
+    assert_eq!(name, "fs__read_file");
+    assert_eq!(parse_mcp_tool_name(&name), Some(("fs", "read_file")));
+
+    assert_eq!(parse_mcp_tool_name("read_file"), None);
+    assert_eq!(parse_mcp_tool_name("__read_file"), None);
+    assert_eq!(parse_mcp_tool_name("fs__"), None);
+}
+
 #[test]
 fn test_tool_result_event_success() {
     let event = ToolResultEvent::success(
@@ -176,6 +314,57 @@ fn test_event_envelope_roundtrip_tool_result() {
     assert_eq!(extracted.result.tool_call_id, "call_test");
 }
 
+#[test]
+fn test_from_json_line_strips_bom_and_crlf() {
+    let envelope = EventEnvelope::message(
+        MessageEvent::user("session_1", 1, "Hello").with_event_id("evt_bom"),
+    );
+    let json_line = envelope.to_json_line();
+
+    let parsed = EventEnvelope::from_json_line(&format!("\u{feff}{}", json_line)).unwrap();
+    assert_eq!(parsed.event_id, "evt_bom");
+
+    let parsed = EventEnvelope::from_json_line(&format!("{}\r\n", json_line)).unwrap();
+    assert_eq!(parsed.event_id, "evt_bom");
+}
+
+#[test]
+fn test_from_json_line_error_includes_snippet() {
+    let line = format!("{{\"event_id\": {}", "x".repeat(200));
+    let err = EventEnvelope::from_json_line(&line).unwrap_err();
+
+    match &err {
+        crate::UmfError::JsonLine { snippet, .. } => {
+            assert!(snippet.starts_with("{\"event_id\": xxx"));
+            assert!(snippet.ends_with("..."));
+            assert!(snippet.len() < line.len());
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+    assert!(err.to_string().starts_with("invalid json line"));
+}
+
+#[test]
+fn test_compact_json_line_roundtrip() {
+    let tool_call = ToolCall::new("call_1", "search", serde_json::json!({"q": "rust"}));
+    let event = ToolCallEvent::new("session_1", 7, "msg_1", tool_call).with_project("abc123");
+    let envelope = EventEnvelope::tool_call(event);
+
+    let full = envelope.to_json_line();
+    let compact = envelope.to_json_line_compact();
+    assert!(compact.len() < full.len());
+
+    let parsed = EventEnvelope::from_json_line_compact(&compact).unwrap();
+    assert_eq!(parsed, envelope);
+    assert_eq!(
+        parsed.as_tool_call_event().unwrap().project_hash.as_deref(),
+        Some("abc123")
+    );
+
+    // Full lines are accepted by the compact reader
+    assert_eq!(EventEnvelope::from_json_line_compact(&full).unwrap(), envelope);
+}
+
 #[test]
 fn test_event_type_mismatch_returns_none() {
     let msg_event = MessageEvent::user("session_1", 1, "Test");
@@ -204,6 +393,18 @@ fn test_tool_result_helper_methods() {
     assert_eq!(error.content, serde_json::json!("Something went wrong"));
 }
 
+#[test]
+fn test_tool_result_truncated() {
+    let content = "x".repeat(10 * 1024);
+    let result = ToolResult::truncated("call_1", content, 1024);
+    let text = result.content.as_str().unwrap();
+
+    assert!(!result.is_error);
+    assert!(text.starts_with(&"x".repeat(1024)));
+    assert!(text.ends_with("\n…[truncated 9216 bytes]"));
+    assert!(std::str::from_utf8(text.as_bytes()).is_ok());
+}
+
 #[test]
 fn test_event_trait_implementations() {
     use super::traits::Event;
@@ -224,3 +425,244 @@ fn test_event_trait_implementations() {
     let tr = ToolResultEvent::success("sess", 3, "tc1", "c1", serde_json::json!("ok"));
     assert_eq!(tr.event_type(), EventType::ToolResult);
 }
+
+#[test]
+fn test_to_envelope_through_dyn_event() {
+    let tool_call = ToolCall::new("c1", "test", serde_json::json!({}));
+    let events: Vec<Box<dyn Event>> = vec![
+        Box::new(MessageEvent::user("sess", 1, "Hello").with_project("proj")),
+        Box::new(ToolCallEvent::new("sess", 2, "m1", tool_call)),
+        Box::new(ToolResultEvent::success("sess", 3, "tc1", "c1", serde_json::json!("ok"))),
+    ];
+
+    for event in &events {
+        let envelope = event.to_envelope();
+        assert_eq!(envelope.event_type, event.event_type());
+        assert_eq!(envelope.event_id, event.event_id());
+        assert_eq!(envelope.session_id, "sess");
+        assert_eq!(envelope.sequence, event.sequence());
+        assert_eq!(envelope.timestamp_ms, event.timestamp_ms());
+    }
+
+    let envelope = events[0].to_envelope();
+    assert_eq!(envelope.project_hash.as_deref(), Some("proj"));
+    assert!(envelope.as_message_event().is_some());
+    assert!(events[1].to_envelope().as_tool_call_event().is_some());
+    assert!(events[2].to_envelope().as_tool_result_event().is_some());
+}
+
+#[test]
+fn test_event_type_and_status_from_str() {
+    for event_type in [
+        EventType::Message,
+        EventType::ToolCall,
+        EventType::ToolResult,
+        EventType::SystemSignal,
+        EventType::Error,
+    ] {
+        assert_eq!(event_type.as_str().parse::<EventType>().unwrap(), event_type);
+    }
+    assert!("wizard".parse::<EventType>().is_err());
+
+    for status in [
+        ToolCallStatus::Pending,
+        ToolCallStatus::Executing,
+        ToolCallStatus::Completed,
+        ToolCallStatus::Failed,
+        ToolCallStatus::Cancelled,
+    ] {
+        let serialized = serde_json::to_value(status).unwrap();
+        assert_eq!(serialized, status.as_str());
+        assert_eq!(status.as_str().parse::<ToolCallStatus>().unwrap(), status);
+    }
+    assert!("wizard".parse::<ToolCallStatus>().is_err());
+}
+
+#[test]
+fn test_tool_call_argument_metrics() {
+    let arguments = serde_json::json!({
+        "path": "src/lib.rs",
+        "options": {"recursive": true, "depth": 3},
+        "patterns": ["*.rs", "*.toml"]
+    });
+    let expected_bytes = serde_json::to_string(&arguments).unwrap().len();
+
+    let event = ToolCallEvent::new("s", 1, "m", ToolCall::new("c1", "search", arguments));
+    assert_eq!(event.arguments_bytes(), expected_bytes);
+    assert!(event.arguments_bytes() > 50);
+    assert!(event.arguments_valid());
+
+    // Metrics follow edits to the arguments instead of going stale
+    let mut event = event;
+    event.tool_call.arguments = serde_json::json!("{not json");
+    assert_eq!(event.arguments_bytes(), 11);
+    assert!(!event.arguments_valid());
+
+    let raw = ToolCall::new("c2", "search", serde_json::json!("{not json"));
+    assert_eq!(raw.arguments_bytes(), serde_json::to_string(&raw.arguments).unwrap().len());
+    assert_eq!(raw.arguments_bytes(), 11);
+    assert!(!raw.arguments_valid());
+    assert!(ToolCall::new("c3", "search", serde_json::json!("{}")).arguments_valid());
+    assert!(ToolCall::new("c4", "search", serde_json::json!("[1, 2]")).arguments_valid());
+}
+
+#[test]
+fn test_summarize() {
+    let log = vec![
+        EventEnvelope::message(MessageEvent::user("session_1", 1, "Hi")),
+        EventEnvelope::message(MessageEvent::assistant("session_1", 2, "Hello")),
+        EventEnvelope::tool_call(ToolCallEvent::new(
+            "session_1",
+            3,
+            "evt_1",
+            ToolCall::new("call_1", "search", serde_json::json!({})),
+        )),
+        EventEnvelope::message(MessageEvent::user("session_2", 1, "Again")),
+    ];
+
+    let summary = summarize(&log);
+    assert_eq!(summary.total, 4);
+    assert_eq!(summary.by_type[&EventType::Message], 3);
+    assert_eq!(summary.by_type[&EventType::ToolCall], 1);
+    assert!(!summary.by_type.contains_key(&EventType::ToolResult));
+    assert_eq!(summary.by_role[&crate::MessageRole::User], 2);
+    assert_eq!(summary.by_role[&crate::MessageRole::Assistant], 1);
+    assert_eq!(summary.sessions.len(), 2);
+
+    assert_eq!(summarize(&[]), LogSummary::default());
+}
+
+#[test]
+fn test_filter_time_range() {
+    let log: Vec<EventEnvelope> = [1_000, 5_000, 2_000, 9_000, 3_000]
+        .into_iter()
+        .enumerate()
+        .map(|(i, timestamp_ms)| {
+            let mut event = MessageEvent::user("session_1", i as u32 + 1, "Hi");
+            event.timestamp_ms = timestamp_ms;
+            EventEnvelope::message(event)
+        })
+        .collect();
+
+    let selected = filter_time_range(&log, 2_000, 9_000);
+    let timestamps: Vec<u64> = selected.iter().map(|e| e.timestamp_ms).collect();
+    assert_eq!(timestamps, vec![5_000, 2_000, 3_000]);
+    assert!(std::ptr::eq(selected[0], &log[1]));
+
+    assert!(filter_time_range(&log, 9_000, 9_000).is_empty());
+    assert_eq!(filter_time_range(&log, 0, u64::MAX).len(), log.len());
+}
+
+#[test]
+fn test_group_into_turns() {
+    let user = MessageEvent::user("session_1", 1, "Weather in Paris?");
+    let assistant = MessageEvent::assistant("session_1", 2, "Let me check");
+    let call = ToolCallEvent::new(
+        "session_1",
+        3,
+        assistant.event_id.clone(),
+        ToolCall::new("call_1", "weather", serde_json::json!({"city": "Paris"})),
+    );
+    let result = ToolResultEvent::success(
+        "session_1",
+        4,
+        call.event_id.clone(),
+        "call_1",
+        serde_json::json!("Sunny"),
+    );
+    let next = MessageEvent::user("session_1", 5, "Thanks!");
+
+    let log = vec![
+        EventEnvelope::message(user.clone()),
+        EventEnvelope::message(assistant.clone()),
+        EventEnvelope::tool_call(call.clone()),
+        EventEnvelope::tool_result(result.clone()),
+        EventEnvelope::message(next),
+    ];
+
+    let turns = group_into_turns(&log);
+    assert_eq!(turns.len(), 2);
+    let turn = &turns[0];
+    assert_eq!(turn.user.as_ref().unwrap().event_id, user.event_id);
+    assert_eq!(turn.assistant.as_ref().unwrap().event_id, assistant.event_id);
+    assert_eq!(turn.tool_calls.len(), 1);
+    assert_eq!(turn.tool_calls[0].event_id, call.event_id);
+    assert_eq!(turn.tool_results.len(), 1);
+    assert_eq!(turn.tool_results[0].event_id, result.event_id);
+
+    assert!(turns[1].assistant.is_none());
+    assert!(turns[1].tool_calls.is_empty());
+}
+
+#[test]
+fn test_event_log_writer() {
+    let path = std::env::temp_dir().join(format!("umf_writer_{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let events = [
+        EventEnvelope::message(MessageEvent::user("session_1", 1, "Hello")),
+        EventEnvelope::message(MessageEvent::assistant("session_1", 2, "Hi there!")),
+        EventEnvelope::tool_call(ToolCallEvent::new(
+            "session_1",
+            3,
+            "evt_1",
+            ToolCall::new("call_1", "search", serde_json::json!({"q": "rust"})),
+        )),
+    ];
+
+    let mut writer = EventLogWriter::open(&path).unwrap();
+    for envelope in &events[..2] {
+        writer.append(envelope).unwrap();
+    }
+    writer.flush_sync().unwrap();
+    assert_eq!(writer.events_written(), 2);
+    drop(writer);
+
+    // Reopening appends rather than truncating
+    let mut writer = EventLogWriter::open(&path).unwrap();
+    writer.append(&events[2]).unwrap();
+    drop(writer);
+
+    let contents = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let read: Vec<_> = contents
+        .lines()
+        .map(|line| EventEnvelope::from_json_line(line).unwrap())
+        .collect();
+    assert_eq!(read, events);
+}
+
+#[test]
+fn test_custom_event_roundtrip() {
+    let event = CustomEvent::new(
+        "session_1",
+        9,
+        "checkpoint",
+        serde_json::json!({"files": ["src/lib.rs"], "label": "before refactor"}),
+    )
+    .with_project("proj_1");
+
+    let envelope = EventEnvelope::custom(event.clone());
+    assert_eq!(envelope.event_type, EventType::Custom("checkpoint".to_string()));
+    assert_eq!(event.to_envelope(), envelope);
+
+    let line = envelope.to_json_line();
+    assert!(line.contains(r#""event_type":"checkpoint""#));
+    let parsed = EventEnvelope::from_json_line(&line).unwrap();
+    assert_eq!(parsed.as_custom_event(), Some(event.clone()));
+    assert!(parsed.as_message_event().is_none());
+
+    let compact = EventEnvelope::from_json_line_compact(&envelope.to_json_line_compact()).unwrap();
+    assert_eq!(compact.as_custom_event(), Some(event));
+
+    // Built-in types serialize exactly as before
+    let message = EventEnvelope::message(MessageEvent::user("session_1", 1, "Hi"));
+    assert!(message.to_json_line().contains(r#""event_type":"message""#));
+    assert_eq!(
+        serde_json::to_value(EventType::SystemSignal).unwrap(),
+        "system_signal"
+    );
+    assert!(EventEnvelope::message(MessageEvent::user("s", 1, "Hi"))
+        .as_custom_event()
+        .is_none());
+}
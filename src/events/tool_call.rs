@@ -1,25 +1,8 @@
 //! Tool call event type
 
 use super::traits::{Event, EventType};
+use super::{generate_id, now_ms};
 use serde::{Deserialize, Serialize};
-use std::time::{SystemTime, UNIX_EPOCH};
-
-/// Generate a simple UUID-like ID
-fn generate_id() -> String {
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_nanos();
-    format!("evt_{:x}", now)
-}
-
-/// Get current timestamp in milliseconds
-fn now_ms() -> u64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_millis() as u64
-}
 
 /// Simple tool call representation for events
 ///
@@ -44,13 +27,42 @@ impl ToolCall {
             arguments,
         }
     }
+
+    /// Size of the arguments serialized as compact JSON, in bytes
+    ///
+    /// Arguments kept as a raw string are measured as a JSON string, i.e.
+    /// including the quotes and escapes.
+    pub fn arguments_bytes(&self) -> usize {
+        self.arguments.to_string().len()
+    }
+
+    /// Get the arguments as a JSON value
+    ///
+    /// Mirrors [`crate::ToolCall::parsed_arguments`]: arguments kept as a raw
+    /// string are parsed, any other value is returned as is.
+    pub fn parsed_arguments(&self) -> Result<serde_json::Value, serde_json::Error> {
+        match &self.arguments {
+            serde_json::Value::String(raw) => serde_json::from_str(raw),
+            other => Ok(other.clone()),
+        }
+    }
+
+    /// Check that the arguments parse as JSON
+    ///
+    /// Arguments kept as a raw string (as providers send them) are valid if
+    /// the string parses as any JSON value; see
+    /// [`parsed_arguments`](Self::parsed_arguments).
+    pub fn arguments_valid(&self) -> bool {
+        self.parsed_arguments().is_ok()
+    }
 }
 
 /// Tool call execution status
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum ToolCallStatus {
     /// Tool call created, not yet executing
+    #[default]
     Pending,
     /// Tool is currently executing
     Executing,
@@ -62,9 +74,109 @@ pub enum ToolCallStatus {
     Cancelled,
 }
 
-impl Default for ToolCallStatus {
-    fn default() -> Self {
-        Self::Pending
+impl ToolCallStatus {
+    /// Convert to string representation
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Pending => "pending",
+            Self::Executing => "executing",
+            Self::Completed => "completed",
+            Self::Failed => "failed",
+            Self::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl std::fmt::Display for ToolCallStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl std::str::FromStr for ToolCallStatus {
+    type Err = crate::UmfError;
+
+    /// Parse a status name (inverse of [`ToolCallStatus::as_str`])
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(Self::Pending),
+            "executing" => Ok(Self::Executing),
+            "completed" => Ok(Self::Completed),
+            "failed" => Ok(Self::Failed),
+            "cancelled" => Ok(Self::Cancelled),
+            other => Err(crate::UmfError::InvalidInput(format!(
+                "unknown tool call status `{}`",
+                other
+            ))),
+        }
+    }
+}
+
+/// MCP server transport
+///
+/// Parsing normalizes case, `_`/space separators and common aliases (e.g.
+/// `"STDIO"` yields [`McpTransport::Stdio`], `"streamable-http"` yields
+/// [`McpTransport::Http`] and `"ws"` yields [`McpTransport::WebSocket`]).
+/// Unknown names are kept as [`McpTransport::Other`]. Serializes as the
+/// [`as_str`](Self::as_str) name, and deserializes from any string.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum McpTransport {
+    /// Standard input/output of a child process
+    Stdio,
+    /// HTTP (including streamable HTTP)
+    Http,
+    /// Server-sent events
+    Sse,
+    /// WebSocket
+    WebSocket,
+    /// Any other transport, as given
+    Other(String),
+}
+
+impl McpTransport {
+    /// Convert to string representation
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Stdio => "stdio",
+            Self::Http => "http",
+            Self::Sse => "sse",
+            Self::WebSocket => "websocket",
+            Self::Other(name) => name,
+        }
+    }
+}
+
+impl std::fmt::Display for McpTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl std::str::FromStr for McpTransport {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized = s.trim().to_lowercase().replace(['_', ' '], "-");
+        Ok(match normalized.as_str() {
+            "stdio" => Self::Stdio,
+            "http" | "https" | "streamable-http" | "streamablehttp" => Self::Http,
+            "sse" | "server-sent-events" => Self::Sse,
+            "websocket" | "web-socket" | "ws" | "wss" => Self::WebSocket,
+            _ => Self::Other(s.trim().to_string()),
+        })
+    }
+}
+
+impl Serialize for McpTransport {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for McpTransport {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ok(name.parse().unwrap_or_else(|never| match never {}))
     }
 }
 
@@ -76,9 +188,30 @@ pub struct McpContext {
     /// MCP server URL
     #[serde(skip_serializing_if = "Option::is_none")]
     pub server_url: Option<String>,
-    /// Transport type (e.g., "stdio", "http")
+    /// Transport type
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub transport: Option<String>,
+    pub transport: Option<McpTransport>,
+}
+
+/// Separator between server and tool in namespaced MCP tool names
+const MCP_NAMESPACE_SEPARATOR: &str = "__";
+
+impl McpContext {
+    /// Namespace a tool name with this server (`{server_name}__{tool}`)
+    pub fn qualified_name(&self, tool: &str) -> String {
+        format!("{}{}{}", self.server_name, MCP_NAMESPACE_SEPARATOR, tool)
+    }
+}
+
+/// Split a namespaced MCP tool name into `(server, tool)`
+///
+/// Splits at the first `__`; returns `None` if there is no separator or
+/// either side is empty.
+pub fn parse_mcp_tool_name(name: &str) -> Option<(&str, &str)> {
+    match name.split_once(MCP_NAMESPACE_SEPARATOR) {
+        Some((server, tool)) if !server.is_empty() && !tool.is_empty() => Some((server, tool)),
+        _ => None,
+    }
 }
 
 /// A tool call event
@@ -113,6 +246,10 @@ pub struct ToolCallEvent {
     /// MCP context (if this is an MCP tool)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub mcp_context: Option<McpContext>,
+
+    /// Status audit trail as (timestamp ms, status), oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub status_history: Vec<(u64, ToolCallStatus)>,
 }
 
 impl ToolCallEvent {
@@ -123,16 +260,18 @@ impl ToolCallEvent {
         message_event_id: impl Into<String>,
         tool_call: ToolCall,
     ) -> Self {
+        let timestamp_ms = now_ms();
         Self {
             event_id: generate_id(),
             session_id: session_id.into(),
             project_hash: None,
-            timestamp_ms: now_ms(),
+            timestamp_ms,
             sequence,
             message_event_id: message_event_id.into(),
             tool_call,
             status: ToolCallStatus::Pending,
             mcp_context: None,
+            status_history: vec![(timestamp_ms, ToolCallStatus::Pending)],
         }
     }
 
@@ -154,11 +293,30 @@ impl ToolCallEvent {
         self
     }
 
+    /// Transition to a new status, recording it in the status history
+    pub fn transition_to(&mut self, status: ToolCallStatus) {
+        self.status = status;
+        self.status_history.push((now_ms(), status));
+    }
+
     /// Set a specific event ID (useful for testing or migration)
     pub fn with_event_id(mut self, event_id: impl Into<String>) -> Self {
         self.event_id = event_id.into();
         self
     }
+
+    /// Serialized size of the tool call arguments in bytes
+    ///
+    /// See [`ToolCall::arguments_bytes`]; computed from the current
+    /// arguments, so it stays correct if `tool_call` is edited.
+    pub fn arguments_bytes(&self) -> usize {
+        self.tool_call.arguments_bytes()
+    }
+
+    /// Whether the arguments parse as JSON (see [`ToolCall::arguments_valid`])
+    pub fn arguments_valid(&self) -> bool {
+        self.tool_call.arguments_valid()
+    }
 }
 
 impl Event for ToolCallEvent {
@@ -182,6 +340,10 @@ impl Event for ToolCallEvent {
         self.sequence
     }
 
+    fn project_hash(&self) -> Option<&str> {
+        self.project_hash.as_deref()
+    }
+
     fn to_json(&self) -> serde_json::Value {
         serde_json::to_value(self).unwrap()
     }
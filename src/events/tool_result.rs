@@ -1,25 +1,8 @@
 //! Tool result event type
 
 use super::traits::{Event, EventType};
+use super::{generate_id, now_ms};
 use serde::{Deserialize, Serialize};
-use std::time::{SystemTime, UNIX_EPOCH};
-
-/// Generate a simple UUID-like ID
-fn generate_id() -> String {
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_nanos();
-    format!("evt_{:x}", now)
-}
-
-/// Get current timestamp in milliseconds
-fn now_ms() -> u64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_millis() as u64
-}
 
 /// Tool execution result
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -54,6 +37,20 @@ impl ToolResult {
         }
     }
 
+    /// Create a successful text result, truncating content beyond `max_bytes`
+    ///
+    /// See [`crate::truncate_with_marker`] for the truncation rules.
+    pub fn truncated(
+        tool_call_id: impl Into<String>,
+        content: impl Into<String>,
+        max_bytes: usize,
+    ) -> Self {
+        Self::success(
+            tool_call_id,
+            crate::truncate_with_marker(content.into(), max_bytes),
+        )
+    }
+
     /// Create an error result
     pub fn error(tool_call_id: impl Into<String>, error: impl Into<String>) -> Self {
         Self {
@@ -190,6 +187,10 @@ impl Event for ToolResultEvent {
         self.sequence
     }
 
+    fn project_hash(&self) -> Option<&str> {
+        self.project_hash.as_deref()
+    }
+
     fn to_json(&self) -> serde_json::Value {
         serde_json::to_value(self).unwrap()
     }
@@ -1,10 +1,14 @@
 //! Event trait definitions
 
+use super::EventEnvelope;
 use serde::{Deserialize, Serialize};
 
 /// Event type discriminator
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
-#[serde(rename_all = "snake_case")]
+///
+/// Serializes as its [`as_str`](Self::as_str) name. Unknown names
+/// deserialize as [`EventType::Custom`], so custom kinds must not reuse a
+/// built-in name.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum EventType {
     /// A message in the conversation
     Message,
@@ -16,6 +20,8 @@ pub enum EventType {
     SystemSignal,
     /// An error event
     Error,
+    /// An application-defined event kind (see [`super::CustomEvent`])
+    Custom(String),
 }
 
 impl EventType {
@@ -27,6 +33,7 @@ impl EventType {
             Self::ToolResult => "tool_result",
             Self::SystemSignal => "system_signal",
             Self::Error => "error",
+            Self::Custom(kind) => kind,
         }
     }
 }
@@ -37,6 +44,39 @@ impl std::fmt::Display for EventType {
     }
 }
 
+impl Serialize for EventType {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for EventType {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ok(name.parse().unwrap_or(Self::Custom(name)))
+    }
+}
+
+impl std::str::FromStr for EventType {
+    type Err = crate::UmfError;
+
+    /// Parse a built-in snake_case event type name (inverse of
+    /// [`EventType::as_str`]); custom kinds are rejected
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "message" => Ok(Self::Message),
+            "tool_call" => Ok(Self::ToolCall),
+            "tool_result" => Ok(Self::ToolResult),
+            "system_signal" => Ok(Self::SystemSignal),
+            "error" => Ok(Self::Error),
+            other => Err(crate::UmfError::InvalidInput(format!(
+                "unknown event type `{}`",
+                other
+            ))),
+        }
+    }
+}
+
 /// Base event trait for all conversation events
 pub trait Event: Send + Sync {
     /// Get the unique event ID
@@ -54,6 +94,24 @@ pub trait Event: Send + Sync {
     /// Get the sequence number (for ordering within session)
     fn sequence(&self) -> u32;
 
+    /// Get the project hash (for storage routing), if any
+    fn project_hash(&self) -> Option<&str> {
+        None
+    }
+
     /// Serialize to JSON value
     fn to_json(&self) -> serde_json::Value;
+
+    /// Wrap this event in an envelope for storage
+    fn to_envelope(&self) -> EventEnvelope {
+        EventEnvelope {
+            event_id: self.event_id().to_string(),
+            event_type: self.event_type(),
+            session_id: self.session_id().to_string(),
+            project_hash: self.project_hash().map(str::to_string),
+            timestamp_ms: self.timestamp_ms(),
+            sequence: self.sequence(),
+            payload: self.to_json(),
+        }
+    }
 }
@@ -0,0 +1,91 @@
+//! Grouping of event logs into conversation turns
+
+use super::{EventEnvelope, EventType, MessageEvent, ToolCallEvent, ToolResultEvent};
+use crate::MessageRole;
+
+/// A logical conversation turn
+///
+/// A user prompt with the assistant response and the tool calls and results
+/// made while producing it.
+#[derive(Debug, Clone, Default)]
+pub struct Turn {
+    /// The user prompt (absent for turns not started by a user message)
+    pub user: Option<MessageEvent>,
+    /// The assistant response
+    pub assistant: Option<MessageEvent>,
+    /// Tool calls made by the assistant response
+    pub tool_calls: Vec<ToolCallEvent>,
+    /// Results of those tool calls
+    pub tool_results: Vec<ToolResultEvent>,
+}
+
+/// Group an event log into turns, in log order
+///
+/// Each user message starts a new turn; the next assistant message completes
+/// it, and a further assistant message starts a turn of its own. Tool call
+/// events join the turn whose assistant message they reference
+/// (`message_event_id`) and tool result events the turn holding their tool
+/// call (`tool_call_event_id`); unmatched references fall back to the
+/// current turn. System and tool role messages, other event types and
+/// payloads that fail to parse are skipped.
+pub fn group_into_turns(envelopes: &[EventEnvelope]) -> Vec<Turn> {
+    let mut turns: Vec<Turn> = Vec::new();
+
+    for envelope in envelopes {
+        match envelope.event_type {
+            EventType::Message => {
+                let Some(event) = envelope.as_message_event() else {
+                    continue;
+                };
+                match event.message.role {
+                    MessageRole::User => turns.push(Turn {
+                        user: Some(event),
+                        ..Turn::default()
+                    }),
+                    MessageRole::Assistant => match turns.last_mut() {
+                        Some(turn) if turn.assistant.is_none() => turn.assistant = Some(event),
+                        _ => turns.push(Turn {
+                            assistant: Some(event),
+                            ..Turn::default()
+                        }),
+                    },
+                    MessageRole::System | MessageRole::Tool => {}
+                }
+            }
+            EventType::ToolCall => {
+                let Some(event) = envelope.as_tool_call_event() else {
+                    continue;
+                };
+                let index = turns.iter().rposition(|turn| {
+                    turn.assistant
+                        .as_ref()
+                        .is_some_and(|a| a.event_id == event.message_event_id)
+                });
+                current_or(&mut turns, index).tool_calls.push(event);
+            }
+            EventType::ToolResult => {
+                let Some(event) = envelope.as_tool_result_event() else {
+                    continue;
+                };
+                let index = turns.iter().rposition(|turn| {
+                    turn.tool_calls
+                        .iter()
+                        .any(|call| call.event_id == event.tool_call_event_id)
+                });
+                current_or(&mut turns, index).tool_results.push(event);
+            }
+            _ => {}
+        }
+    }
+
+    turns
+}
+
+/// Get the turn at `index`, or the current (last) turn, creating one if needed
+fn current_or(turns: &mut Vec<Turn>, index: Option<usize>) -> &mut Turn {
+    if turns.is_empty() {
+        turns.push(Turn::default());
+    }
+    let last = turns.len() - 1;
+    &mut turns[index.unwrap_or(last)]
+}
@@ -0,0 +1,60 @@
+//! Append-only JSONL event log writer
+
+use super::EventEnvelope;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Buffered, append-only writer of [`EventEnvelope`] JSONL lines
+///
+/// Centralizes the write path of an event log: events are written one line
+/// each, in the order they are appended. Writes are buffered; call
+/// [`flush`](Self::flush) to hand them to the OS or
+/// [`flush_sync`](Self::flush_sync) to also fsync them to disk. The buffer is
+/// flushed (without fsync) when the writer is dropped.
+#[derive(Debug)]
+pub struct EventLogWriter {
+    writer: BufWriter<File>,
+    events_written: usize,
+}
+
+impl EventLogWriter {
+    /// Open `path` for appending, creating it if needed
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::new(file))
+    }
+
+    /// Wrap an already opened file
+    pub fn new(file: File) -> Self {
+        Self {
+            writer: BufWriter::new(file),
+            events_written: 0,
+        }
+    }
+
+    /// Append an event as one JSON line
+    pub fn append(&mut self, envelope: &EventEnvelope) -> io::Result<()> {
+        let mut line = envelope.to_json_line();
+        line.push('\n');
+        self.writer.write_all(line.as_bytes())?;
+        self.events_written += 1;
+        Ok(())
+    }
+
+    /// Flush buffered events to the file
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Flush buffered events and fsync the file data to disk
+    pub fn flush_sync(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_data()
+    }
+
+    /// Number of events appended through this writer
+    pub fn events_written(&self) -> usize {
+        self.events_written
+    }
+}
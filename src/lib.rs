@@ -43,22 +43,154 @@ pub use chatml::{ChatMLFormatter, ChatMLMessage, MessageRole as ChatMLMessageRol
 #[cfg(feature = "streaming")]
 pub mod streaming;
 #[cfg(feature = "streaming")]
-pub use streaming::{AccumulatedResponse, StreamChunk, StreamingAccumulator};
+pub use streaming::{
+    accumulate_anthropic_stream, AccumulatedResponse, AnthropicStreamAccumulator, StreamChunk,
+    StreamingAccumulator, ToolArgError,
+};
+
+// ============================================================================
+// Conversations
+// ============================================================================
+
+pub mod conversation;
+pub use conversation::{truncate_conversation, Conversation, RepairMode};
+#[cfg(feature = "json-patch")]
+pub use conversation::PatchError;
+
+// ============================================================================
+// Operation Dispatch
+// ============================================================================
+
+pub mod operations;
+pub use operations::{apply_operation, Operation, OperationCache};
+
+// ============================================================================
+// Provider Formats
+// ============================================================================
+
+pub mod providers;
+pub use providers::{
+    detect_provider_format, from_anthropic_message, from_anthropic_request, from_gemini_request,
+    from_openai_message, from_openai_messages, to_anthropic_message,
+    to_anthropic_message_with_hook, to_anthropic_request, to_anthropic_request_with_hook,
+    to_anthropic_request_with_policy, to_gemini_request, to_gemini_request_with_hook,
+    to_openai_message, to_openai_message_with_hook, AnthropicEmptyPolicy, ConversionError,
+    Provider, ProviderFormat,
+};
+
+// ============================================================================
+// Token Counting
+// ============================================================================
+
+pub mod tokens;
+pub use tokens::{estimate_cost, Pricing, TokenCounter};
 
 // ============================================================================
-// Events Support (for conversation tracking and storage)
+// Events Support (for conversation tracking and storage, optional feature)
 // ============================================================================
 
+#[cfg(feature = "events")]
 pub mod events;
+#[cfg(feature = "events")]
 pub use events::{
-    Event, EventEnvelope, EventType, McpContext, MessageEvent, ModelInfo, ToolCall as EventToolCall,
-    ToolCallEvent, ToolCallStatus, ToolResult, ToolResultEvent,
+    filter_time_range, group_into_turns, parse_mcp_tool_name, project_hash, summarize,
+    CustomEvent, Event, EventEnvelope, EventLogWriter, EventType, LogSummary, McpContext,
+    McpTransport, MessageEvent, ModelInfo, ToolCall as EventToolCall, ToolCallEvent,
+    ToolCallStatus, ToolResult, ToolResultEvent, Turn,
 };
 
+// ============================================================================
+// Errors
+// ============================================================================
+
+/// Error type for fallible UMF operations
+#[derive(Debug)]
+pub enum UmfError {
+    /// Input did not have the expected shape
+    InvalidInput(String),
+    /// JSON serialization or deserialization failed
+    Json(serde_json::Error),
+    /// Tokenizer could not be loaded
+    Tokenizer(String),
+    /// Provider JSON could not be converted
+    Conversion(ConversionError),
+    /// A JSONL line could not be parsed
+    JsonLine {
+        /// Underlying parse error
+        error: serde_json::Error,
+        /// Truncated copy of the offending line
+        snippet: String,
+    },
+    /// Reading or writing a file failed
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for UmfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidInput(msg) => write!(f, "invalid input: {}", msg),
+            Self::Json(err) => write!(f, "json error: {}", err),
+            Self::Tokenizer(msg) => write!(f, "tokenizer error: {}", msg),
+            Self::Conversion(err) => write!(f, "conversion error: {}", err),
+            Self::JsonLine { error, snippet } => {
+                write!(f, "invalid json line ({}): {}", error, snippet)
+            }
+            Self::Io(err) => write!(f, "io error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for UmfError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Json(err) | Self::JsonLine { error: err, .. } => Some(err),
+            Self::Conversion(err) => Some(err),
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for UmfError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Json(err)
+    }
+}
+
+impl From<std::io::Error> for UmfError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<ConversionError> for UmfError {
+    fn from(err: ConversionError) -> Self {
+        Self::Conversion(err)
+    }
+}
+
 // ============================================================================
 // Core Message Types
 // ============================================================================
 
+/// Metadata key describing the format of a message's text content
+pub const CONTENT_TYPE_KEY: &str = "content_type";
+
+/// [`CONTENT_TYPE_KEY`] value marking content as serialized JSON
+pub const JSON_CONTENT_TYPE: &str = "application/json";
+
+/// Metadata key marking a tool result message as an error (value `"true"`)
+pub const IS_ERROR_KEY: &str = "is_error";
+
+/// Prefix of error tool results in [`InternalMessage::to_text`]
+const ERROR_MARKER: &str = "[error]";
+
+/// Provider of thinking blocks that cannot be sent back to any provider
+///
+/// Used for reasoning streamed by OpenAI-style models; see
+/// [`ContentBlock::thinking`].
+pub const REASONING_PROVIDER: &str = "reasoning";
+
 /// A message in the internal format
 ///
 /// This represents a single message in a conversation, with role, content,
@@ -78,6 +210,33 @@ pub struct InternalMessage {
     /// Tool name for tool messages (required when role is "tool")
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
+    /// Creation time (Unix milliseconds) for client-side ordering
+    ///
+    /// Not sent to providers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<u64>,
+    /// Routing hints for choosing how to answer this message
+    ///
+    /// Not sent to providers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hints: Option<MessageHints>,
+}
+
+/// Typed model routing hints attached to a message
+///
+/// Requests made before generation (e.g. by a router picking a model), as
+/// opposed to the parameters recorded for a response after the fact.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MessageHints {
+    /// Requested reasoning effort (e.g. "low", "medium", "high")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_effort: Option<String>,
+    /// Preferred model or model tier
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preferred_model: Option<String>,
+    /// Preferred sampling temperature
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
 }
 
 impl InternalMessage {
@@ -89,6 +248,8 @@ impl InternalMessage {
             metadata: HashMap::new(),
             tool_call_id: None,
             name: None,
+            created_at: None,
+            hints: None,
         }
     }
 
@@ -100,6 +261,8 @@ impl InternalMessage {
             metadata: HashMap::new(),
             tool_call_id: None,
             name: None,
+            created_at: None,
+            hints: None,
         }
     }
 
@@ -111,6 +274,8 @@ impl InternalMessage {
             metadata: HashMap::new(),
             tool_call_id: None,
             name: None,
+            created_at: None,
+            hints: None,
         }
     }
 
@@ -122,6 +287,8 @@ impl InternalMessage {
             metadata: HashMap::new(),
             tool_call_id: None,
             name: None,
+            created_at: None,
+            hints: None,
         }
     }
 
@@ -137,7 +304,83 @@ impl InternalMessage {
             metadata: HashMap::new(),
             tool_call_id: Some(tool_call_id.into()),
             name: Some(name.into()),
+            created_at: None,
+            hints: None,
+        }
+    }
+
+    /// Create a successful tool result message
+    ///
+    /// Same as [`tool_result`](Self::tool_result); pairs with
+    /// [`tool_error`](Self::tool_error).
+    pub fn tool_success(
+        tool_call_id: impl Into<String>,
+        name: impl Into<String>,
+        content: impl Into<String>,
+    ) -> Self {
+        Self::tool_result(tool_call_id, name, content)
+    }
+
+    /// Create a tool result message reporting a failed tool call
+    ///
+    /// Sets the [`IS_ERROR_KEY`] metadata entry; [`to_text`](Self::to_text)
+    /// prefixes the content with `[error]`.
+    pub fn tool_error(
+        tool_call_id: impl Into<String>,
+        name: impl Into<String>,
+        content: impl Into<String>,
+    ) -> Self {
+        let mut msg = Self::tool_result(tool_call_id, name, content);
+        msg.metadata
+            .insert(IS_ERROR_KEY.to_string(), "true".to_string());
+        msg
+    }
+
+    /// Whether this is an error tool result
+    ///
+    /// True if the [`IS_ERROR_KEY`] metadata entry is `"true"` or any tool
+    /// result block is marked as an error.
+    pub fn is_error_result(&self) -> bool {
+        self.metadata.get(IS_ERROR_KEY).map(String::as_str) == Some("true")
+            || self
+                .blocks()
+                .unwrap_or_default()
+                .iter()
+                .any(ContentBlock::is_error_result)
+    }
+
+    /// Create a tool result message carrying structured JSON content
+    ///
+    /// The value is stored serialized, with the `content_type` metadata entry
+    /// set to `application/json` so it can be recovered with
+    /// [`tool_result_json_value`](Self::tool_result_json_value).
+    pub fn tool_result_json(
+        tool_call_id: impl Into<String>,
+        name: impl Into<String>,
+        value: serde_json::Value,
+    ) -> Self {
+        let mut msg = Self::tool_result(tool_call_id, name, value.to_string());
+        msg.metadata
+            .insert(CONTENT_TYPE_KEY.to_string(), JSON_CONTENT_TYPE.to_string());
+        msg
+    }
+
+    /// Whether the content is marked as serialized JSON
+    pub fn is_json_content(&self) -> bool {
+        self.metadata.get(CONTENT_TYPE_KEY).map(String::as_str) == Some(JSON_CONTENT_TYPE)
+    }
+
+    /// Get the structured content of a JSON tool result
+    ///
+    /// Returns `None` unless the message was created with
+    /// [`tool_result_json`](Self::tool_result_json) (or carries the same
+    /// metadata flag) and its text parses as JSON.
+    pub fn tool_result_json_value(&self) -> Option<serde_json::Value> {
+        if !self.is_json_content() {
+            return None;
         }
+        let text = self.text_blocks().collect::<Vec<_>>().join("\n");
+        serde_json::from_str(&text).ok()
     }
 
     /// Create an assistant message with tool calls
@@ -151,9 +394,69 @@ impl InternalMessage {
             metadata: HashMap::new(),
             tool_call_id: None,
             name: None,
+            created_at: None,
+            hints: None,
+        }
+    }
+
+    /// Create an assistant message with tool calls, checking the block types
+    ///
+    /// Like [`assistant_with_tools`](Self::assistant_with_tools), but fails
+    /// with [`UmfError::InvalidInput`] if any of `tool_calls` is not a
+    /// [`ContentBlock::ToolUse`] block.
+    pub fn try_assistant_with_tools(
+        content: impl Into<String>,
+        tool_calls: Vec<ContentBlock>,
+    ) -> Result<Self, UmfError> {
+        if let Some(index) = tool_calls.iter().position(|b| b.as_tool_use().is_none()) {
+            return Err(UmfError::InvalidInput(format!(
+                "tool_calls[{}] is not a tool use block",
+                index
+            )));
+        }
+        Ok(Self::assistant_with_tools(content, tool_calls))
+    }
+
+    /// Create an assistant message from a fully-formed block list
+    ///
+    /// Unlike [`assistant_with_tools`](Self::assistant_with_tools), no leading
+    /// text block is assumed; blocks are kept exactly in the given order.
+    pub fn assistant_blocks(blocks: Vec<ContentBlock>) -> Self {
+        Self {
+            role: MessageRole::Assistant,
+            content: MessageContent::Blocks(blocks),
+            metadata: HashMap::new(),
+            tool_call_id: None,
+            name: None,
+            created_at: None,
+            hints: None,
         }
     }
 
+    /// Set the participant or tool name
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Clear the name
+    pub fn without_name(mut self) -> Self {
+        self.name = None;
+        self
+    }
+
+    /// Set the routing hints
+    pub fn with_hints(mut self, hints: MessageHints) -> Self {
+        self.hints = Some(hints);
+        self
+    }
+
+    /// Set the creation time (Unix milliseconds)
+    pub fn with_created_at(mut self, created_at: u64) -> Self {
+        self.created_at = Some(created_at);
+        self
+    }
+
     /// Get text content if this is a text message
     pub fn text(&self) -> Option<&str> {
         match &self.content {
@@ -169,17 +472,400 @@ impl InternalMessage {
             _ => None,
         }
     }
+
+    /// Iterate over borrowed text pieces without allocating
+    ///
+    /// Yields the whole text for text messages, and for block-based messages
+    /// the pieces [`to_text`](Self::to_text) joins (in order): the text of
+    /// every text block and tool result block, the name (or file ID) of
+    /// every attachment and the transcript of audio output.
+    pub fn text_blocks(&self) -> impl Iterator<Item = &str> {
+        let (text, blocks): (Option<&str>, &[ContentBlock]) = match &self.content {
+            MessageContent::Text(text) => (Some(text), &[]),
+            MessageContent::Blocks(blocks) => (None, blocks),
+        };
+        text.into_iter().chain(blocks.iter().filter_map(|block| match block {
+            ContentBlock::Text { text, .. } => Some(text.as_str()),
+            ContentBlock::ToolResult { content, .. } => Some(content.as_str()),
+            ContentBlock::Attachment { file_id, name, .. } => {
+                Some(name.as_deref().unwrap_or(file_id))
+            }
+            ContentBlock::AudioOutput { transcript, .. } => transcript.as_deref(),
+            _ => None,
+        }))
+    }
+
+    /// Get all text content joined with newlines
+    ///
+    /// Attachments are rendered as their name, or file ID if unnamed, and
+    /// audio output as its transcript. Error tool results (see
+    /// [`is_error_result`](Self::is_error_result)) are prefixed with
+    /// `[error]`, for models that read tool output as plain text.
+    pub fn to_text(&self) -> String {
+        self.render_text(false)
+    }
+
+    /// Stable hash of the message content, including tool call ids
+    ///
+    /// Covers role, content, name and tool call ID; metadata is excluded.
+    /// Computed from canonical (key-sorted) JSON with SHA-256, so the value
+    /// is stable across runs and can be persisted.
+    pub fn content_hash(&self) -> u64 {
+        hash_json(&serde_json::json!({
+            "role": self.role,
+            "content": self.content,
+            "name": self.name,
+            "tool_call_id": self.tool_call_id,
+        }))
+    }
+
+    /// Compare with another message, ignoring metadata
+    ///
+    /// Compares role, content, name and tool call ID, i.e. exactly the
+    /// fields covered by [`content_hash`](Self::content_hash): messages equal
+    /// under this method have the same content hash. Metadata (including the
+    /// [`IS_ERROR_KEY`] flag), hints and `created_at` are not compared.
+    pub fn eq_ignoring_metadata(&self, other: &Self) -> bool {
+        self.role == other.role
+            && self.content == other.content
+            && self.name == other.name
+            && self.tool_call_id == other.tool_call_id
+    }
+
+    /// Stable hash of the message meaning, ignoring tool call ids
+    ///
+    /// Like [`content_hash`](Self::content_hash), but tool use ids, tool
+    /// result ids and `tool_call_id` are left out, so messages that differ
+    /// only in generated ids share a fingerprint. A text message and a block
+    /// message with a single identical text block also share one.
+    pub fn semantic_fingerprint(&self) -> u64 {
+        let single;
+        let blocks = match &self.content {
+            MessageContent::Text(text) => {
+                single = [ContentBlock::text(text.clone())];
+                &single[..]
+            }
+            MessageContent::Blocks(blocks) => blocks.as_slice(),
+        };
+        let blocks: Vec<serde_json::Value> = blocks
+            .iter()
+            .map(|block| match block {
+                ContentBlock::Text { text, .. } => serde_json::json!(["text", text]),
+                ContentBlock::ToolUse { name, input, .. } => {
+                    serde_json::json!(["tool_use", name, input])
+                }
+                ContentBlock::ToolResult { content, .. } => {
+                    serde_json::json!(["tool_result", content])
+                }
+                ContentBlock::Image { source, .. } => serde_json::json!(["image", source]),
+                ContentBlock::Attachment { file_id, .. } => {
+                    serde_json::json!(["attachment", file_id])
+                }
+                ContentBlock::AudioOutput { id, transcript, .. } => {
+                    serde_json::json!(["audio_output", id, transcript])
+                }
+                ContentBlock::Raw { provider, value } => {
+                    serde_json::json!(["raw", provider, value])
+                }
+            })
+            .collect();
+
+        hash_json(&serde_json::json!({
+            "role": self.role,
+            "blocks": blocks,
+            "name": self.name,
+        }))
+    }
+
+    /// Count characters of the text pieces of the message
+    ///
+    /// Counts Unicode scalar values over [`text_blocks`](Self::text_blocks);
+    /// images, tool use inputs and the separators of [`to_text`](Self::to_text)
+    /// are not counted.
+    pub fn char_count(&self) -> usize {
+        self.text_blocks().map(|text| text.chars().count()).sum()
+    }
+
+    /// Count whitespace-separated words over [`text_blocks`](Self::text_blocks)
+    pub fn word_count(&self) -> usize {
+        self.text_blocks()
+            .map(|text| text.split_whitespace().count())
+            .sum()
+    }
+
+    /// Count image blocks in the message
+    pub fn image_count(&self) -> usize {
+        self.blocks()
+            .map_or(0, |blocks| blocks.iter().filter(|b| b.as_image().is_some()).count())
+    }
+
+    /// Get all text content with a placeholder for each image
+    ///
+    /// Like [`to_text`](Self::to_text) (including the `[error]` marker), but
+    /// each image block is rendered in place as `[image i/n]`, so image-only
+    /// messages do not look empty.
+    pub fn to_text_with_placeholders(&self) -> String {
+        self.render_text(true)
+    }
+
+    fn render_text(&self, placeholders: bool) -> String {
+        let error_message = self.metadata.get(IS_ERROR_KEY).map(String::as_str) == Some("true");
+        let blocks = match &self.content {
+            MessageContent::Text(text) if error_message => {
+                return format!("{} {}", ERROR_MARKER, text)
+            }
+            MessageContent::Text(text) => return text.clone(),
+            MessageContent::Blocks(blocks) => blocks,
+        };
+
+        let total = self.image_count();
+        let mut image_index = 0;
+        let mut parts = Vec::new();
+        for block in blocks {
+            match block {
+                ContentBlock::Text { text, .. } => parts.push(text.clone()),
+                ContentBlock::ToolResult {
+                    content, is_error, ..
+                } if *is_error || error_message => {
+                    parts.push(format!("{} {}", ERROR_MARKER, content))
+                }
+                ContentBlock::ToolResult { content, .. } => parts.push(content.clone()),
+                ContentBlock::Image { .. } if placeholders => {
+                    image_index += 1;
+                    parts.push(format!("[image {}/{}]", image_index, total));
+                }
+                ContentBlock::Attachment { file_id, name, .. } => {
+                    parts.push(name.clone().unwrap_or_else(|| file_id.clone()))
+                }
+                ContentBlock::AudioOutput {
+                    transcript: Some(transcript),
+                    ..
+                } => parts.push(transcript.clone()),
+                _ => {}
+            }
+        }
+        parts.join("\n")
+    }
+
+    /// Render a compact one-line summary, bounded to `max_chars` characters
+    ///
+    /// Shows the role, then text and tool result content with whitespace
+    /// collapsed, `[image]` for images (never the image data), `[attachment
+    /// name]` for attachments, `[audio]` for audio output, `tool(name)`
+    /// for tool calls and `[raw provider]` for raw blocks. Truncated output
+    /// ends with `…`. Safe to log by default.
+    pub fn preview(&self, max_chars: usize) -> String {
+        let mut parts = vec![format!("{}:", self.role.as_str())];
+        match &self.content {
+            MessageContent::Text(text) => parts.push(text.clone()),
+            MessageContent::Blocks(blocks) => {
+                for block in blocks {
+                    parts.push(match block {
+                        ContentBlock::Text { text, .. } => text.clone(),
+                        ContentBlock::ToolResult { content, .. } => content.clone(),
+                        ContentBlock::Image { .. } => "[image]".to_string(),
+                        ContentBlock::Attachment { file_id, name, .. } => {
+                            format!("[attachment {}]", name.as_ref().unwrap_or(file_id))
+                        }
+                        ContentBlock::AudioOutput { .. } => "[audio]".to_string(),
+                        ContentBlock::ToolUse { name, .. } => format!("tool({})", name),
+                        ContentBlock::Raw { provider, .. } => format!("[raw {}]", provider),
+                    });
+                }
+            }
+        }
+
+        let line = parts
+            .iter()
+            .flat_map(|part| part.split_whitespace())
+            .collect::<Vec<_>>()
+            .join(" ");
+        if line.chars().count() <= max_chars {
+            return line;
+        }
+        let mut truncated: String = line.chars().take(max_chars.saturating_sub(1)).collect();
+        if max_chars > 0 {
+            truncated.push('…');
+        }
+        truncated
+    }
+
+    /// Apply `f` to all text content, returning the transformed message
+    ///
+    /// Text messages are mapped directly; block messages map each block with
+    /// [`ContentBlock::map_text`]. Role, name, tool call ID and metadata are
+    /// preserved.
+    pub fn map_text<F: FnMut(&str) -> String>(&self, mut f: F) -> InternalMessage {
+        let content = match &self.content {
+            MessageContent::Text(text) => MessageContent::Text(f(text)),
+            MessageContent::Blocks(blocks) => {
+                MessageContent::Blocks(blocks.iter().map(|b| b.map_text(&mut f)).collect())
+            }
+        };
+
+        InternalMessage {
+            content,
+            ..self.clone()
+        }
+    }
+
+    /// Replace every match of `patterns` with `[REDACTED]`
+    ///
+    /// Applies to text, text blocks, tool result content and string values
+    /// (at any depth) inside tool use inputs.
+    pub fn redact(&mut self, patterns: &[regex::Regex]) {
+        match &mut self.content {
+            MessageContent::Text(text) => redact_str(text, patterns),
+            MessageContent::Blocks(blocks) => {
+                for block in blocks {
+                    match block {
+                        ContentBlock::Text { text, .. } => redact_str(text, patterns),
+                        ContentBlock::ToolResult { content, .. } => redact_str(content, patterns),
+                        ContentBlock::ToolUse { input, .. } => redact_value(input, patterns),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    /// Remove text blocks that are empty or whitespace-only
+    ///
+    /// Tool use, tool result, image and raw blocks are always kept. If a
+    /// single text block without extensions remains, the content collapses
+    /// back to [`MessageContent::Text`].
+    pub fn prune_empty_blocks(&mut self) {
+        let MessageContent::Blocks(blocks) = &mut self.content else {
+            return;
+        };
+        blocks.retain(|block| !block.as_text().is_some_and(|text| text.trim().is_empty()));
+
+        if let [ContentBlock::Text { text, extensions }] = blocks.as_mut_slice() {
+            if extensions.is_empty() {
+                self.content = MessageContent::Text(std::mem::take(text));
+            }
+        }
+    }
+
+    /// Convert to a text-only message for providers without block support
+    ///
+    /// Block content is joined with newlines as in [`to_text`](Self::to_text).
+    /// When `render_tool_calls` is set, tool use blocks are kept as a readable
+    /// `[tool: name(args)]` note in place; other non-text blocks are dropped.
+    /// Role, name, tool call ID and metadata are preserved.
+    pub fn flatten(&self, render_tool_calls: bool) -> InternalMessage {
+        let text = match &self.content {
+            MessageContent::Text(text) => text.clone(),
+            MessageContent::Blocks(blocks) => blocks
+                .iter()
+                .filter_map(|block| match block {
+                    ContentBlock::Text { text, .. } => Some(text.clone()),
+                    ContentBlock::ToolResult { content, .. } => Some(content.clone()),
+                    ContentBlock::ToolUse { name, input, .. } if render_tool_calls => {
+                        Some(format!("[tool: {}({})]", name, input))
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        };
+
+        InternalMessage {
+            content: MessageContent::Text(text),
+            ..self.clone()
+        }
+    }
+
+    /// Parse a single message in the wire format of `provider`
+    ///
+    /// Dispatches on [`Provider::wire_format`] to [`from_openai_message`],
+    /// [`from_anthropic_message`] or the Gemini parser.
+    pub fn from_provider(
+        provider: Provider,
+        value: &serde_json::Value,
+    ) -> Result<InternalMessage, ConversionError> {
+        providers::from_provider_message(&provider, value)
+    }
+
+    /// Convert into the wire format of `provider`
+    ///
+    /// Counterpart of [`from_provider`](Self::from_provider).
+    pub fn to_provider(&self, provider: Provider) -> serde_json::Value {
+        providers::to_provider_message(&provider, self)
+    }
+
+    /// Serialize with metadata entries merged into the top-level object
+    ///
+    /// For consumers that expect provider metadata alongside `role` and
+    /// `content` rather than nested under `metadata`. Fails if a metadata key
+    /// collides with a reserved message field.
+    pub fn to_flat_json(&self) -> Result<serde_json::Value, UmfError> {
+        const RESERVED: [&str; 7] = [
+            "role",
+            "content",
+            "name",
+            "tool_call_id",
+            "metadata",
+            "created_at",
+            "hints",
+        ];
+
+        let mut value = serde_json::to_value(self)?;
+        let root = value
+            .as_object_mut()
+            .expect("InternalMessage serializes to an object");
+        root.remove("metadata");
+
+        for (key, val) in &self.metadata {
+            if RESERVED.contains(&key.as_str()) {
+                return Err(UmfError::InvalidInput(format!(
+                    "metadata key `{}` collides with a reserved message field",
+                    key
+                )));
+            }
+            root.insert(key.clone(), serde_json::Value::String(val.clone()));
+        }
+
+        Ok(value)
+    }
+
+    /// Keep only raw blocks belonging to `provider`
+    ///
+    /// Raw blocks for any other provider are removed and reported to
+    /// `on_drop` with their provider and value. The provider converters drop
+    /// the same blocks; their `_with_hook` variants (e.g.
+    /// [`to_openai_message_with_hook`]) report them without modifying the
+    /// message.
+    pub fn retain_raw_blocks_for<F>(&mut self, provider: &str, mut on_drop: F)
+    where
+        F: FnMut(&str, &serde_json::Value),
+    {
+        if let MessageContent::Blocks(blocks) = &mut self.content {
+            blocks.retain(|block| match block.as_raw() {
+                Some((p, value)) if p != provider => {
+                    on_drop(p, value);
+                    false
+                }
+                _ => true,
+            });
+        }
+    }
 }
 
 /// Message role in a conversation
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+///
+/// Deserialization also accepts common provider aliases: `human` for
+/// [`User`](Self::User), and `ai`/`model` for [`Assistant`](Self::Assistant).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum MessageRole {
     /// System-level instructions
     System,
     /// User input
+    #[serde(alias = "human")]
     User,
     /// Assistant response
+    #[serde(alias = "ai", alias = "model")]
     Assistant,
     /// Tool execution result
     Tool,
@@ -203,8 +889,23 @@ impl std::fmt::Display for MessageRole {
     }
 }
 
+impl std::str::FromStr for MessageRole {
+    type Err = UmfError;
+
+    /// Parse a canonical lowercase role name (inverse of [`MessageRole::as_str`])
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "system" => Ok(Self::System),
+            "user" => Ok(Self::User),
+            "assistant" => Ok(Self::Assistant),
+            "tool" => Ok(Self::Tool),
+            other => Err(UmfError::InvalidInput(format!("unknown role `{}`", other))),
+        }
+    }
+}
+
 /// Message content (text or structured blocks)
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum MessageContent {
     /// Simple text content
@@ -240,7 +941,7 @@ impl MessageContent {
 // ============================================================================
 
 /// Image source for image blocks
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum ImageSource {
     /// Base64-encoded image data
@@ -257,22 +958,107 @@ pub enum ImageSource {
     },
 }
 
+impl ImageSource {
+    /// Identify the image content for deduplication
+    ///
+    /// For base64 sources, the data is decoded (ignoring whitespace and
+    /// padding differences) and the raw bytes are hashed, so re-encodings of
+    /// the same image compare equal; `None` if the data is not valid base64.
+    /// For URL sources, the URL itself is hashed. Returns the first 16 hex
+    /// characters of the SHA-256 digest.
+    pub fn content_id(&self) -> Option<String> {
+        use base64::engine::{general_purpose, DecodePaddingMode, GeneralPurpose};
+        use base64::Engine;
+        use sha2::{Digest, Sha256};
+
+        const LENIENT: GeneralPurpose = GeneralPurpose::new(
+            &base64::alphabet::STANDARD,
+            general_purpose::GeneralPurposeConfig::new()
+                .with_decode_padding_mode(DecodePaddingMode::Indifferent),
+        );
+
+        let digest = match self {
+            Self::Base64 { data, .. } => {
+                let compact: String = data.chars().filter(|c| !c.is_whitespace()).collect();
+                Sha256::digest(LENIENT.decode(compact).ok()?)
+            }
+            Self::Url { url } => Sha256::digest(url.as_bytes()),
+        };
+        let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+        Some(hex[..16].to_string())
+    }
+}
+
+/// Audio source for audio output blocks
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AudioSource {
+    /// Base64-encoded audio data
+    Base64 {
+        /// MIME type of the audio (e.g., "audio/wav"), if known
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        media_type: Option<String>,
+        /// Base64-encoded audio data
+        data: String,
+    },
+    /// URL to an audio file
+    Url {
+        /// URL of the audio
+        url: String,
+    },
+}
+
 /// A content block within a message
 ///
 /// This follows the Universal Message Format specification exactly.
 /// Each variant serializes to JSON with a "type" field and flattened fields.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum ContentBlock {
     /// Text content
     Text {
         /// The text content
         text: String,
+        /// Provider-specific extra fields (e.g. `cache_control`), kept verbatim
+        #[serde(flatten)]
+        extensions: HashMap<String, serde_json::Value>,
     },
     /// Image content
     Image {
         /// The image source
         source: ImageSource,
+        /// Provider-specific extra fields (e.g. `cache_control`), kept verbatim
+        #[serde(flatten)]
+        extensions: HashMap<String, serde_json::Value>,
+    },
+    /// Reference to a pre-uploaded file (e.g. an OpenAI Files API id)
+    Attachment {
+        /// Provider file identifier or URI
+        file_id: String,
+        /// MIME type of the file, if known
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        media_type: Option<String>,
+        /// Display name of the file, if known
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+        /// Provider-specific extra fields (e.g. `cache_control`), kept verbatim
+        #[serde(flatten)]
+        extensions: HashMap<String, serde_json::Value>,
+    },
+    /// Audio produced by the model (e.g. OpenAI `message.audio`)
+    AudioOutput {
+        /// Provider audio identifier, used to refer back to the audio
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        /// Transcript of the audio
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        transcript: Option<String>,
+        /// The audio data
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        source: Option<AudioSource>,
+        /// Provider-specific extra fields (e.g. `expires_at`), kept verbatim
+        #[serde(flatten)]
+        extensions: HashMap<String, serde_json::Value>,
     },
     /// Tool use (function call)
     ToolUse {
@@ -282,6 +1068,9 @@ pub enum ContentBlock {
         name: String,
         /// Input arguments for the tool
         input: serde_json::Value,
+        /// Provider-specific extra fields (e.g. `cache_control`), kept verbatim
+        #[serde(flatten)]
+        extensions: HashMap<String, serde_json::Value>,
     },
     /// Tool result (function response)
     ToolResult {
@@ -289,18 +1078,78 @@ pub enum ContentBlock {
         tool_use_id: String,
         /// The result content
         content: String,
+        /// Whether the tool call failed
+        #[serde(default, skip_serializing_if = "is_false")]
+        is_error: bool,
+        /// Provider-specific extra fields (e.g. `cache_control`), kept verbatim
+        #[serde(flatten)]
+        extensions: HashMap<String, serde_json::Value>,
+    },
+    /// Provider-native block passed through untouched
+    ///
+    /// Escape hatch for blocks this crate does not model (e.g. Anthropic
+    /// `server_tool_use`). Only emitted to the provider it came from.
+    Raw {
+        /// Provider the block belongs to (e.g. "anthropic")
+        provider: String,
+        /// The block exactly as the provider defines it
+        value: serde_json::Value,
     },
 }
 
 impl ContentBlock {
     /// Create a text block
     pub fn text(text: impl Into<String>) -> Self {
-        Self::Text { text: text.into() }
+        Self::Text {
+            text: text.into(),
+            extensions: HashMap::new(),
+        }
     }
 
     /// Create an image block from a source
     pub fn image(source: ImageSource) -> Self {
-        Self::Image { source }
+        Self::Image {
+            source,
+            extensions: HashMap::new(),
+        }
+    }
+
+    /// Create an attachment block referencing a pre-uploaded file
+    pub fn attachment(file_id: impl Into<String>) -> Self {
+        Self::Attachment {
+            file_id: file_id.into(),
+            media_type: None,
+            name: None,
+            extensions: HashMap::new(),
+        }
+    }
+
+    /// Create an attachment block with a MIME type and display name
+    pub fn attachment_with(
+        file_id: impl Into<String>,
+        media_type: Option<String>,
+        name: Option<String>,
+    ) -> Self {
+        Self::Attachment {
+            file_id: file_id.into(),
+            media_type,
+            name,
+            extensions: HashMap::new(),
+        }
+    }
+
+    /// Create an audio output block
+    pub fn audio_output(
+        id: Option<String>,
+        transcript: Option<String>,
+        source: Option<AudioSource>,
+    ) -> Self {
+        Self::AudioOutput {
+            id,
+            transcript,
+            source,
+            extensions: HashMap::new(),
+        }
     }
 
     /// Create a tool use block
@@ -309,21 +1158,147 @@ impl ContentBlock {
             id: id.into(),
             name: name.into(),
             input,
+            extensions: HashMap::new(),
         }
     }
 
+    /// Create a tool use block with object keys of `input` sorted recursively
+    ///
+    /// Serialization is then deterministic even when serde_json's
+    /// `preserve_order` feature is enabled elsewhere in the dependency graph,
+    /// so logically equal inputs serialize identically.
+    pub fn tool_use_canonical(
+        id: impl Into<String>,
+        name: impl Into<String>,
+        input: serde_json::Value,
+    ) -> Self {
+        Self::tool_use(id, name, canonicalize_json(input))
+    }
+
     /// Create a tool result block
     pub fn tool_result(tool_use_id: impl Into<String>, content: impl Into<String>) -> Self {
         Self::ToolResult {
             tool_use_id: tool_use_id.into(),
             content: content.into(),
+            is_error: false,
+            extensions: HashMap::new(),
+        }
+    }
+
+    /// Create a successful tool result block (same as [`tool_result`](Self::tool_result))
+    pub fn tool_success(tool_use_id: impl Into<String>, content: impl Into<String>) -> Self {
+        Self::tool_result(tool_use_id, content)
+    }
+
+    /// Create a tool result block reporting a failed tool call
+    pub fn tool_error(tool_use_id: impl Into<String>, content: impl Into<String>) -> Self {
+        Self::ToolResult {
+            tool_use_id: tool_use_id.into(),
+            content: content.into(),
+            is_error: true,
+            extensions: HashMap::new(),
+        }
+    }
+
+    /// Check if this is a tool result block marked as an error
+    pub fn is_error_result(&self) -> bool {
+        matches!(self, Self::ToolResult { is_error: true, .. })
+    }
+
+    /// Create a provider-native raw block
+    pub fn raw(provider: impl Into<String>, value: serde_json::Value) -> Self {
+        Self::Raw {
+            provider: provider.into(),
+            value,
+        }
+    }
+
+    /// Create a thinking block
+    ///
+    /// Model thinking is kept as a raw block of `provider` holding
+    /// `{"type": "thinking", "thinking": ..., "signature": ...}` (the
+    /// signature only when given), the shape Anthropic uses. `provider` is
+    /// `"anthropic"` for signed Anthropic thinking, which is sent back to
+    /// Anthropic as is, and [`REASONING_PROVIDER`] for reasoning that no
+    /// provider accepts back. Read thinking of either kind with
+    /// [`as_thinking`](Self::as_thinking).
+    pub fn thinking(
+        provider: impl Into<String>,
+        thinking: impl Into<String>,
+        signature: Option<String>,
+    ) -> Self {
+        let mut value = serde_json::json!({ "type": "thinking", "thinking": thinking.into() });
+        if let Some(signature) = signature {
+            value["signature"] = serde_json::Value::String(signature);
+        }
+        Self::raw(provider, value)
+    }
+
+    /// Get the text of a thinking block (see [`thinking`](Self::thinking)),
+    /// whatever its provider
+    pub fn as_thinking(&self) -> Option<&str> {
+        match self {
+            Self::Raw { value, .. } if value["type"] == "thinking" => value["thinking"].as_str(),
+            _ => None,
+        }
+    }
+
+    /// Create a tool result block, truncating content beyond `max_bytes`
+    ///
+    /// See [`truncate_with_marker`] for the truncation rules.
+    pub fn tool_result_truncated(
+        tool_use_id: impl Into<String>,
+        content: impl Into<String>,
+        max_bytes: usize,
+    ) -> Self {
+        Self::ToolResult {
+            tool_use_id: tool_use_id.into(),
+            content: truncate_with_marker(content.into(), max_bytes),
+            is_error: false,
+            extensions: HashMap::new(),
+        }
+    }
+
+    /// Apply `f` to the text of this block, returning the transformed block
+    ///
+    /// Maps text blocks, tool result content and the `thinking` text of raw
+    /// thinking blocks. Tool use, image and other raw blocks are returned
+    /// unchanged.
+    pub fn map_text<F: FnMut(&str) -> String>(&self, mut f: F) -> ContentBlock {
+        match self {
+            Self::Text { text, extensions } => Self::Text {
+                text: f(text),
+                extensions: extensions.clone(),
+            },
+            Self::ToolResult {
+                tool_use_id,
+                content,
+                is_error,
+                extensions,
+            } => Self::ToolResult {
+                tool_use_id: tool_use_id.clone(),
+                content: f(content),
+                is_error: *is_error,
+                extensions: extensions.clone(),
+            },
+            Self::Raw { provider, value } if value["type"] == "thinking" => {
+                let mut value = value.clone();
+                if let Some(thinking) = value["thinking"].as_str() {
+                    value["thinking"] = serde_json::Value::String(f(thinking));
+                }
+                Self::Raw {
+                    provider: provider.clone(),
+                    value,
+                }
+            }
+            other => other.clone(),
         }
     }
 
     /// Get the text from a text block
     pub fn as_text(&self) -> Option<&str> {
         match self {
-            Self::Text { text } => Some(text),
+            Self::Text { text, .. } => Some(text),
             _ => None,
         }
     }
@@ -331,7 +1306,9 @@ impl ContentBlock {
     /// Get tool use information (id, name, input)
     pub fn as_tool_use(&self) -> Option<(&str, &str, &serde_json::Value)> {
         match self {
-            Self::ToolUse { id, name, input } => Some((id, name, input)),
+            Self::ToolUse {
+                id, name, input, ..
+            } => Some((id, name, input)),
             _ => None,
         }
     }
@@ -339,7 +1316,32 @@ impl ContentBlock {
     /// Get tool result information (tool_use_id, content)
     pub fn as_tool_result(&self) -> Option<(&str, &str)> {
         match self {
-            Self::ToolResult { tool_use_id, content } => Some((tool_use_id, content)),
+            Self::ToolResult {
+                tool_use_id,
+                content,
+                ..
+            } => Some((tool_use_id, content)),
+            _ => None,
+        }
+    }
+
+    /// Get attachment information (file_id, media_type, name)
+    pub fn as_attachment(&self) -> Option<(&str, Option<&str>, Option<&str>)> {
+        match self {
+            Self::Attachment {
+                file_id,
+                media_type,
+                name,
+                ..
+            } => Some((file_id, media_type.as_deref(), name.as_deref())),
+            _ => None,
+        }
+    }
+
+    /// Get the transcript of an audio output block
+    pub fn as_audio_transcript(&self) -> Option<&str> {
+        match self {
+            Self::AudioOutput { transcript, .. } => transcript.as_deref(),
             _ => None,
         }
     }
@@ -347,14 +1349,415 @@ impl ContentBlock {
     /// Get image source
     pub fn as_image(&self) -> Option<&ImageSource> {
         match self {
-            Self::Image { source } => Some(source),
+            Self::Image { source, .. } => Some(source),
             _ => None,
         }
     }
-}
 
-// ============================================================================
-// OpenAI-Compatible Tool Types
+    /// Get the provider-specific extra fields of this block
+    ///
+    /// Raw blocks keep everything in their value and have none.
+    pub fn extensions(&self) -> Option<&HashMap<String, serde_json::Value>> {
+        match self {
+            Self::Text { extensions, .. }
+            | Self::Image { extensions, .. }
+            | Self::Attachment { extensions, .. }
+            | Self::AudioOutput { extensions, .. }
+            | Self::ToolUse { extensions, .. }
+            | Self::ToolResult { extensions, .. } => Some(extensions),
+            Self::Raw { .. } => None,
+        }
+    }
+
+    /// Set a provider-specific extra field (no-op on raw blocks)
+    pub fn with_extension(mut self, key: impl Into<String>, value: serde_json::Value) -> Self {
+        match &mut self {
+            Self::Text { extensions, .. }
+            | Self::Image { extensions, .. }
+            | Self::Attachment { extensions, .. }
+            | Self::AudioOutput { extensions, .. }
+            | Self::ToolUse { extensions, .. }
+            | Self::ToolResult { extensions, .. } => {
+                extensions.insert(key.into(), value);
+            }
+            Self::Raw { .. } => {}
+        }
+        self
+    }
+
+    /// Get raw block information (provider, value)
+    pub fn as_raw(&self) -> Option<(&str, &serde_json::Value)> {
+        match self {
+            Self::Raw { provider, value } => Some((provider, value)),
+            _ => None,
+        }
+    }
+
+    /// Split a text block into blocks of at most `max_chars` characters
+    ///
+    /// Each chunk ends after the last newline within the limit, else after
+    /// the last whitespace, else at the limit itself, so concatenating the
+    /// chunks gives back the original text. Chunks keep the block's
+    /// extensions. Other variants, text that already fits and a zero
+    /// `max_chars` yield the block unchanged.
+    pub fn split_text(&self, max_chars: usize) -> Vec<ContentBlock> {
+        let (text, extensions) = match self {
+            Self::Text { text, extensions } if max_chars > 0 => (text, extensions),
+            _ => return vec![self.clone()],
+        };
+
+        let mut chunks = Vec::new();
+        let mut rest = text.as_str();
+        while let Some((limit, _)) = rest.char_indices().nth(max_chars) {
+            let window = &rest[..limit];
+            let cut = window
+                .rfind('\n')
+                .or_else(|| window.rfind(char::is_whitespace))
+                .map(|i| i + window[i..].chars().next().map_or(1, char::len_utf8))
+                .unwrap_or(limit);
+            chunks.push(Self::Text {
+                text: rest[..cut].to_string(),
+                extensions: extensions.clone(),
+            });
+            rest = &rest[cut..];
+        }
+        if !rest.is_empty() || chunks.is_empty() {
+            chunks.push(Self::Text {
+                text: rest.to_string(),
+                extensions: extensions.clone(),
+            });
+        }
+        chunks
+    }
+}
+
+/// A tool use block whose input is kept as unparsed JSON
+///
+/// Reads and writes the same JSON shape as [`ContentBlock::ToolUse`], but
+/// the `input` is held as raw JSON text so pass-through code can forward
+/// large inputs without building a [`serde_json::Value`] tree. Convert with
+/// [`into_block`](Self::into_block) when the input is needed.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RawToolUse {
+    /// Tool call ID
+    pub id: String,
+    /// Name of the tool
+    pub name: String,
+    /// Unparsed tool input
+    pub input: Box<serde_json::value::RawValue>,
+}
+
+impl RawToolUse {
+    /// Parse the input, upgrading to a [`ContentBlock::ToolUse`]
+    pub fn into_block(self) -> Result<ContentBlock, serde_json::Error> {
+        let input = serde_json::from_str(self.input.get())?;
+        Ok(ContentBlock::tool_use(self.id, self.name, input))
+    }
+
+    /// Create from a tool use block, serializing its input
+    ///
+    /// Returns `None` for other block types.
+    pub fn from_block(block: &ContentBlock) -> Option<Self> {
+        let (id, name, input) = block.as_tool_use()?;
+        Some(Self {
+            id: id.to_string(),
+            name: name.to_string(),
+            input: serde_json::value::to_raw_value(input).ok()?,
+        })
+    }
+}
+
+impl Serialize for RawToolUse {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("RawToolUse", 4)?;
+        state.serialize_field("type", "tool_use")?;
+        state.serialize_field("id", &self.id)?;
+        state.serialize_field("name", &self.name)?;
+        state.serialize_field("input", &self.input)?;
+        state.end()
+    }
+}
+
+/// Serde helper to skip `false` flags
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+/// Rebuild a JSON value with the keys of every object in sorted order
+fn canonicalize_json(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut entries: Vec<_> = map.into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            entries
+                .into_iter()
+                .map(|(key, value)| (key, canonicalize_json(value)))
+                .collect()
+        }
+        serde_json::Value::Array(items) => items.into_iter().map(canonicalize_json).collect(),
+        other => other,
+    }
+}
+
+/// Hash canonical JSON (object keys are sorted) to a stable `u64`
+///
+/// Keys are sorted explicitly rather than relying on serde_json's map order,
+/// which changes if any crate enables its `preserve_order` feature.
+fn hash_json(value: &serde_json::Value) -> u64 {
+    use sha2::{Digest, Sha256};
+
+    let canonical = canonicalize_json(value.clone()).to_string();
+    let digest = Sha256::digest(canonical.as_bytes());
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    u64::from_be_bytes(bytes)
+}
+
+/// Marker substituted for redacted text
+const REDACTED: &str = "[REDACTED]";
+
+fn redact_str(text: &mut String, patterns: &[regex::Regex]) {
+    for pattern in patterns {
+        if let std::borrow::Cow::Owned(replaced) = pattern.replace_all(text, REDACTED) {
+            *text = replaced;
+        }
+    }
+}
+
+fn redact_value(value: &mut serde_json::Value, patterns: &[regex::Regex]) {
+    match value {
+        serde_json::Value::String(text) => redact_str(text, patterns),
+        serde_json::Value::Array(items) => {
+            items.iter_mut().for_each(|v| redact_value(v, patterns))
+        }
+        serde_json::Value::Object(map) => {
+            map.values_mut().for_each(|v| redact_value(v, patterns))
+        }
+        _ => {}
+    }
+}
+
+/// Truncate text to at most `max_bytes`, appending a truncation marker
+///
+/// The cut is moved back to the nearest char boundary so multi-byte
+/// characters are never split, and `\n…[truncated N bytes]` is appended
+/// where N is the number of bytes removed. Text within the limit is
+/// returned unchanged.
+pub fn truncate_with_marker(mut text: String, max_bytes: usize) -> String {
+    if text.len() <= max_bytes {
+        return text;
+    }
+    let mut cut = max_bytes;
+    while !text.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    let removed = text.len() - cut;
+    text.truncate(cut);
+    text.push_str(&format!("\n…[truncated {} bytes]", removed));
+    text
+}
+
+// ============================================================================
+// Message Normalization
+// ============================================================================
+
+/// Merge all system messages into a single leading system message
+///
+/// The text of every `system` message is concatenated in order (newline-joined)
+/// into one system message placed at the start of the list. All other messages
+/// keep their relative order. Does nothing if there are no system messages.
+pub fn merge_system_messages(messages: &mut Vec<InternalMessage>) {
+    if !messages.iter().any(|m| m.role == MessageRole::System) {
+        return;
+    }
+
+    let mut system_texts = Vec::new();
+    let mut first_system: Option<InternalMessage> = None;
+    let mut rest = Vec::with_capacity(messages.len());
+
+    for msg in messages.drain(..) {
+        if msg.role == MessageRole::System {
+            system_texts.push(msg.to_text());
+            if first_system.is_none() {
+                first_system = Some(msg);
+            }
+        } else {
+            rest.push(msg);
+        }
+    }
+
+    // Keep metadata/name from the first system message
+    let mut merged = first_system.expect("at least one system message");
+    merged.content = MessageContent::Text(system_texts.join("\n"));
+
+    messages.push(merged);
+    messages.extend(rest);
+}
+
+/// Clear the `name` of all non-tool messages
+///
+/// For providers that reject participant names. Tool messages keep their
+/// name and tool call ID, which identify the tool being answered.
+pub fn strip_names(messages: &mut [InternalMessage]) {
+    for msg in messages.iter_mut().filter(|m| m.role != MessageRole::Tool) {
+        msg.name = None;
+    }
+}
+
+// ============================================================================
+// Conversation Validation
+// ============================================================================
+
+/// Validation errors for a single message in a conversation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageValidation {
+    /// Index of the message in the conversation
+    pub index: usize,
+    /// Human-readable error descriptions
+    pub errors: Vec<String>,
+}
+
+/// Validate the structural integrity of a conversation
+///
+/// Checks that tool messages carry a `tool_call_id`, that every tool result
+/// answers a tool call made earlier in the conversation, and that tool call
+/// IDs are unique. Returns one entry per invalid message; an empty result
+/// means the conversation is valid.
+pub fn validate_conversation(messages: &[InternalMessage]) -> Vec<MessageValidation> {
+    let mut seen_calls = std::collections::HashSet::new();
+    let mut report = Vec::new();
+
+    for (index, msg) in messages.iter().enumerate() {
+        let mut errors = Vec::new();
+
+        if msg.role == MessageRole::Tool {
+            match &msg.tool_call_id {
+                Some(id) if !seen_calls.contains(id.as_str()) => {
+                    errors.push(format!("tool result `{}` has no preceding tool call", id));
+                }
+                Some(_) => {}
+                None => errors.push("tool message is missing `tool_call_id`".to_string()),
+            }
+        }
+
+        for block in msg.blocks().unwrap_or_default() {
+            if let Some((id, _, _)) = block.as_tool_use() {
+                if msg.role != MessageRole::Assistant {
+                    errors.push(format!("tool call `{}` outside an assistant message", id));
+                }
+                if !seen_calls.insert(id) {
+                    errors.push(format!("duplicate tool call id `{}`", id));
+                }
+            }
+            if let Some((id, _)) = block.as_tool_result() {
+                if !seen_calls.contains(id) {
+                    errors.push(format!("tool result `{}` has no preceding tool call", id));
+                }
+            }
+        }
+
+        if !errors.is_empty() {
+            report.push(MessageValidation { index, errors });
+        }
+    }
+
+    report
+}
+
+/// Mismatch between an assistant's tool calls and the results answering them
+///
+/// Returned by [`check_tool_linkage`]. IDs are listed in the order they
+/// were encountered.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LinkageError {
+    /// Tool call IDs with no result
+    pub unmatched_calls: Vec<String>,
+    /// Result IDs answering no tool call of the assistant message
+    pub orphan_results: Vec<String>,
+    /// Tool call IDs answered more than once
+    pub duplicate_results: Vec<String>,
+}
+
+impl std::fmt::Display for LinkageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = Vec::new();
+        for (label, ids) in [
+            ("unmatched tool calls", &self.unmatched_calls),
+            ("orphan tool results", &self.orphan_results),
+            ("duplicate tool results", &self.duplicate_results),
+        ] {
+            if !ids.is_empty() {
+                parts.push(format!("{}: {}", label, ids.join(", ")));
+            }
+        }
+        write!(f, "tool linkage mismatch ({})", parts.join("; "))
+    }
+}
+
+impl std::error::Error for LinkageError {}
+
+/// Check that each tool call of `assistant` is answered exactly once
+///
+/// Results are read from `tool_call_id` of tool messages and from tool
+/// result blocks in `results`. Succeeds when every tool use block of the
+/// assistant message has exactly one result and every result answers one
+/// of those calls.
+pub fn check_tool_linkage(
+    assistant: &InternalMessage,
+    results: &[InternalMessage],
+) -> Result<(), LinkageError> {
+    let calls: Vec<&str> = assistant
+        .blocks()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|b| b.as_tool_use().map(|(id, _, _)| id))
+        .collect();
+
+    let mut answered: Vec<&str> = Vec::new();
+    for msg in results {
+        // A tool message may name its call both in `tool_call_id` and in a
+        // tool result block; count each id once per message
+        let mut seen = std::collections::HashSet::new();
+        if msg.role == MessageRole::Tool {
+            if let Some(id) = &msg.tool_call_id {
+                seen.insert(id.as_str());
+                answered.push(id);
+            }
+        }
+        for block in msg.blocks().unwrap_or_default() {
+            if let Some((id, _)) = block.as_tool_result() {
+                if seen.insert(id) {
+                    answered.push(id);
+                }
+            }
+        }
+    }
+
+    let mut error = LinkageError::default();
+    for call in &calls {
+        match answered.iter().filter(|id| *id == call).count() {
+            0 => error.unmatched_calls.push(call.to_string()),
+            1 => {}
+            _ => error.duplicate_results.push(call.to_string()),
+        }
+    }
+    for id in &answered {
+        if !calls.contains(id) && !error.orphan_results.iter().any(|o| o == id) {
+            error.orphan_results.push(id.to_string());
+        }
+    }
+
+    if error == LinkageError::default() {
+        Ok(())
+    } else {
+        Err(error)
+    }
+}
+
+// ============================================================================
+// OpenAI-Compatible Tool Types
 // ============================================================================
 
 /// Function call structure for tool invocations
@@ -373,6 +1776,18 @@ pub struct ToolCall {
     pub function: FunctionCall,
 }
 
+impl ToolCall {
+    /// Get the arguments string exactly as received
+    pub fn raw_arguments(&self) -> &str {
+        &self.function.arguments
+    }
+
+    /// Parse the raw JSON arguments string
+    pub fn parsed_arguments(&self) -> Result<serde_json::Value, serde_json::Error> {
+        serde_json::from_str(&self.function.arguments)
+    }
+}
+
 /// Function definition for tools
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Function {
@@ -389,6 +1804,45 @@ pub struct Tool {
     pub function: Function,
 }
 
+/// Whether and which tool the model must call in a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToolChoice {
+    /// The model decides
+    Auto,
+    /// No tool may be called
+    None,
+    /// Some tool must be called
+    Required,
+    /// The named tool must be called
+    Specific(String),
+}
+
+impl ToolChoice {
+    /// Convert to the OpenAI `tool_choice` request field
+    pub fn to_openai(&self) -> serde_json::Value {
+        match self {
+            Self::Auto => serde_json::json!("auto"),
+            Self::None => serde_json::json!("none"),
+            Self::Required => serde_json::json!("required"),
+            Self::Specific(name) => {
+                serde_json::json!({"type": "function", "function": {"name": name}})
+            }
+        }
+    }
+
+    /// Convert to the Anthropic `tool_choice` request field
+    ///
+    /// [`Required`](Self::Required) maps to Anthropic's `any`.
+    pub fn to_anthropic(&self) -> serde_json::Value {
+        match self {
+            Self::Auto => serde_json::json!({"type": "auto"}),
+            Self::None => serde_json::json!({"type": "none"}),
+            Self::Required => serde_json::json!({"type": "any"}),
+            Self::Specific(name) => serde_json::json!({"type": "tool", "name": name}),
+        }
+    }
+}
+
 /// Result of generation with tools
 #[derive(Debug)]
 pub enum GenerateResult {
@@ -531,6 +1985,8 @@ mod tests {
             metadata: std::collections::HashMap::new(),
             tool_call_id: None,
             name: None,
+            created_at: None,
+            hints: None,
         };
 
         let json = serde_json::to_string(&msg).unwrap();
@@ -565,6 +2021,8 @@ mod tests {
             metadata: std::collections::HashMap::new(),
             tool_call_id: None,
             name: None,
+            created_at: None,
+            hints: None,
         };
 
         let json = serde_json::to_value(&msg).unwrap();
@@ -599,4 +2057,801 @@ mod tests {
         assert_eq!(parsed["name"].as_str(), Some("search"));
         assert_eq!(parsed["content"].as_str(), Some("Result"));
     }
+
+    #[test]
+    fn test_merge_system_messages() {
+        let mut messages = vec![
+            InternalMessage::user("First question"),
+            InternalMessage::system("You are a helpful assistant"),
+            InternalMessage::user("Second question"),
+            InternalMessage::system("Answer concisely"),
+            InternalMessage::assistant("Sure"),
+        ];
+
+        merge_system_messages(&mut messages);
+
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[0].role, MessageRole::System);
+        assert_eq!(
+            messages[0].text(),
+            Some("You are a helpful assistant\nAnswer concisely")
+        );
+        assert_eq!(messages[1].text(), Some("First question"));
+        assert_eq!(messages[2].text(), Some("Second question"));
+        assert_eq!(messages[3].role, MessageRole::Assistant);
+        assert_eq!(
+            messages.iter().filter(|m| m.role == MessageRole::System).count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_text_blocks_borrowed() {
+        let msg = InternalMessage {
+            role: MessageRole::Assistant,
+            content: MessageContent::Blocks(vec![
+                ContentBlock::text("First"),
+                ContentBlock::tool_use("call_1", "search", serde_json::json!({})),
+                ContentBlock::text("Second"),
+                ContentBlock::tool_result("call_1", "Result"),
+            ]),
+            metadata: HashMap::new(),
+            tool_call_id: None,
+            name: None,
+            created_at: None,
+            hints: None,
+        };
+
+        let pieces: Vec<&str> = msg.text_blocks().collect();
+        assert_eq!(pieces, vec!["First", "Second", "Result"]);
+
+        // Slices borrow from the message rather than copying
+        let blocks = msg.blocks().unwrap();
+        assert!(std::ptr::eq(pieces[0], blocks[0].as_text().unwrap()));
+
+        assert_eq!(msg.to_text(), "First\nSecond\nResult");
+        assert_eq!(InternalMessage::user("Hi").to_text(), "Hi");
+    }
+
+    #[test]
+    fn test_raw_block_matches_spec() {
+        let block = ContentBlock::raw(
+            "anthropic",
+            serde_json::json!({"type": "server_tool_use", "id": "srv_1"}),
+        );
+        let json = serde_json::to_value(&block).unwrap();
+
+        assert_eq!(json["type"], "raw");
+        assert_eq!(json["provider"], "anthropic");
+        assert_eq!(json["value"]["type"], "server_tool_use");
+        assert_eq!(json.as_object().unwrap().len(), 3);
+
+        let parsed: ContentBlock = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.as_raw().unwrap().0, "anthropic");
+    }
+
+    #[test]
+    fn test_raw_blocks_pass_through_matching_provider() {
+        let raw = serde_json::json!({"type": "server_tool_use", "id": "srv_1"});
+        let mut msg = InternalMessage {
+            role: MessageRole::Assistant,
+            content: MessageContent::Blocks(vec![
+                ContentBlock::text("Searching"),
+                ContentBlock::raw("anthropic", raw.clone()),
+            ]),
+            metadata: HashMap::new(),
+            tool_call_id: None,
+            name: None,
+            created_at: None,
+            hints: None,
+        };
+        assert_eq!(msg.to_text(), "Searching");
+
+        let mut dropped = Vec::new();
+        msg.retain_raw_blocks_for("anthropic", |p, _| dropped.push(p.to_string()));
+
+        assert!(dropped.is_empty());
+        let blocks = msg.blocks().unwrap();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[1].as_raw(), Some(("anthropic", &raw)));
+    }
+
+    #[test]
+    fn test_raw_blocks_dropped_for_other_provider() {
+        let mut msg = InternalMessage {
+            role: MessageRole::Assistant,
+            content: MessageContent::Blocks(vec![
+                ContentBlock::text("Searching"),
+                ContentBlock::raw("anthropic", serde_json::json!({"type": "server_tool_use"})),
+            ]),
+            metadata: HashMap::new(),
+            tool_call_id: None,
+            name: None,
+            created_at: None,
+            hints: None,
+        };
+
+        let mut dropped = Vec::new();
+        msg.retain_raw_blocks_for("openai", |p, v| dropped.push((p.to_string(), v.clone())));
+
+        assert_eq!(dropped.len(), 1);
+        assert_eq!(dropped[0].0, "anthropic");
+        assert_eq!(msg.blocks().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_tool_choice() {
+        let choice = ToolChoice::Specific("search".to_string());
+        assert_eq!(
+            choice.to_openai(),
+            serde_json::json!({"type": "function", "function": {"name": "search"}})
+        );
+        assert_eq!(
+            choice.to_anthropic(),
+            serde_json::json!({"type": "tool", "name": "search"})
+        );
+        assert_eq!(ToolChoice::Required.to_openai(), "required");
+        assert_eq!(ToolChoice::Required.to_anthropic()["type"], "any");
+        assert_eq!(ToolChoice::None.to_openai(), "none");
+    }
+
+    #[test]
+    fn test_validate_conversation() {
+        let valid = vec![
+            InternalMessage::user("Search for rust"),
+            InternalMessage::assistant_with_tools(
+                "Searching",
+                vec![ContentBlock::tool_use("call_1", "search", serde_json::json!({}))],
+            ),
+            InternalMessage::tool_result("call_1", "search", "Found it"),
+        ];
+        assert!(validate_conversation(&valid).is_empty());
+
+        let mut invalid = valid.clone();
+        invalid.push(InternalMessage::tool_result("call_1", "search", "Again"));
+        invalid.push(InternalMessage::assistant_with_tools(
+            "Oops",
+            vec![ContentBlock::tool_use("call_1", "search", serde_json::json!({}))],
+        ));
+        invalid.push(InternalMessage::tool(MessageContent::text("No id")));
+
+        let report = validate_conversation(&invalid);
+        assert_eq!(report.len(), 2);
+        assert_eq!(report[0].index, 4);
+        assert!(report[0].errors[0].contains("duplicate"));
+        assert_eq!(report[1].index, 5);
+        assert!(report[1].errors[0].contains("tool_call_id"));
+    }
+
+    #[test]
+    fn test_check_tool_linkage() {
+        let assistant = InternalMessage::assistant_blocks(vec![
+            ContentBlock::tool_use("call_1", "search", serde_json::json!({})),
+            ContentBlock::tool_use("call_2", "fetch", serde_json::json!({})),
+        ]);
+        let result_block = ContentBlock::tool_result("call_2", "Fetched");
+        let paired = vec![
+            InternalMessage::tool_result("call_1", "search", "Found"),
+            InternalMessage::tool(MessageContent::blocks(vec![result_block])),
+        ];
+        assert_eq!(check_tool_linkage(&assistant, &paired), Ok(()));
+
+        let missing = &paired[..1];
+        let err = check_tool_linkage(&assistant, missing).unwrap_err();
+        assert_eq!(err.unmatched_calls, ["call_2"]);
+        assert!(err.orphan_results.is_empty());
+
+        let mut extra = paired.clone();
+        extra.push(InternalMessage::tool_result("call_9", "search", "Stray"));
+        extra.push(InternalMessage::tool_result("call_1", "search", "Again"));
+        let err = check_tool_linkage(&assistant, &extra).unwrap_err();
+        assert!(err.unmatched_calls.is_empty());
+        assert_eq!(err.orphan_results, ["call_9"]);
+        assert_eq!(err.duplicate_results, ["call_1"]);
+        assert!(err.to_string().contains("orphan tool results: call_9"));
+
+        // tool_call_id and a result block naming the same call answer it once
+        let mut mixed = InternalMessage::tool(MessageContent::blocks(vec![
+            ContentBlock::tool_result("call_1", "Found"),
+        ]));
+        mixed.tool_call_id = Some("call_1".to_string());
+        let results = vec![mixed, paired[1].clone()];
+        assert_eq!(check_tool_linkage(&assistant, &results), Ok(()));
+    }
+
+    #[test]
+    fn test_preview() {
+        let data = "iVBORw0KGgo".repeat(100_000);
+        let msg = InternalMessage::assistant_blocks(vec![
+            ContentBlock::text("Here is\nthe chart"),
+            ContentBlock::image(ImageSource::Base64 {
+                media_type: "image/png".to_string(),
+                data: data.clone(),
+            }),
+            ContentBlock::tool_use("call_1", "render", serde_json::json!({"data": data})),
+        ]);
+
+        let preview = msg.preview(200);
+        assert_eq!(preview, "assistant: Here is the chart [image] tool(render)");
+        assert!(!preview.contains("iVBOR"));
+
+        let short = msg.preview(16);
+        assert_eq!(short, "assistant: Here…");
+        assert_eq!(short.chars().count(), 16);
+        assert_eq!(InternalMessage::user("Hi").preview(0), "");
+    }
+
+    #[test]
+    fn test_tool_error_results() {
+        let success = InternalMessage::tool_success("call_1", "search", "Found it");
+        let error = InternalMessage::tool_error("call_2", "search", "Timed out");
+        assert!(!success.is_error_result());
+        assert!(error.is_error_result());
+        assert_eq!(success.to_text(), "Found it");
+        assert_eq!(error.to_text(), "[error] Timed out");
+        assert_eq!(ChatMLMessage::from(&error).content, "[error] Timed out");
+
+        let blocks = InternalMessage::tool(MessageContent::blocks(vec![
+            ContentBlock::tool_success("call_1", "Found it"),
+            ContentBlock::tool_error("call_2", "Timed out"),
+        ]));
+        assert!(blocks.is_error_result());
+        assert_eq!(blocks.to_text(), "Found it\n[error] Timed out");
+
+        // The flag is omitted from JSON unless set
+        let json = serde_json::to_value(ContentBlock::tool_success("call_1", "ok")).unwrap();
+        assert!(json.get("is_error").is_none());
+        let json = serde_json::to_value(ContentBlock::tool_error("call_2", "boom")).unwrap();
+        assert_eq!(json["is_error"], true);
+        let parsed: ContentBlock = serde_json::from_value(json).unwrap();
+        assert!(parsed.is_error_result());
+    }
+
+    #[test]
+    fn test_eq_ignoring_metadata() {
+        let expected = InternalMessage::tool_result("call_1", "search", "Found it");
+        let mut actual = expected.clone().with_created_at(1_700_000_000_000);
+        actual
+            .metadata
+            .insert("trace_id".to_string(), "abc123".to_string());
+
+        assert!(actual.eq_ignoring_metadata(&expected));
+        // Metadata is not hashed either, so the content hashes agree
+        assert_eq!(actual.content_hash(), expected.content_hash());
+
+        let other = InternalMessage::tool_result("call_2", "search", "Found it");
+        assert!(!actual.eq_ignoring_metadata(&other));
+        let other = InternalMessage::tool_result("call_1", "search", "Nothing");
+        assert!(!actual.eq_ignoring_metadata(&other));
+    }
+
+    #[test]
+    fn test_content_block_extensions_roundtrip() {
+        let json = r#"{"type":"text","text":"Long context","cache_control":{"type":"ephemeral"}}"#;
+        let block: ContentBlock = serde_json::from_str(json).unwrap();
+        assert_eq!(block.as_text(), Some("Long context"));
+        assert_eq!(
+            block.extensions().unwrap()["cache_control"],
+            serde_json::json!({"type": "ephemeral"})
+        );
+
+        let value = serde_json::to_value(&block).unwrap();
+        assert_eq!(value, serde_json::from_str::<serde_json::Value>(json).unwrap());
+
+        // Blocks without extras serialize as before
+        let plain = serde_json::to_value(ContentBlock::text("Hi")).unwrap();
+        assert_eq!(plain, serde_json::json!({"type": "text", "text": "Hi"}));
+    }
+
+    #[test]
+    fn test_strip_names() {
+        let mut messages = vec![
+            InternalMessage::system("Be brief").with_name("policy"),
+            InternalMessage::user("Hi").with_name("alice"),
+            InternalMessage::assistant("Hello").with_name("bot"),
+            InternalMessage::tool_result("call_1", "search", "Found it"),
+        ];
+        strip_names(&mut messages);
+
+        assert!(messages[..3].iter().all(|m| m.name.is_none()));
+        assert_eq!(messages[3].name.as_deref(), Some("search"));
+        assert_eq!(messages[3].tool_call_id.as_deref(), Some("call_1"));
+
+        let msg = InternalMessage::user("Hi").with_name("alice").without_name();
+        assert!(msg.name.is_none());
+    }
+
+    #[test]
+    fn test_message_hints_roundtrip() {
+        let plain = serde_json::to_value(InternalMessage::user("Hi")).unwrap();
+        assert!(plain.get("hints").is_none());
+
+        let msg = InternalMessage::user("Prove the theorem").with_hints(MessageHints {
+            reasoning_effort: Some("high".to_string()),
+            preferred_model: Some("large".to_string()),
+            temperature: Some(0.5),
+        });
+        let value = serde_json::to_value(&msg).unwrap();
+        assert_eq!(
+            value["hints"],
+            serde_json::json!({
+                "reasoning_effort": "high",
+                "preferred_model": "large",
+                "temperature": 0.5,
+            })
+        );
+        let restored: InternalMessage = serde_json::from_value(value).unwrap();
+        assert_eq!(restored.hints, msg.hints);
+
+        // Hints are internal and never reach providers
+        assert!(to_openai_message(&msg).get("hints").is_none());
+    }
+
+    #[test]
+    fn test_tool_use_canonical() {
+        let input: serde_json::Value = serde_json::from_str(
+            r#"{"query": "rust", "filters": {"year": 2024, "lang": "en"}}"#,
+        )
+        .unwrap();
+        let block = ContentBlock::tool_use_canonical("call_1", "search", input);
+
+        // Hand-written in sorted key order; `RawValue` keeps the text as written
+        let expected = serde_json::value::RawValue::from_string(
+            concat!(
+                r#"{"type":"tool_use","id":"call_1","name":"search","#,
+                r#""input":{"filters":{"lang":"en","year":2024},"query":"rust"}}"#
+            )
+            .to_string(),
+        )
+        .unwrap();
+        assert_eq!(serde_json::to_string(&block).unwrap(), expected.get());
+    }
+
+    #[test]
+    fn test_attachment_block() {
+        let block = ContentBlock::attachment_with(
+            "file-abc123",
+            Some("application/pdf".to_string()),
+            Some("report.pdf".to_string()),
+        );
+        let value = serde_json::to_value(&block).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "type": "attachment",
+                "file_id": "file-abc123",
+                "media_type": "application/pdf",
+                "name": "report.pdf",
+            })
+        );
+        let restored: ContentBlock = serde_json::from_value(value).unwrap();
+        assert_eq!(restored, block);
+        assert_eq!(
+            restored.as_attachment(),
+            Some(("file-abc123", Some("application/pdf"), Some("report.pdf")))
+        );
+
+        let unnamed = serde_json::to_value(ContentBlock::attachment("file-xyz")).unwrap();
+        assert_eq!(unnamed, serde_json::json!({"type": "attachment", "file_id": "file-xyz"}));
+
+        let msg = InternalMessage::assistant_blocks(vec![
+            ContentBlock::text("Summarize"),
+            block,
+            ContentBlock::attachment("file-xyz"),
+        ]);
+        assert_eq!(msg.to_text(), "Summarize\nreport.pdf\nfile-xyz");
+    }
+
+    #[test]
+    fn test_audio_output_block() {
+        let block = ContentBlock::audio_output(
+            Some("audio_1".to_string()),
+            Some("Hi!".to_string()),
+            Some(AudioSource::Base64 {
+                media_type: Some("audio/wav".to_string()),
+                data: "UklGRg==".to_string(),
+            }),
+        );
+        let value = serde_json::to_value(&block).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "type": "audio_output",
+                "id": "audio_1",
+                "transcript": "Hi!",
+                "source": {"type": "base64", "media_type": "audio/wav", "data": "UklGRg=="},
+            })
+        );
+        assert_eq!(serde_json::from_value::<ContentBlock>(value).unwrap(), block);
+    }
+
+    #[test]
+    fn test_split_text() {
+        let text: String = (0..100)
+            .map(|i| format!("word{:04}. ", i))
+            .collect::<String>()
+            .replace("word0050. ", "word0050.\n");
+        assert_eq!(text.chars().count(), 1000);
+
+        let chunks = ContentBlock::text(text.clone()).split_text(300);
+        assert!(chunks.len() >= 4);
+        for chunk in &chunks {
+            assert!(chunk.as_text().unwrap().chars().count() <= 300);
+        }
+        let joined: String = chunks.iter().filter_map(ContentBlock::as_text).collect();
+        assert_eq!(joined, text);
+        // Cuts fall on the newline, then on spaces
+        assert!(chunks[1].as_text().unwrap().ends_with("word0050.\n"));
+        assert!(chunks[0].as_text().unwrap().ends_with(' '));
+
+        let unbroken = "é".repeat(10);
+        let chunks = ContentBlock::text(unbroken.clone()).split_text(3);
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(chunks.iter().filter_map(ContentBlock::as_text).collect::<String>(), unbroken);
+
+        let image = ContentBlock::image(ImageSource::Url {
+            url: "https://example.com/a.png".to_string(),
+        });
+        assert_eq!(image.split_text(3), vec![image]);
+    }
+
+    #[test]
+    fn test_role_aliases() {
+        let msg: InternalMessage =
+            serde_json::from_str(r#"{"role":"ai","content":"Hello"}"#).unwrap();
+        assert_eq!(msg.role, MessageRole::Assistant);
+
+        let msg: InternalMessage =
+            serde_json::from_str(r#"{"role":"human","content":"Hi"}"#).unwrap();
+        assert_eq!(msg.role, MessageRole::User);
+
+        let role: MessageRole = serde_json::from_str(r#""model""#).unwrap();
+        assert_eq!(role, MessageRole::Assistant);
+
+        // Canonical names still work and serialization stays canonical
+        let role: MessageRole = serde_json::from_str(r#""system""#).unwrap();
+        assert_eq!(role, MessageRole::System);
+        assert_eq!(serde_json::to_string(&MessageRole::Assistant).unwrap(), r#""assistant""#);
+
+        assert!(serde_json::from_str::<MessageRole>(r#""narrator""#).is_err());
+    }
+
+    #[test]
+    fn test_flatten_renders_tool_calls() {
+        let mut msg = InternalMessage::assistant_with_tools(
+            "Let me search",
+            vec![ContentBlock::tool_use("call_1", "search", serde_json::json!({"q": "rust"}))],
+        );
+        msg.metadata.insert("trace_id".to_string(), "abc".to_string());
+
+        let flat = msg.flatten(true);
+        assert!(flat.content.is_text());
+        assert_eq!(flat.role, MessageRole::Assistant);
+        assert_eq!(flat.text(), Some("Let me search\n[tool: search({\"q\":\"rust\"})]"));
+        assert_eq!(flat.metadata.get("trace_id").map(String::as_str), Some("abc"));
+
+        let flat = msg.flatten(false);
+        assert_eq!(flat.text(), Some("Let me search"));
+    }
+
+    #[test]
+    fn test_tool_result_truncated() {
+        // 3-byte chars so 1024 falls mid-character
+        let content = "€".repeat(10 * 1024 / 3);
+        let block = ContentBlock::tool_result_truncated("call_1", content.clone(), 1024);
+        let (_, truncated) = block.as_tool_result().unwrap();
+
+        let (kept, marker) = truncated.split_once('\n').unwrap();
+        assert!(kept.len() <= 1024);
+        assert!(kept.chars().all(|c| c == '€'));
+        assert_eq!(marker, format!("…[truncated {} bytes]", content.len() - kept.len()));
+
+        let block = ContentBlock::tool_result_truncated("call_1", "short", 1024);
+        assert_eq!(block.as_tool_result().unwrap().1, "short");
+    }
+
+    #[test]
+    fn test_to_flat_json() {
+        let mut msg = InternalMessage::user("Hello");
+        msg.metadata.insert("trace_id".to_string(), "x".to_string());
+
+        let flat = msg.to_flat_json().unwrap();
+        assert_eq!(flat["role"], "user");
+        assert_eq!(flat["content"], "Hello");
+        assert_eq!(flat["trace_id"], "x");
+        assert!(flat.get("metadata").is_none());
+
+        msg.metadata.insert("role".to_string(), "admin".to_string());
+        assert!(matches!(msg.to_flat_json(), Err(UmfError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_assistant_blocks_preserves_order() {
+        let msg = InternalMessage::assistant_blocks(vec![
+            ContentBlock::raw(
+                "anthropic",
+                serde_json::json!({"type": "thinking", "thinking": "User wants a search"}),
+            ),
+            ContentBlock::text("Searching now"),
+            ContentBlock::tool_use("call_1", "search", serde_json::json!({"q": "rust"})),
+        ]);
+
+        assert_eq!(msg.role, MessageRole::Assistant);
+        let blocks = msg.blocks().unwrap();
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[0].as_raw().unwrap().1["type"], "thinking");
+        assert_eq!(blocks[1].as_text(), Some("Searching now"));
+        assert!(blocks[2].as_tool_use().is_some());
+    }
+
+    #[test]
+    fn test_role_from_str() {
+        assert_eq!("assistant".parse::<MessageRole>().unwrap(), MessageRole::Assistant);
+        for role in [
+            MessageRole::System,
+            MessageRole::User,
+            MessageRole::Assistant,
+            MessageRole::Tool,
+        ] {
+            assert_eq!(role.as_str().parse::<MessageRole>().unwrap(), role);
+        }
+        assert!(matches!(
+            "wizard".parse::<MessageRole>(),
+            Err(UmfError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_image_placeholders() {
+        let image = |url: &str| {
+            ContentBlock::image(ImageSource::Url {
+                url: url.to_string(),
+            })
+        };
+        let msg = InternalMessage {
+            role: MessageRole::User,
+            content: MessageContent::Blocks(vec![
+                image("https://example.com/a.png"),
+                image("https://example.com/b.png"),
+            ]),
+            metadata: HashMap::new(),
+            tool_call_id: None,
+            name: None,
+            created_at: None,
+            hints: None,
+        };
+
+        assert_eq!(msg.image_count(), 2);
+        assert_eq!(msg.to_text(), "");
+        assert_eq!(msg.to_text_with_placeholders(), "[image 1/2]\n[image 2/2]");
+
+        let text = InternalMessage::user("No images");
+        assert_eq!(text.image_count(), 0);
+        assert_eq!(text.to_text_with_placeholders(), "No images");
+    }
+
+    #[test]
+    fn test_redact() {
+        let patterns = vec![regex::Regex::new(r"\bSECRET\b").unwrap()];
+
+        let mut msg = InternalMessage::user("The password is SECRET, not SECRETS");
+        msg.redact(&patterns);
+        assert_eq!(msg.text(), Some("The password is [REDACTED], not SECRETS"));
+
+        let mut msg = InternalMessage::assistant_with_tools(
+            "Logging in with SECRET",
+            vec![ContentBlock::tool_use(
+                "call_1",
+                "login",
+                serde_json::json!({"auth": {"token": "SECRET"}, "retries": 3}),
+            )],
+        );
+        msg.redact(&patterns);
+        let blocks = msg.blocks().unwrap();
+        assert_eq!(blocks[0].as_text(), Some("Logging in with [REDACTED]"));
+        let (_, _, input) = blocks[1].as_tool_use().unwrap();
+        assert_eq!(input["auth"]["token"], "[REDACTED]");
+        assert_eq!(input["retries"], 3);
+    }
+
+    #[test]
+    fn test_map_text() {
+        let msg = InternalMessage::assistant_blocks(vec![
+            ContentBlock::raw(
+                "anthropic",
+                serde_json::json!({"type": "thinking", "thinking": "hmm", "signature": "sig"}),
+            ),
+            ContentBlock::text("let me search"),
+            ContentBlock::tool_use("call_1", "search", serde_json::json!({"query": "rust"})),
+            ContentBlock::tool_result("call_1", "found it"),
+        ]);
+
+        let upper = msg.map_text(|text| text.to_uppercase());
+        let blocks = upper.blocks().unwrap();
+        let (_, thinking) = blocks[0].as_raw().unwrap();
+        assert_eq!(thinking["thinking"], "HMM");
+        assert_eq!(thinking["signature"], "sig");
+        assert_eq!(blocks[1].as_text(), Some("LET ME SEARCH"));
+        let (_, name, input) = blocks[2].as_tool_use().unwrap();
+        assert_eq!(name, "search");
+        assert_eq!(input["query"], "rust");
+        assert_eq!(blocks[3].as_tool_result(), Some(("call_1", "FOUND IT")));
+
+        let text = InternalMessage::user("hi").map_text(|t| t.to_uppercase());
+        assert_eq!(text.text(), Some("HI"));
+    }
+
+    #[test]
+    fn test_try_assistant_with_tools() {
+        let msg = InternalMessage::try_assistant_with_tools(
+            "Searching",
+            vec![ContentBlock::tool_use("call_1", "search", serde_json::json!({}))],
+        )
+        .unwrap();
+        assert_eq!(msg.blocks().unwrap().len(), 2);
+
+        let err = InternalMessage::try_assistant_with_tools(
+            "Searching",
+            vec![
+                ContentBlock::tool_use("call_1", "search", serde_json::json!({})),
+                ContentBlock::text("not a tool call"),
+            ],
+        )
+        .unwrap_err();
+        assert!(matches!(err, UmfError::InvalidInput(ref m) if m.contains("tool_calls[1]")));
+    }
+
+    #[test]
+    fn test_prune_empty_blocks() {
+        let mut msg = InternalMessage::assistant_blocks(vec![
+            ContentBlock::text(""),
+            ContentBlock::tool_use("call_1", "search", serde_json::json!({"q": "rust"})),
+            ContentBlock::text("  \n"),
+        ]);
+        msg.prune_empty_blocks();
+        let blocks = msg.blocks().unwrap();
+        assert_eq!(blocks.len(), 1);
+        assert!(blocks[0].as_tool_use().is_some());
+
+        let mut msg = InternalMessage::assistant_blocks(vec![
+            ContentBlock::text("Hello"),
+            ContentBlock::text(" "),
+        ]);
+        msg.prune_empty_blocks();
+        assert_eq!(msg.text(), Some("Hello"));
+    }
+
+    #[test]
+    fn test_tool_call_parsed_arguments() {
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            r#type: "function".to_string(),
+            function: FunctionCall {
+                name: "search".to_string(),
+                arguments: r#"{"query": "rust"}"#.to_string(),
+            },
+        };
+        assert_eq!(call.parsed_arguments().unwrap(), serde_json::json!({"query": "rust"}));
+
+        let partial = ToolCall {
+            function: FunctionCall {
+                name: "search".to_string(),
+                arguments: r#"{"query": "ru"#.to_string(),
+            },
+            ..call
+        };
+        assert!(partial.parsed_arguments().is_err());
+    }
+
+    #[test]
+    fn test_char_and_word_count() {
+        let msg = InternalMessage::assistant_blocks(vec![
+            ContentBlock::text("Hello there, world"),
+            ContentBlock::tool_use("call_1", "search", serde_json::json!({"q": "not counted"})),
+            ContentBlock::image(ImageSource::Url {
+                url: "https://example.com/a.png".to_string(),
+            }),
+            ContentBlock::tool_result("call_1", "café ok"),
+        ]);
+        assert_eq!(msg.char_count(), 18 + 7);
+        assert_eq!(msg.word_count(), 3 + 2);
+
+        assert_eq!(InternalMessage::user("  ").word_count(), 0);
+    }
+
+    #[test]
+    fn test_semantic_fingerprint_ignores_ids() {
+        let call = |id: &str| {
+            InternalMessage::assistant_with_tools(
+                "Searching",
+                vec![ContentBlock::tool_use(
+                    id,
+                    "search",
+                    serde_json::json!({"query": "rust", "limit": 5}),
+                )],
+            )
+        };
+        let a = call("call_abc");
+        let b = call("call_xyz");
+        assert_eq!(a.semantic_fingerprint(), b.semantic_fingerprint());
+        assert_ne!(a.content_hash(), b.content_hash());
+        assert_eq!(a.content_hash(), call("call_abc").content_hash());
+
+        let ra = InternalMessage::tool_result("call_abc", "search", "3 results");
+        let rb = InternalMessage::tool_result("call_xyz", "search", "3 results");
+        assert_eq!(ra.semantic_fingerprint(), rb.semantic_fingerprint());
+        assert_ne!(ra.content_hash(), rb.content_hash());
+
+        let other = InternalMessage::tool_result("call_abc", "search", "4 results");
+        assert_ne!(ra.semantic_fingerprint(), other.semantic_fingerprint());
+        assert_ne!(
+            InternalMessage::user("Hi").semantic_fingerprint(),
+            InternalMessage::assistant("Hi").semantic_fingerprint()
+        );
+    }
+
+    #[test]
+    fn test_image_content_id() {
+        let base64 = |data: &str| ImageSource::Base64 {
+            media_type: "image/png".to_string(),
+            data: data.to_string(),
+        };
+
+        // "hello!!" encoded with and without padding, and wrapped
+        let padded = base64("aGVsbG8hIQ==");
+        let unpadded = base64("aGVsbG8hIQ");
+        let wrapped = base64("aGVs\nbG8h IQ==");
+        let id = padded.content_id().unwrap();
+        assert_eq!(id.len(), 16);
+        assert_eq!(unpadded.content_id(), Some(id.clone()));
+        assert_eq!(wrapped.content_id(), Some(id.clone()));
+        assert_ne!(base64("aGVsbG8=").content_id(), Some(id));
+        assert_eq!(base64("not base64!").content_id(), None);
+
+        let url = ImageSource::Url {
+            url: "https://example.com/a.png".to_string(),
+        };
+        assert_eq!(url.content_id().map(|id| id.len()), Some(16));
+    }
+
+    #[test]
+    fn test_raw_tool_use_pass_through() {
+        let rows: Vec<String> = (0..10_000)
+            .map(|i| format!(r#"{{"id":{},"label":"row {}","tags":["a","b"]}}"#, i, i))
+            .collect();
+        let json = format!(
+            r#"{{"type":"tool_use","id":"call_1","name":"bulk_insert","input":{{"rows":[{}]}}}}"#,
+            rows.join(",")
+        );
+
+        let raw: RawToolUse = serde_json::from_str(&json).unwrap();
+        assert_eq!(raw.name, "bulk_insert");
+        assert!(raw.input.get().starts_with(r#"{"rows":[{"id":0"#));
+        assert_eq!(serde_json::to_string(&raw).unwrap(), json);
+
+        let block = raw.into_block().unwrap();
+        let (id, _, input) = block.as_tool_use().unwrap();
+        assert_eq!(id, "call_1");
+        assert_eq!(input["rows"][9999]["label"], "row 9999");
+
+        let back = RawToolUse::from_block(&block).unwrap();
+        assert_eq!(serde_json::to_string(&back).unwrap(), json);
+        assert!(RawToolUse::from_block(&ContentBlock::text("hi")).is_none());
+    }
+
+    #[test]
+    fn test_created_at() {
+        let msg = InternalMessage::user("Hi").with_created_at(1_700_000_000_000);
+        let json = serde_json::to_value(&msg).unwrap();
+        assert_eq!(json["created_at"], 1_700_000_000_000u64);
+        let restored: InternalMessage = serde_json::from_value(json).unwrap();
+        assert_eq!(restored.created_at, Some(1_700_000_000_000));
+
+        let unset = serde_json::to_value(InternalMessage::user("Hi")).unwrap();
+        assert!(unset.get("created_at").is_none());
+
+        // Provider converters ignore it
+        let openai = crate::to_openai_message(&msg);
+        assert!(openai.get("created_at").is_none());
+    }
 }
@@ -0,0 +1,431 @@
+//! Lightweight operation dispatch over JSON values.
+//!
+//! This module exposes the core UMF operations (message creation, ChatML
+//! rendering, token counting, ...) behind a single uniform entry point,
+//! [`apply_operation`], without requiring any protocol-level dependency.
+//! Inputs and outputs are plain [`serde_json::Value`]s so the dispatcher can
+//! be driven from configuration, RPC handlers, or plugin boundaries.
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use umf::{apply_operation, Operation};
+//!
+//! let msg = apply_operation(
+//!     Operation::CreateUserMessage,
+//!     serde_json::json!({"content": "Hello!"}),
+//! )
+//! .unwrap();
+//! assert_eq!(msg["role"], "user");
+//! ```
+
+use crate::chatml::{ChatMLFormatter, ChatMLMessage};
+use crate::providers::{
+    from_anthropic_request, from_gemini_request, from_openai_messages, to_anthropic_request,
+    to_gemini_request,
+};
+use crate::{Conversation, InternalMessage, TokenCounter, UmfError};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+
+/// Operations supported by [`apply_operation`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Operation {
+    /// Create a system message from `{"content"}`
+    CreateSystemMessage,
+    /// Create a user message from `{"content"}`
+    CreateUserMessage,
+    /// Create an assistant message from `{"content"}`
+    CreateAssistantMessage,
+    /// Create a tool result message from `{"tool_call_id", "name", "content"}`
+    CreateToolResult,
+    /// Parse and normalize a single message from `{"message"}`
+    ParseMessage,
+    /// Render `{"messages"}` as a ChatML string
+    ToChatml,
+    /// Convert `{"messages"}` to an array of ChatML message objects
+    ToChatmlConversation,
+    /// Parse a `{"messages"}` array of ChatML message objects into UMF messages
+    FromChatmlConversation,
+    /// Convert `{"messages"}` to OpenAI API message dictionaries
+    ToOpenai,
+    /// Convert `{"messages"}` to an Anthropic Messages request body
+    ToAnthropic,
+    /// Convert `{"messages"}` to a Gemini `generateContent` request body
+    ToGemini,
+    /// Parse an OpenAI `{"messages"}` array into UMF messages
+    FromOpenai,
+    /// Parse an Anthropic request body (`{"system"?, "messages"}`) into UMF messages
+    FromAnthropic,
+    /// Parse a Gemini request body (`{"systemInstruction"?, "contents"}`) into UMF messages
+    FromGemini,
+    /// Count cl100k tokens of `{"text"}` or the ChatML rendering of `{"messages"}`
+    CountTokens,
+    /// Fold all system messages in `{"messages"}` into one leading message
+    MergeSystemMessages,
+    /// Validate the structure of `{"messages"}`, returning per-message errors
+    ValidateConversation,
+    /// Redact regex `{"patterns"}` matches from `{"message"}`
+    RedactMessage,
+    /// Count cl100k tokens of each message's ChatML rendering in `{"messages"}`
+    TokenBreakdown,
+    /// Check whether `{"messages"}` fit `{"max_tokens"}` minus an optional
+    /// `{"reserve_output"}`, and which messages to drop if not
+    PlanContextFit,
+}
+
+impl Operation {
+    /// Convert to string representation
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::CreateSystemMessage => "create-system-message",
+            Self::CreateUserMessage => "create-user-message",
+            Self::CreateAssistantMessage => "create-assistant-message",
+            Self::CreateToolResult => "create-tool-result",
+            Self::ParseMessage => "parse-message",
+            Self::ToChatml => "to-chatml",
+            Self::ToChatmlConversation => "to-chatml-conversation",
+            Self::FromChatmlConversation => "from-chatml-conversation",
+            Self::ToOpenai => "to-openai",
+            Self::ToAnthropic => "to-anthropic",
+            Self::ToGemini => "to-gemini",
+            Self::FromOpenai => "from-openai",
+            Self::FromAnthropic => "from-anthropic",
+            Self::FromGemini => "from-gemini",
+            Self::CountTokens => "count-tokens",
+            Self::MergeSystemMessages => "merge-system-messages",
+            Self::ValidateConversation => "validate-conversation",
+            Self::RedactMessage => "redact-message",
+            Self::TokenBreakdown => "token-breakdown",
+            Self::PlanContextFit => "plan-context-fit",
+        }
+    }
+
+    /// Whether [`OperationCache`] stores results of this operation
+    ///
+    /// Every operation is deterministic; only those that render or tokenize
+    /// a whole conversation are costly enough to be worth caching.
+    pub fn is_cacheable(&self) -> bool {
+        matches!(
+            self,
+            Self::ToChatml | Self::CountTokens | Self::TokenBreakdown | Self::PlanContextFit
+        )
+    }
+}
+
+impl std::fmt::Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Apply an operation to a JSON input and return its JSON output
+///
+/// Message-producing operations return the serialized [`InternalMessage`];
+/// conversation operations return an object (`{"chatml"}`, `{"messages"}`,
+/// `{"tokens"}`), except `validate-conversation` which returns an array of
+/// `{"index", "errors"}` entries (empty when valid) and `token-breakdown`
+/// which returns `{"breakdown": [{"index", "role", "tokens"}], "total"}`.
+/// `plan-context-fit` returns `{"fits", "total_tokens", "drop_indices"}`,
+/// where the indices are the messages [`Conversation::window`] would drop.
+/// `to-anthropic` and `to-gemini` return the provider request body, and the
+/// `from-*` operations return `{"messages"}` in UMF form. The
+/// `*-chatml-conversation` operations convert a whole `{"messages"}` array
+/// in one call.
+///
+/// With the `tracing` feature, each call runs in an `umf_operation` span
+/// recording the `operation` name and whether it succeeded (`success`).
+pub fn apply_operation(op: Operation, input: Value) -> Result<Value, UmfError> {
+    #[cfg(feature = "tracing")]
+    let span = tracing::info_span!(
+        "umf_operation",
+        operation = op.as_str(),
+        success = tracing::field::Empty
+    );
+    #[cfg(feature = "tracing")]
+    let _entered = span.enter();
+
+    let result = dispatch(op, input);
+
+    #[cfg(feature = "tracing")]
+    span.record("success", result.is_ok());
+
+    result
+}
+
+/// Bounded LRU cache of [`apply_operation`] results
+///
+/// Results of [cacheable](Operation::is_cacheable) operations are keyed by
+/// the operation and a hash of the input; the input itself is kept to rule
+/// out hash collisions. Other operations and errors are never cached. Once
+/// `capacity` entries are held, the least recently used one is evicted.
+#[derive(Debug, Clone, Default)]
+pub struct OperationCache {
+    capacity: usize,
+    entries: HashMap<(Operation, u64), (Value, Value)>,
+    order: VecDeque<(Operation, u64)>,
+    hits: usize,
+}
+
+impl OperationCache {
+    /// Create a cache holding at most `capacity` results (0 disables caching)
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            ..Self::default()
+        }
+    }
+
+    /// Apply an operation, answering from the cache when possible
+    pub fn apply(&mut self, op: Operation, input: Value) -> Result<Value, UmfError> {
+        if !op.is_cacheable() || self.capacity == 0 {
+            return apply_operation(op, input);
+        }
+
+        let key = (op, crate::hash_json(&input));
+        if let Some((cached_input, output)) = self.entries.get(&key) {
+            if *cached_input == input {
+                let output = output.clone();
+                self.hits += 1;
+                self.touch(key);
+                return Ok(output);
+            }
+        }
+
+        let output = apply_operation(op, input.clone())?;
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(key, (input, output.clone()));
+        self.touch(key);
+        Ok(output)
+    }
+
+    /// Number of calls answered from the cache
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// Number of cached results
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check if no results are cached
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Drop all cached results
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+
+    /// Mark `key` as most recently used
+    fn touch(&mut self, key: (Operation, u64)) {
+        self.order.retain(|k| *k != key);
+        self.order.push_back(key);
+    }
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Number of `CountTokens` dispatches on the current thread
+    pub(crate) static COUNT_TOKENS_CALLS: std::cell::Cell<usize> =
+        const { std::cell::Cell::new(0) };
+}
+
+/// Run an operation, moving payload fields out of `input` rather than cloning
+fn dispatch(op: Operation, mut input: Value) -> Result<Value, UmfError> {
+    match op {
+        Operation::CreateSystemMessage => {
+            let content = string_field(&mut input, "content")?;
+            Ok(serde_json::to_value(InternalMessage::system(content))?)
+        }
+        Operation::CreateUserMessage => {
+            let content = string_field(&mut input, "content")?;
+            Ok(serde_json::to_value(InternalMessage::user(content))?)
+        }
+        Operation::CreateAssistantMessage => {
+            let content = string_field(&mut input, "content")?;
+            Ok(serde_json::to_value(InternalMessage::assistant(content))?)
+        }
+        Operation::CreateToolResult => {
+            let msg = InternalMessage::tool_result(
+                string_field(&mut input, "tool_call_id")?,
+                string_field(&mut input, "name")?,
+                string_field(&mut input, "content")?,
+            );
+            Ok(serde_json::to_value(msg)?)
+        }
+        Operation::ParseMessage => Ok(serde_json::to_value(message_field(&mut input)?)?),
+        Operation::ToChatml => {
+            let messages = messages_field(&mut input)?;
+            Ok(json!({ "chatml": render_chatml(&messages) }))
+        }
+        Operation::ToChatmlConversation => {
+            let formatter = ChatMLFormatter::from_internal(&messages_field(&mut input)?);
+            Ok(json!({ "messages": formatter.get_messages() }))
+        }
+        Operation::FromChatmlConversation => {
+            let formatter = ChatMLFormatter::from_json(json!({
+                "messages": take_field(&mut input, "messages")?,
+            }))?;
+            Ok(json!({ "messages": formatter.to_internal_messages() }))
+        }
+        Operation::ToOpenai => {
+            let messages = messages_field(&mut input)?;
+            let dicts: Vec<_> = messages
+                .iter()
+                .map(|m| ChatMLMessage::from(m).to_dict())
+                .collect();
+            Ok(json!({ "messages": dicts }))
+        }
+        Operation::ToAnthropic => Ok(to_anthropic_request(&messages_field(&mut input)?)),
+        Operation::ToGemini => Ok(to_gemini_request(&messages_field(&mut input)?)),
+        Operation::FromOpenai => {
+            let messages = from_openai_messages(&take_field(&mut input, "messages")?)?;
+            Ok(json!({ "messages": messages }))
+        }
+        Operation::FromAnthropic => Ok(json!({ "messages": from_anthropic_request(&input)? })),
+        Operation::FromGemini => Ok(json!({ "messages": from_gemini_request(&input)? })),
+        Operation::CountTokens => {
+            #[cfg(test)]
+            COUNT_TOKENS_CALLS.with(|calls| calls.set(calls.get() + 1));
+            let text = match input.get("text") {
+                Some(_) => string_field(&mut input, "text")?,
+                None => render_chatml(&messages_field(&mut input)?),
+            };
+            let tokens = TokenCounter::new()?.count_text(&text);
+            Ok(json!({ "tokens": tokens }))
+        }
+        Operation::MergeSystemMessages => {
+            let mut messages = messages_field(&mut input)?;
+            crate::merge_system_messages(&mut messages);
+            Ok(json!({ "messages": messages }))
+        }
+        Operation::ValidateConversation => {
+            let messages = messages_field(&mut input)?;
+            Ok(serde_json::to_value(crate::validate_conversation(&messages))?)
+        }
+        Operation::RedactMessage => {
+            let mut message = message_field(&mut input)?;
+            let patterns = take_field(&mut input, "patterns")?;
+            let sources: Vec<String> = serde_json::from_value(patterns)?;
+            let patterns = sources
+                .iter()
+                .map(|p| {
+                    regex::Regex::new(p).map_err(|e| {
+                        UmfError::InvalidInput(format!("invalid pattern `{}`: {}", p, e))
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            message.redact(&patterns);
+            Ok(serde_json::to_value(message)?)
+        }
+        Operation::TokenBreakdown => {
+            let messages = messages_field(&mut input)?;
+            let counter = TokenCounter::new()?;
+            let mut total = 0;
+            let breakdown: Vec<_> = messages
+                .iter()
+                .enumerate()
+                .map(|(index, msg)| {
+                    let tokens = counter.count_text(&ChatMLMessage::from(msg).to_chatml_string());
+                    total += tokens;
+                    json!({ "index": index, "role": msg.role.as_str(), "tokens": tokens })
+                })
+                .collect();
+            Ok(json!({ "breakdown": breakdown, "total": total }))
+        }
+        Operation::PlanContextFit => {
+            let conversation = Conversation::from(messages_field(&mut input)?);
+            let max_tokens = usize_field(&input, "max_tokens")?;
+            let reserve_output = match input.get("reserve_output") {
+                Some(_) => usize_field(&input, "reserve_output")?,
+                None => 0,
+            };
+            let budget = max_tokens.saturating_sub(reserve_output);
+
+            let counter = TokenCounter::new()?;
+            let total_tokens: usize = conversation
+                .messages()
+                .iter()
+                .map(|m| counter.count_message(m))
+                .sum();
+            let fits = total_tokens <= budget;
+            let drop_indices: Vec<usize> = if fits {
+                Vec::new()
+            } else {
+                let kept = conversation.window_indices(budget, &counter);
+                (0..conversation.len()).filter(|i| !kept.contains(i)).collect()
+            };
+
+            Ok(json!({
+                "fits": fits,
+                "total_tokens": total_tokens,
+                "drop_indices": drop_indices,
+            }))
+        }
+    }
+}
+
+fn missing(field: &str) -> UmfError {
+    UmfError::InvalidInput(format!("missing field `{}`", field))
+}
+
+/// Take a required string field from the input object
+fn string_field(input: &mut Value, field: &str) -> Result<String, UmfError> {
+    match input.get_mut(field) {
+        Some(Value::String(s)) => Ok(std::mem::take(s)),
+        Some(_) => Err(UmfError::InvalidInput(format!(
+            "field `{}` must be a string",
+            field
+        ))),
+        None => Err(missing(field)),
+    }
+}
+
+/// Get a required non-negative integer field from the input object
+fn usize_field(input: &Value, field: &str) -> Result<usize, UmfError> {
+    match input.get(field) {
+        Some(value) => value.as_u64().map(|n| n as usize).ok_or_else(|| {
+            UmfError::InvalidInput(format!("field `{}` must be a non-negative integer", field))
+        }),
+        None => Err(missing(field)),
+    }
+}
+
+/// Move a required field out of the input object
+fn take_field(input: &mut Value, field: &str) -> Result<Value, UmfError> {
+    input
+        .get_mut(field)
+        .map(Value::take)
+        .ok_or_else(|| missing(field))
+}
+
+/// Take the required `message` object from the input object
+fn message_field(input: &mut Value) -> Result<InternalMessage, UmfError> {
+    Ok(serde_json::from_value(take_field(input, "message")?)?)
+}
+
+/// Take the required `messages` array from the input object
+fn messages_field(input: &mut Value) -> Result<Vec<InternalMessage>, UmfError> {
+    Ok(serde_json::from_value(take_field(input, "messages")?)?)
+}
+
+fn render_chatml(messages: &[InternalMessage]) -> String {
+    messages
+        .iter()
+        .map(|m| ChatMLMessage::from(m).to_chatml_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests;
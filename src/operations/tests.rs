@@ -0,0 +1,382 @@
+//! Tests for operation dispatch
+
+use super::*;
+
+#[test]
+fn test_create_user_message() {
+    let output = apply_operation(
+        Operation::CreateUserMessage,
+        json!({"content": "Hello, world!"}),
+    )
+    .unwrap();
+
+    assert_eq!(output["role"], "user");
+    assert_eq!(output["content"], "Hello, world!");
+
+    let msg: InternalMessage = serde_json::from_value(output).unwrap();
+    assert_eq!(msg.text(), Some("Hello, world!"));
+}
+
+#[test]
+fn test_create_tool_result() {
+    let output = apply_operation(
+        Operation::CreateToolResult,
+        json!({"tool_call_id": "call_1", "name": "search", "content": "found"}),
+    )
+    .unwrap();
+
+    assert_eq!(output["role"], "tool");
+    assert_eq!(output["tool_call_id"], "call_1");
+    assert_eq!(output["name"], "search");
+}
+
+#[test]
+fn test_count_tokens() {
+    let output = apply_operation(Operation::CountTokens, json!({"text": "Hello world"})).unwrap();
+    assert_eq!(output["tokens"], 2);
+
+    let messages = vec![InternalMessage::user("Hello world")];
+    let output =
+        apply_operation(Operation::CountTokens, json!({ "messages": messages })).unwrap();
+    assert!(output["tokens"].as_u64().unwrap() > 2);
+}
+
+#[test]
+fn test_token_breakdown() {
+    let messages = vec![
+        InternalMessage::system("You are a helpful assistant."),
+        InternalMessage::user("Summarize the plot of Hamlet in one sentence."),
+        InternalMessage::assistant("A prince avenges his father, and nearly everyone dies."),
+    ];
+    let output =
+        apply_operation(Operation::TokenBreakdown, json!({ "messages": messages })).unwrap();
+
+    let breakdown = output["breakdown"].as_array().unwrap();
+    assert_eq!(breakdown.len(), 3);
+    assert_eq!(breakdown[1]["index"], 1);
+    assert_eq!(breakdown[1]["role"], "user");
+
+    let sum: u64 = breakdown.iter().map(|e| e["tokens"].as_u64().unwrap()).sum();
+    assert!(sum > 0);
+    assert_eq!(output["total"], sum);
+}
+
+#[test]
+fn test_plan_context_fit() {
+    let messages = vec![
+        InternalMessage::system("You are a helpful assistant."),
+        InternalMessage::user("Tell me a long story about a dragon and a knight."),
+        InternalMessage::assistant("Once upon a time, a dragon guarded a mountain of gold."),
+        InternalMessage::user("Shorter, please."),
+    ];
+
+    let output = apply_operation(
+        Operation::PlanContextFit,
+        json!({ "messages": messages, "max_tokens": 1000, "reserve_output": 200 }),
+    )
+    .unwrap();
+    assert_eq!(output["fits"], true);
+    assert_eq!(output["drop_indices"], json!([]));
+    let total = output["total_tokens"].as_u64().unwrap();
+
+    let output = apply_operation(
+        Operation::PlanContextFit,
+        json!({ "messages": messages, "max_tokens": total + 10, "reserve_output": 20 }),
+    )
+    .unwrap();
+    assert_eq!(output["fits"], false);
+    assert_eq!(output["total_tokens"], total);
+    let dropped = output["drop_indices"].as_array().unwrap();
+    assert!(!dropped.is_empty());
+    assert!(!dropped.contains(&json!(0)));
+    assert!(!dropped.contains(&json!(3)));
+
+    let err = apply_operation(Operation::PlanContextFit, json!({ "messages": messages }))
+        .unwrap_err();
+    assert!(matches!(err, UmfError::InvalidInput(_)));
+}
+
+#[test]
+fn test_parse_message_large_payload() {
+    let data = "A".repeat(4 * 1024 * 1024);
+    let message = json!({
+        "role": "user",
+        "content": [
+            {"type": "text", "text": "What is in this image?"},
+            {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": data}}
+        ]
+    });
+
+    let output = apply_operation(Operation::ParseMessage, json!({ "message": message })).unwrap();
+    assert_eq!(output, message);
+
+    let output = apply_operation(Operation::CreateUserMessage, json!({ "content": data })).unwrap();
+    assert_eq!(output["content"].as_str().map(str::len), Some(data.len()));
+}
+
+#[test]
+fn test_to_chatml() {
+    let messages = vec![
+        InternalMessage::system("Be brief"),
+        InternalMessage::user("Hi"),
+    ];
+    let output = apply_operation(Operation::ToChatml, json!({ "messages": messages })).unwrap();
+
+    assert_eq!(
+        output["chatml"],
+        "<|im_start|>system\nBe brief\n<|im_end|>\n<|im_start|>user\nHi\n<|im_end|>"
+    );
+}
+
+#[test]
+fn test_chatml_conversation_roundtrip() {
+    let messages = vec![
+        InternalMessage::user("Weather in Paris?"),
+        InternalMessage::assistant_with_tools(
+            "Checking.",
+            vec![crate::ContentBlock::tool_use(
+                "call_1",
+                "get_weather",
+                json!({"city": "Paris"}),
+            )],
+        ),
+        InternalMessage::tool_result("call_1", "get_weather", "Sunny"),
+    ];
+
+    let chatml = apply_operation(
+        Operation::ToChatmlConversation,
+        json!({ "messages": messages }),
+    )
+    .unwrap();
+    assert_eq!(chatml["messages"].as_array().map(Vec::len), Some(3));
+    assert_eq!(chatml["messages"][1]["tool_calls"][0]["function"]["name"], "get_weather");
+    assert_eq!(chatml["messages"][2]["tool_call_id"], "call_1");
+
+    let output = apply_operation(Operation::FromChatmlConversation, chatml).unwrap();
+    let restored: Vec<InternalMessage> =
+        serde_json::from_value(output["messages"].clone()).unwrap();
+    assert_eq!(restored.len(), messages.len());
+    for (restored, original) in restored.iter().zip(&messages) {
+        assert!(restored.eq_ignoring_metadata(original));
+    }
+}
+
+#[test]
+fn test_to_anthropic() {
+    let messages = vec![
+        InternalMessage::system("Be brief"),
+        InternalMessage::user("Weather in Paris?"),
+        InternalMessage::assistant_with_tools(
+            "Checking",
+            vec![crate::ContentBlock::tool_use("call_1", "weather", json!({"city": "Paris"}))],
+        ),
+        InternalMessage::tool_result("call_1", "weather", "Sunny"),
+    ];
+    let output = apply_operation(Operation::ToAnthropic, json!({ "messages": messages })).unwrap();
+
+    assert_eq!(output["system"], "Be brief");
+    let sent = output["messages"].as_array().unwrap();
+    assert_eq!(sent.len(), 3);
+    assert_eq!(sent[1]["role"], "assistant");
+    assert_eq!(sent[1]["content"][1]["type"], "tool_use");
+    assert_eq!(sent[1]["content"][1]["input"]["city"], "Paris");
+    assert_eq!(sent[2]["role"], "user");
+    assert_eq!(sent[2]["content"][0]["type"], "tool_result");
+    assert_eq!(sent[2]["content"][0]["tool_use_id"], "call_1");
+
+    // Parses back to the same conversation shape
+    let parsed = apply_operation(Operation::FromAnthropic, output).unwrap();
+    let parsed: Vec<InternalMessage> = serde_json::from_value(parsed["messages"].clone()).unwrap();
+    assert_eq!(parsed.len(), 4);
+    assert_eq!(parsed[3].role, crate::MessageRole::Tool);
+    assert_eq!(parsed[3].tool_call_id.as_deref(), Some("call_1"));
+}
+
+#[test]
+fn test_gemini_and_openai_transforms() {
+    let messages = vec![InternalMessage::system("Be brief"), InternalMessage::user("Hi")];
+    let output = apply_operation(Operation::ToGemini, json!({ "messages": messages })).unwrap();
+    assert!(output["contents"].is_array());
+    let parsed = apply_operation(Operation::FromGemini, output).unwrap();
+    assert_eq!(parsed["messages"], json!(messages));
+
+    let openai = json!({"messages": [{"role": "user", "content": "Hi"}]});
+    let parsed = apply_operation(Operation::FromOpenai, openai).unwrap();
+    assert_eq!(parsed["messages"][0]["role"], "user");
+
+    let err = apply_operation(Operation::FromAnthropic, json!({})).unwrap_err();
+    assert!(matches!(err, UmfError::Conversion(_)));
+}
+
+#[test]
+fn test_validate_conversation_reports_orphaned_tool_result() {
+    let messages = vec![
+        InternalMessage::user("Hi"),
+        InternalMessage::assistant("Hello"),
+        InternalMessage::tool_result("call_missing", "search", "Result"),
+    ];
+    let output = apply_operation(
+        Operation::ValidateConversation,
+        json!({ "messages": messages }),
+    )
+    .unwrap();
+
+    let report = output.as_array().unwrap();
+    assert_eq!(report.len(), 1);
+    assert_eq!(report[0]["index"], 2);
+    assert!(report[0]["errors"][0]
+        .as_str()
+        .unwrap()
+        .contains("call_missing"));
+
+    let valid = vec![InternalMessage::user("Hi")];
+    let output =
+        apply_operation(Operation::ValidateConversation, json!({ "messages": valid })).unwrap();
+    assert_eq!(output, json!([]));
+}
+
+#[test]
+fn test_redact_message() {
+    let message = InternalMessage::user("Contact me at jane.doe@example.com please");
+    let output = apply_operation(
+        Operation::RedactMessage,
+        json!({
+            "message": message,
+            "patterns": [r"[\w.+-]+@[\w-]+\.[\w.]+"]
+        }),
+    )
+    .unwrap();
+
+    assert_eq!(output["role"], "user");
+    assert_eq!(output["content"], "Contact me at [REDACTED] please");
+
+    let err = apply_operation(
+        Operation::RedactMessage,
+        json!({"message": message, "patterns": ["("]}),
+    )
+    .unwrap_err();
+    assert!(matches!(err, UmfError::InvalidInput(_)));
+}
+
+#[test]
+fn test_missing_field_is_invalid_input() {
+    let err = apply_operation(Operation::CreateUserMessage, json!({})).unwrap_err();
+    assert!(matches!(err, UmfError::InvalidInput(_)));
+
+    let err = apply_operation(Operation::CreateUserMessage, json!({"content": 1})).unwrap_err();
+    assert!(matches!(err, UmfError::InvalidInput(_)));
+}
+
+#[test]
+fn test_operation_names() {
+    assert_eq!(Operation::CountTokens.as_str(), "count-tokens");
+    let op: Operation = serde_json::from_value(json!("create-user-message")).unwrap();
+    assert_eq!(op, Operation::CreateUserMessage);
+}
+
+#[test]
+fn test_operation_cache() {
+    let mut cache = OperationCache::new(1);
+    let input = json!({"text": "Hello world"});
+
+    let calls = || COUNT_TOKENS_CALLS.with(std::cell::Cell::get);
+    let before = calls();
+    let first = cache.apply(Operation::CountTokens, input.clone()).unwrap();
+    let second = cache.apply(Operation::CountTokens, input).unwrap();
+    assert_eq!(first, second);
+    assert_eq!(cache.hits(), 1);
+    assert_eq!(calls() - before, 1);
+
+    // Non-cacheable operations bypass the cache
+    cache.apply(Operation::CreateUserMessage, json!({"content": "Hi"})).unwrap();
+    assert_eq!(cache.len(), 1);
+
+    // A new input evicts the least recently used result
+    cache.apply(Operation::CountTokens, json!({"text": "Goodbye"})).unwrap();
+    cache.apply(Operation::CountTokens, json!({"text": "Hello world"})).unwrap();
+    assert_eq!(cache.hits(), 1);
+    assert_eq!(cache.len(), 1);
+    assert_eq!(calls() - before, 3);
+}
+
+#[cfg(feature = "tracing")]
+mod tracing_spans {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+
+    /// Recorded `(span name, field, value)` triples
+    type Recorded = Arc<Mutex<Vec<(String, String, String)>>>;
+
+    struct FieldRecorder<'a> {
+        span: &'a str,
+        recorded: &'a Recorded,
+    }
+
+    impl Visit for FieldRecorder<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            let value = format!("{:?}", value).trim_matches('"').to_string();
+            self.recorded.lock().unwrap().push((
+                self.span.to_string(),
+                field.name().to_string(),
+                value,
+            ));
+        }
+    }
+
+    /// Minimal subscriber capturing span fields
+    struct SpanRecorder {
+        names: Mutex<Vec<&'static str>>,
+        recorded: Recorded,
+    }
+
+    impl tracing::Subscriber for SpanRecorder {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, attrs: &Attributes<'_>) -> Id {
+            let mut names = self.names.lock().unwrap();
+            names.push(attrs.metadata().name());
+            let span = names.last().unwrap();
+            attrs.record(&mut FieldRecorder { span, recorded: &self.recorded });
+            Id::from_u64(names.len() as u64)
+        }
+
+        fn record(&self, id: &Id, values: &Record<'_>) {
+            let span = self.names.lock().unwrap()[id.into_u64() as usize - 1];
+            values.record(&mut FieldRecorder { span, recorded: &self.recorded });
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, _: &tracing::Event<'_>) {}
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn test_apply_operation_emits_span() {
+        let recorded = Recorded::default();
+        let subscriber = SpanRecorder {
+            names: Mutex::new(Vec::new()),
+            recorded: recorded.clone(),
+        };
+
+        tracing::subscriber::with_default(subscriber, || {
+            apply_operation(Operation::CreateUserMessage, json!({"content": "Hi"})).unwrap();
+            apply_operation(Operation::CreateUserMessage, json!({})).unwrap_err();
+        });
+
+        let recorded = recorded.lock().unwrap();
+        let field = |name: &str| -> Vec<&str> {
+            recorded
+                .iter()
+                .filter(|(span, field, _)| span == "umf_operation" && field == name)
+                .map(|(_, _, value)| value.as_str())
+                .collect()
+        };
+        assert_eq!(field("operation"), ["create-user-message", "create-user-message"]);
+        assert_eq!(field("success"), ["true", "false"]);
+    }
+}
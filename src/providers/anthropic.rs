@@ -0,0 +1,429 @@
+//! Anthropic Messages conversion
+
+use super::{report_dropped_raw_blocks, required_str, ConversionError};
+use crate::{ContentBlock, ImageSource, InternalMessage, MessageContent, MessageRole};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+/// Text substituted for empty content by [`AnthropicEmptyPolicy::PlaceholderText`]
+const EMPTY_PLACEHOLDER: &str = "(empty)";
+
+/// How [`to_anthropic_request_with_policy`] handles messages with empty content
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AnthropicEmptyPolicy {
+    /// Omit the message
+    #[default]
+    Drop,
+    /// Replace the content with a short placeholder text
+    PlaceholderText,
+}
+
+/// Parse a single Anthropic message into an [`InternalMessage`]
+///
+/// Supports string and block array content. `text`, `image`, `tool_use` and
+/// `tool_result` blocks map to their UMF equivalents (tool result content
+/// given as text blocks is joined with newlines); any other block type, such
+/// as `thinking`, is kept as a raw `anthropic` block. Extra block fields such
+/// as `cache_control` or `citations` are kept as block extensions.
+pub fn from_anthropic_message(value: &Value) -> Result<InternalMessage, ConversionError> {
+    parse_message(value, "anthropic message")
+}
+
+/// Parse an Anthropic request body (`{"system"?, "messages": [...]}`)
+///
+/// A `system` prompt (string or text blocks) becomes a leading system
+/// message. A `user` message made only of `tool_result` blocks becomes one
+/// tool message per result, named after the matching earlier `tool_use`.
+/// Errors name the offending message as `messages[i]`.
+pub fn from_anthropic_request(value: &Value) -> Result<Vec<InternalMessage>, ConversionError> {
+    let messages = value
+        .get("messages")
+        .ok_or_else(|| ConversionError::missing("messages", "anthropic request"))?;
+    let items = messages
+        .as_array()
+        .ok_or_else(|| ConversionError::type_mismatch("messages", "array", messages))?;
+
+    let mut result = Vec::with_capacity(items.len() + 1);
+    match value.get("system") {
+        None | Some(Value::Null) => {}
+        Some(Value::String(text)) => result.push(InternalMessage::system(text.clone())),
+        Some(Value::Array(blocks)) => {
+            let texts = blocks
+                .iter()
+                .map(|block| required_str(block, "text", "system block"))
+                .collect::<Result<Vec<_>, _>>()?;
+            result.push(InternalMessage::system(texts.join("\n")));
+        }
+        Some(other) => {
+            return Err(ConversionError::type_mismatch(
+                "system",
+                "string or array",
+                other,
+            ))
+        }
+    }
+
+    let mut tool_names = HashMap::new();
+    for (i, item) in items.iter().enumerate() {
+        let msg = parse_message(item, &format!("messages[{}]", i))?;
+        let blocks = msg.blocks().unwrap_or_default();
+        for block in blocks {
+            if let Some((id, name, _)) = block.as_tool_use() {
+                tool_names.insert(id.to_string(), name.to_string());
+            }
+        }
+        if msg.role == MessageRole::User
+            && !blocks.is_empty()
+            && blocks.iter().all(|b| b.as_tool_result().is_some())
+        {
+            for block in blocks {
+                let Some((id, content)) = block.as_tool_result() else {
+                    continue;
+                };
+                let name = tool_names.get(id).map(String::as_str).unwrap_or_default();
+                result.push(if block.is_error_result() {
+                    InternalMessage::tool_error(id, name, content)
+                } else {
+                    InternalMessage::tool_result(id, name, content)
+                });
+            }
+            continue;
+        }
+        result.push(msg);
+    }
+    Ok(result)
+}
+
+/// Convert an [`InternalMessage`] into an Anthropic message
+///
+/// Text messages keep string content; block messages use the block array
+/// form, without whitespace-only text blocks and with block extensions
+/// re-emitted as fields. Tool messages become `user` messages with a single
+/// `tool_result` block. Audio output is sent as its transcript text, if
+/// any. Raw blocks are only emitted when their provider is `"anthropic"`;
+/// see [`to_anthropic_message_with_hook`]. Anthropic has no system role in
+/// `messages`: use [`to_anthropic_request`] to move system messages into
+/// `system`.
+pub fn to_anthropic_message(msg: &InternalMessage) -> Value {
+    if msg.role == MessageRole::Tool {
+        return json!({ "role": "user", "content": tool_result_blocks(msg) });
+    }
+
+    let content = match &msg.content {
+        MessageContent::Text(text) => json!(text),
+        MessageContent::Blocks(blocks) => Value::Array(
+            blocks
+                .iter()
+                .filter(|b| !b.as_text().is_some_and(|text| text.trim().is_empty()))
+                .filter_map(block_to_anthropic)
+                .collect(),
+        ),
+    };
+    json!({ "role": msg.role.as_str(), "content": content })
+}
+
+/// Convert an [`InternalMessage`] into an Anthropic message, reporting
+/// dropped raw blocks
+///
+/// Like [`to_anthropic_message`], but calls `on_drop` with the provider and
+/// value of each raw block left out because it belongs to another provider.
+pub fn to_anthropic_message_with_hook<F>(msg: &InternalMessage, on_drop: F) -> Value
+where
+    F: FnMut(&str, &Value),
+{
+    report_dropped_raw_blocks([msg], "anthropic", on_drop);
+    to_anthropic_message(msg)
+}
+
+/// Convert messages into an Anthropic request body
+///
+/// System messages are joined with newlines into the top-level `system`
+/// field. Consecutive tool messages are folded into one `user` message with
+/// a `tool_result` block each, as Anthropic expects all results answering an
+/// assistant turn in the next user turn. Messages left without content are
+/// dropped; see [`to_anthropic_request_with_policy`].
+pub fn to_anthropic_request(messages: &[InternalMessage]) -> Value {
+    to_anthropic_request_with_policy(messages, AnthropicEmptyPolicy::default())
+}
+
+/// Convert messages into an Anthropic request body, reporting dropped raw
+/// blocks
+///
+/// Like [`to_anthropic_request_with_policy`], but calls `on_drop` with the
+/// provider and value of each raw block left out because it belongs to
+/// another provider.
+pub fn to_anthropic_request_with_hook<F>(
+    messages: &[InternalMessage],
+    policy: AnthropicEmptyPolicy,
+    on_drop: F,
+) -> Value
+where
+    F: FnMut(&str, &Value),
+{
+    report_dropped_raw_blocks(messages, "anthropic", on_drop);
+    to_anthropic_request_with_policy(messages, policy)
+}
+
+/// Convert messages into an Anthropic request body, handling messages with
+/// empty content per `policy`
+///
+/// Anthropic rejects messages whose content is empty or only whitespace,
+/// e.g. an assistant message holding nothing but a blank text block.
+pub fn to_anthropic_request_with_policy(
+    messages: &[InternalMessage],
+    policy: AnthropicEmptyPolicy,
+) -> Value {
+    let mut system = Vec::new();
+    let mut converted: Vec<Value> = Vec::new();
+    let mut last_was_tool = false;
+
+    for msg in messages {
+        match msg.role {
+            MessageRole::System => system.push(msg.to_text()),
+            MessageRole::Tool if last_was_tool => {
+                if let Some(Value::Array(blocks)) = converted
+                    .last_mut()
+                    .and_then(|last| last.get_mut("content"))
+                {
+                    blocks.extend(tool_result_blocks(msg));
+                }
+            }
+            _ => {
+                let mut converted_msg = to_anthropic_message(msg);
+                if has_empty_content(&converted_msg) {
+                    match policy {
+                        AnthropicEmptyPolicy::Drop => continue,
+                        AnthropicEmptyPolicy::PlaceholderText => {
+                            converted_msg["content"] = json!(EMPTY_PLACEHOLDER);
+                        }
+                    }
+                }
+                converted.push(converted_msg);
+            }
+        }
+        if msg.role != MessageRole::System {
+            last_was_tool = msg.role == MessageRole::Tool;
+        }
+    }
+
+    let mut request = Map::new();
+    if !system.is_empty() {
+        request.insert("system".to_string(), json!(system.join("\n")));
+    }
+    request.insert("messages".to_string(), Value::Array(converted));
+    Value::Object(request)
+}
+
+/// Check if a converted message has empty or whitespace-only content
+fn has_empty_content(msg: &Value) -> bool {
+    match &msg["content"] {
+        Value::String(text) => text.trim().is_empty(),
+        Value::Array(blocks) => blocks.is_empty(),
+        _ => true,
+    }
+}
+
+/// `tool_result` blocks for a tool message
+fn tool_result_blocks(msg: &InternalMessage) -> Vec<Value> {
+    match &msg.content {
+        MessageContent::Text(text) => {
+            let mut block = json!({
+                "type": "tool_result",
+                "tool_use_id": msg.tool_call_id.as_deref().unwrap_or_default(),
+                "content": text,
+            });
+            if msg.is_error_result() {
+                block["is_error"] = json!(true);
+            }
+            vec![block]
+        }
+        MessageContent::Blocks(blocks) if blocks.iter().any(|b| b.as_tool_result().is_some()) => {
+            blocks.iter().filter_map(block_to_anthropic).collect()
+        }
+        MessageContent::Blocks(blocks) => vec![json!({
+            "type": "tool_result",
+            "tool_use_id": msg.tool_call_id.as_deref().unwrap_or_default(),
+            "content": blocks.iter().filter_map(block_to_anthropic).collect::<Vec<_>>(),
+        })],
+    }
+}
+
+fn block_to_anthropic(block: &ContentBlock) -> Option<Value> {
+    let mut converted = match block {
+        ContentBlock::Text { text, .. } => json!({ "type": "text", "text": text }),
+        ContentBlock::Image { source, .. } => {
+            let source = match source {
+                ImageSource::Base64 { media_type, data } => {
+                    json!({ "type": "base64", "media_type": media_type, "data": data })
+                }
+                ImageSource::Url { url } => json!({ "type": "url", "url": url }),
+            };
+            json!({ "type": "image", "source": source })
+        }
+        ContentBlock::Attachment {
+            file_id,
+            media_type,
+            ..
+        } => {
+            // Files API reference; images use an image block, anything else a document
+            let block_type = match media_type {
+                Some(media_type) if media_type.starts_with("image/") => "image",
+                _ => "document",
+            };
+            json!({ "type": block_type, "source": { "type": "file", "file_id": file_id } })
+        }
+        ContentBlock::AudioOutput { transcript, .. } => {
+            json!({ "type": "text", "text": transcript.as_deref()? })
+        }
+        ContentBlock::ToolUse {
+            id, name, input, ..
+        } => {
+            json!({ "type": "tool_use", "id": id, "name": name, "input": input })
+        }
+        ContentBlock::ToolResult {
+            tool_use_id,
+            content,
+            is_error,
+            ..
+        } => {
+            let mut block =
+                json!({ "type": "tool_result", "tool_use_id": tool_use_id, "content": content });
+            if *is_error {
+                block["is_error"] = json!(true);
+            }
+            block
+        }
+        ContentBlock::Raw { provider, value } => {
+            if provider != "anthropic" {
+                return None;
+            }
+            return Some(value.clone());
+        }
+    };
+    // Extras such as `cache_control` and `citations` are passed back as given
+    for (key, value) in block.extensions().into_iter().flatten() {
+        converted[key.as_str()] = value.clone();
+    }
+    Some(converted)
+}
+
+fn parse_message(value: &Value, context: &str) -> Result<InternalMessage, ConversionError> {
+    let obj = value
+        .as_object()
+        .ok_or_else(|| ConversionError::type_mismatch(context, "object", value))?;
+
+    let role_value = obj
+        .get("role")
+        .ok_or_else(|| ConversionError::missing("role", context))?;
+    let role_str = role_value
+        .as_str()
+        .ok_or_else(|| ConversionError::type_mismatch("role", "string", role_value))?;
+    let role: MessageRole = serde_json::from_value(role_value.clone())
+        .map_err(|_| ConversionError::InvalidRole(role_str.to_string()))?;
+
+    let content = match obj.get("content") {
+        Some(Value::String(text)) => MessageContent::Text(text.clone()),
+        Some(Value::Array(blocks)) => {
+            let blocks = blocks
+                .iter()
+                .map(parse_block)
+                .collect::<Result<Vec<_>, _>>()?;
+            match blocks.as_slice() {
+                [ContentBlock::Text { text, extensions }] if extensions.is_empty() => {
+                    MessageContent::Text(text.clone())
+                }
+                _ => MessageContent::Blocks(blocks),
+            }
+        }
+        Some(other) => {
+            return Err(ConversionError::type_mismatch(
+                "content",
+                "string or array",
+                other,
+            ))
+        }
+        None => return Err(ConversionError::missing("content", context)),
+    };
+
+    Ok(InternalMessage {
+        role,
+        content,
+        metadata: HashMap::new(),
+        tool_call_id: None,
+        name: None,
+        created_at: None,
+        hints: None,
+    })
+}
+
+fn parse_block(block: &Value) -> Result<ContentBlock, ConversionError> {
+    let block_type = required_str(block, "type", "content block")?;
+    let known_fields: &[&str] = match block_type {
+        "text" => &["type", "text"],
+        "image" => &["type", "source"],
+        "tool_use" => &["type", "id", "name", "input"],
+        "tool_result" => &["type", "tool_use_id", "content", "is_error"],
+        _ => return Ok(ContentBlock::raw("anthropic", block.clone())),
+    };
+    let mut parsed = parse_known_block(block, block_type)?;
+    if let Some(obj) = block.as_object() {
+        for (key, value) in obj {
+            if !known_fields.contains(&key.as_str()) {
+                parsed = parsed.with_extension(key.clone(), value.clone());
+            }
+        }
+    }
+    Ok(parsed)
+}
+
+/// Parse a `text`, `image`, `tool_use` or `tool_result` block
+fn parse_known_block(block: &Value, block_type: &str) -> Result<ContentBlock, ConversionError> {
+    match block_type {
+        "text" => Ok(ContentBlock::text(required_str(block, "text", "text block")?)),
+        "image" => {
+            let source = block
+                .get("source")
+                .ok_or_else(|| ConversionError::missing("source", "image block"))?;
+            let source = match required_str(source, "type", "image source")? {
+                "url" => ImageSource::Url {
+                    url: required_str(source, "url", "image source")?.to_string(),
+                },
+                _ => ImageSource::Base64 {
+                    media_type: required_str(source, "media_type", "image source")?.to_string(),
+                    data: required_str(source, "data", "image source")?.to_string(),
+                },
+            };
+            Ok(ContentBlock::image(source))
+        }
+        "tool_use" => Ok(ContentBlock::tool_use(
+            required_str(block, "id", "tool_use block")?,
+            required_str(block, "name", "tool_use block")?,
+            block.get("input").cloned().unwrap_or_else(|| json!({})),
+        )),
+        "tool_result" => {
+            let tool_use_id = required_str(block, "tool_use_id", "tool_result block")?;
+            let content = match block.get("content") {
+                None | Some(Value::Null) => String::new(),
+                Some(Value::String(text)) => text.clone(),
+                Some(Value::Array(parts)) => parts
+                    .iter()
+                    .filter_map(|part| part.get("text").and_then(Value::as_str))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                Some(other) => {
+                    return Err(ConversionError::type_mismatch(
+                        "content",
+                        "string or array",
+                        other,
+                    ))
+                }
+            };
+            if block.get("is_error").and_then(Value::as_bool) == Some(true) {
+                Ok(ContentBlock::tool_error(tool_use_id, content))
+            } else {
+                Ok(ContentBlock::tool_result(tool_use_id, content))
+            }
+        }
+        _ => Ok(ContentBlock::raw("anthropic", block.clone())),
+    }
+}
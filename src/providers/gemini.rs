@@ -0,0 +1,286 @@
+//! Google Gemini `generateContent` request conversion
+
+use super::{optional_str, report_dropped_raw_blocks, required_str, ConversionError};
+use crate::{ContentBlock, ImageSource, InternalMessage, MessageContent, MessageRole};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+/// Parse a Gemini request body (`{"systemInstruction"?, "contents": [...]}`)
+///
+/// `model` contents become assistant messages, with `functionCall` parts as
+/// tool use blocks. Gemini calls may have no `id`; one is then generated as
+/// `call_{i}_{j}` (content and part index) and reused for the next
+/// `functionResponse` with the same name. Each `functionResponse` part becomes
+/// a tool message: a `{"content": ...}` response yields its text, any other
+/// response is kept as JSON (see [`InternalMessage::tool_result_json`]).
+/// Unknown part types are kept as raw `gemini` blocks.
+pub fn from_gemini_request(value: &Value) -> Result<Vec<InternalMessage>, ConversionError> {
+    let contents = value
+        .get("contents")
+        .ok_or_else(|| ConversionError::missing("contents", "gemini request"))?;
+    let items = contents
+        .as_array()
+        .ok_or_else(|| ConversionError::type_mismatch("contents", "array", contents))?;
+
+    let mut messages = Vec::with_capacity(items.len() + 1);
+    if let Some(instruction) = value.get("systemInstruction") {
+        messages.push(InternalMessage::system(parts_text(instruction)?));
+    }
+
+    // Calls awaiting a response, as (name, id)
+    let mut pending: Vec<(String, String)> = Vec::new();
+    for (i, content) in items.iter().enumerate() {
+        let context = format!("contents[{}]", i);
+        let role = match content.get("role").and_then(Value::as_str) {
+            None | Some("user") => MessageRole::User,
+            Some("model") => MessageRole::Assistant,
+            Some(other) => return Err(ConversionError::InvalidRole(other.to_string())),
+        };
+        let parts = content
+            .get("parts")
+            .ok_or_else(|| ConversionError::missing("parts", &context))?;
+        let parts = parts
+            .as_array()
+            .ok_or_else(|| ConversionError::type_mismatch("parts", "array", parts))?;
+
+        let mut blocks = Vec::new();
+        for (j, part) in parts.iter().enumerate() {
+            if let Some(text) = part.get("text").and_then(Value::as_str) {
+                blocks.push(ContentBlock::text(text));
+            } else if let Some(call) = part.get("functionCall") {
+                let name = required_str(call, "name", "functionCall")?;
+                let id = match call.get("id").and_then(Value::as_str) {
+                    Some(id) => id.to_string(),
+                    None => format!("call_{}_{}", i, j),
+                };
+                pending.push((name.to_string(), id.clone()));
+                let args = call.get("args").cloned().unwrap_or_else(|| json!({}));
+                blocks.push(ContentBlock::tool_use(id, name, args));
+            } else if let Some(response) = part.get("functionResponse") {
+                let name = required_str(response, "name", "functionResponse")?;
+                let id = match response.get("id").and_then(Value::as_str) {
+                    Some(id) => {
+                        pending.retain(|(_, pending_id)| pending_id != id);
+                        id.to_string()
+                    }
+                    None => match pending.iter().position(|(n, _)| n == name) {
+                        Some(index) => pending.remove(index).1,
+                        None => format!("call_{}_{}", i, j),
+                    },
+                };
+                let result = response.get("response").cloned().unwrap_or(Value::Null);
+                messages.push(match result.get("content").and_then(Value::as_str) {
+                    Some(text) if result.as_object().is_some_and(|o| o.len() == 1) => {
+                        InternalMessage::tool_result(id, name, text)
+                    }
+                    _ => InternalMessage::tool_result_json(id, name, result),
+                });
+            } else if let Some(data) = part.get("inlineData") {
+                blocks.push(ContentBlock::image(ImageSource::Base64 {
+                    media_type: required_str(data, "mimeType", "inlineData")?.to_string(),
+                    data: required_str(data, "data", "inlineData")?.to_string(),
+                }));
+            } else if let Some(file) = part.get("fileData") {
+                let uri = required_str(file, "fileUri", "fileData")?;
+                let mime_type = optional_str(file.get("mimeType"), "mimeType")?;
+                blocks.push(match mime_type {
+                    Some(mime_type) if !mime_type.starts_with("image/") => {
+                        ContentBlock::attachment_with(uri, Some(mime_type), None)
+                    }
+                    _ => ContentBlock::image(ImageSource::Url {
+                        url: uri.to_string(),
+                    }),
+                });
+            } else {
+                blocks.push(ContentBlock::raw("gemini", part.clone()));
+            }
+        }
+
+        if blocks.is_empty() {
+            continue;
+        }
+        let mut msg = InternalMessage::assistant_blocks(blocks);
+        msg.role = role;
+        if let MessageContent::Blocks(blocks) = &msg.content {
+            if let [ContentBlock::Text { text, .. }] = blocks.as_slice() {
+                msg.content = MessageContent::Text(text.clone());
+            }
+        }
+        messages.push(msg);
+    }
+
+    Ok(messages)
+}
+
+/// Join the text parts of a `{"parts": [...]}` object
+fn parts_text(value: &Value) -> Result<String, ConversionError> {
+    let parts = value
+        .get("parts")
+        .ok_or_else(|| ConversionError::missing("parts", "systemInstruction"))?;
+    let parts = parts
+        .as_array()
+        .ok_or_else(|| ConversionError::type_mismatch("parts", "array", parts))?;
+    Ok(parts
+        .iter()
+        .filter_map(|part| part.get("text").and_then(Value::as_str))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// Convert messages into a Gemini `generateContent` request body
+///
+/// Produces `{"contents": [...]}` plus a `systemInstruction` when there are
+/// system messages (their text is joined with newlines). Assistant messages
+/// become `model` turns with `functionCall` parts for tool use blocks.
+///
+/// Gemini expects all function responses answering a model turn in a single
+/// `user` turn, so consecutive tool messages (and tool result blocks) are
+/// batched into one content with a `functionResponse` part each; any other
+/// blocks of those tool messages are added to the same content. The part
+/// `name` is the tool message's `name`, falling back to the name of the tool
+/// call it answers. A result whose content is a JSON object is sent as the
+/// `response` as is; anything else is wrapped as `{"content": ...}`. Raw
+/// blocks are only emitted when their provider is `"gemini"`; see
+/// [`to_gemini_request_with_hook`].
+pub fn to_gemini_request(messages: &[InternalMessage]) -> Value {
+    let mut system = Vec::new();
+    let mut contents: Vec<Value> = Vec::new();
+    let mut tool_names: HashMap<&str, &str> = HashMap::new();
+    // Whether the last content is a batch of function responses
+    let mut in_responses = false;
+
+    for msg in messages {
+        if msg.role == MessageRole::System {
+            system.push(msg.to_text());
+            continue;
+        }
+
+        let mut parts = Vec::new();
+        let mut responses = Vec::new();
+        match &msg.content {
+            MessageContent::Text(text) if msg.role == MessageRole::Tool => {
+                let id = msg.tool_call_id.as_deref().unwrap_or_default();
+                let name = msg
+                    .name
+                    .as_deref()
+                    .or_else(|| tool_names.get(id).copied())
+                    .unwrap_or_default();
+                responses.push(function_response(name, text));
+            }
+            MessageContent::Text(text) => parts.push(json!({ "text": text })),
+            MessageContent::Blocks(blocks) => {
+                for block in blocks {
+                    match block {
+                        ContentBlock::Text { text, .. } => parts.push(json!({ "text": text })),
+                        ContentBlock::ToolUse {
+                            id, name, input, ..
+                        } => {
+                            tool_names.insert(id, name);
+                            parts.push(json!({"functionCall": {"name": name, "args": input}}));
+                        }
+                        ContentBlock::ToolResult {
+                            tool_use_id,
+                            content,
+                            ..
+                        } => {
+                            let name = msg
+                                .name
+                                .as_deref()
+                                .or_else(|| tool_names.get(tool_use_id.as_str()).copied())
+                                .unwrap_or_default();
+                            responses.push(function_response(name, content));
+                        }
+                        ContentBlock::Image { source, .. } => parts.push(image_part(source)),
+                        ContentBlock::AudioOutput {
+                            transcript: Some(transcript),
+                            ..
+                        } => parts.push(json!({ "text": transcript })),
+                        ContentBlock::AudioOutput { .. } => {}
+                        ContentBlock::Attachment {
+                            file_id,
+                            media_type,
+                            ..
+                        } => {
+                            let mut file = json!({ "fileUri": file_id });
+                            if let Some(media_type) = media_type {
+                                file["mimeType"] = json!(media_type);
+                            }
+                            parts.push(json!({ "fileData": file }));
+                        }
+                        ContentBlock::Raw { provider, value } => {
+                            if provider == "gemini" {
+                                parts.push(value.clone());
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // Other parts of a tool message (such as a screenshot the tool
+        // returned) belong to the same user turn as its function responses
+        if msg.role == MessageRole::Tool {
+            responses.append(&mut parts);
+        }
+        if !responses.is_empty() {
+            if in_responses {
+                if let Some(Value::Array(last)) = contents
+                    .last_mut()
+                    .and_then(|content| content.get_mut("parts"))
+                {
+                    last.extend(responses);
+                }
+            } else {
+                contents.push(json!({ "role": "user", "parts": responses }));
+            }
+            in_responses = true;
+        }
+        if !parts.is_empty() {
+            let role = match msg.role {
+                MessageRole::Assistant => "model",
+                _ => "user",
+            };
+            contents.push(json!({ "role": role, "parts": parts }));
+            in_responses = false;
+        }
+    }
+
+    let mut request = Map::new();
+    request.insert("contents".to_string(), Value::Array(contents));
+    if !system.is_empty() {
+        request.insert(
+            "systemInstruction".to_string(),
+            json!({ "parts": [{ "text": system.join("\n") }] }),
+        );
+    }
+    Value::Object(request)
+}
+
+/// Convert messages into a Gemini request body, reporting dropped raw blocks
+///
+/// Like [`to_gemini_request`], but calls `on_drop` with the provider and
+/// value of each raw block left out because it belongs to another provider.
+pub fn to_gemini_request_with_hook<F>(messages: &[InternalMessage], on_drop: F) -> Value
+where
+    F: FnMut(&str, &Value),
+{
+    report_dropped_raw_blocks(messages, "gemini", on_drop);
+    to_gemini_request(messages)
+}
+
+fn function_response(name: &str, content: &str) -> Value {
+    let response = match serde_json::from_str::<Value>(content) {
+        Ok(object @ Value::Object(_)) => object,
+        _ => json!({ "content": content }),
+    };
+    json!({ "functionResponse": { "name": name, "response": response } })
+}
+
+fn image_part(source: &ImageSource) -> Value {
+    match source {
+        ImageSource::Base64 { media_type, data } => {
+            json!({ "inlineData": { "mimeType": media_type, "data": data } })
+        }
+        ImageSource::Url { url } => json!({ "fileData": { "fileUri": url } }),
+    }
+}
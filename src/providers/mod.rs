@@ -0,0 +1,371 @@
+//! Provider format helpers.
+//!
+//! Utilities for working with message JSON produced by provider SDKs
+//! (OpenAI, Anthropic, Google Gemini): format detection and conversion
+//! into UMF types.
+
+use crate::InternalMessage;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+mod anthropic;
+mod gemini;
+mod openai;
+
+pub use anthropic::{
+    from_anthropic_message, from_anthropic_request, to_anthropic_message,
+    to_anthropic_message_with_hook, to_anthropic_request, to_anthropic_request_with_hook,
+    to_anthropic_request_with_policy, AnthropicEmptyPolicy,
+};
+pub use gemini::{from_gemini_request, to_gemini_request, to_gemini_request_with_hook};
+pub use openai::{
+    from_openai_message, from_openai_messages, to_openai_message, to_openai_message_with_hook,
+};
+
+/// Error converting provider JSON into UMF types
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConversionError {
+    /// A required field is absent
+    MissingField {
+        /// Name of the missing field
+        field: String,
+        /// Where the field was expected (e.g. `messages[2]`)
+        context: String,
+    },
+    /// A field has the wrong JSON type
+    TypeMismatch {
+        /// Name of the offending field
+        field: String,
+        /// Expected type description
+        expected: String,
+        /// Actual JSON type found
+        got: String,
+    },
+    /// The role is not a known role or alias
+    InvalidRole(String),
+}
+
+impl ConversionError {
+    pub(crate) fn missing(field: &str, context: &str) -> Self {
+        Self::MissingField {
+            field: field.to_string(),
+            context: context.to_string(),
+        }
+    }
+
+    pub(crate) fn type_mismatch(field: &str, expected: &str, got: &Value) -> Self {
+        Self::TypeMismatch {
+            field: field.to_string(),
+            expected: expected.to_string(),
+            got: json_type_name(got).to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingField { field, context } => {
+                write!(f, "missing field `{}` in {}", field, context)
+            }
+            Self::TypeMismatch {
+                field,
+                expected,
+                got,
+            } => write!(f, "field `{}` expected {}, got {}", field, expected, got),
+            Self::InvalidRole(role) => write!(f, "invalid role `{}`", role),
+        }
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+/// Name of a JSON value's type for error messages
+pub(crate) fn json_type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Report raw blocks of `messages` that a converter for `provider` drops
+///
+/// Calls `on_drop` with the provider and value of every raw block belonging
+/// to another provider, like [`InternalMessage::retain_raw_blocks_for`] but
+/// without modifying the messages.
+pub(crate) fn report_dropped_raw_blocks<'a, F>(
+    messages: impl IntoIterator<Item = &'a InternalMessage>,
+    provider: &str,
+    mut on_drop: F,
+) where
+    F: FnMut(&str, &Value),
+{
+    for msg in messages {
+        for block in msg.blocks().unwrap_or_default() {
+            match block.as_raw() {
+                Some((p, value)) if p != provider => on_drop(p, value),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Get a required string field of a JSON object
+pub(crate) fn required_str<'a>(
+    value: &'a Value,
+    field: &str,
+    context: &str,
+) -> Result<&'a str, ConversionError> {
+    let field_value = value
+        .get(field)
+        .ok_or_else(|| ConversionError::missing(field, context))?;
+    field_value
+        .as_str()
+        .ok_or_else(|| ConversionError::type_mismatch(field, "string", field_value))
+}
+
+/// Get an optional string field, treating `null` as absent
+pub(crate) fn optional_str(
+    value: Option<&Value>,
+    field: &str,
+) -> Result<Option<String>, ConversionError> {
+    match value {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s.clone())),
+        Some(other) => Err(ConversionError::TypeMismatch {
+            field: field.to_string(),
+            expected: "string".to_string(),
+            got: json_type_name(other).to_string(),
+        }),
+    }
+}
+
+/// Wire format of provider message JSON
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderFormat {
+    /// OpenAI Chat Completions format
+    OpenAI,
+    /// Anthropic Messages format
+    Anthropic,
+    /// Google Gemini `contents` format
+    Gemini,
+    /// No distinguishing fields found
+    Unknown,
+}
+
+impl ProviderFormat {
+    /// Convert to string representation
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::OpenAI => "openai",
+            Self::Anthropic => "anthropic",
+            Self::Gemini => "gemini",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
+impl std::fmt::Display for ProviderFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Model provider (vendor) identity
+///
+/// Parsing normalizes case, `_`/space separators and common aliases, so
+/// `"OpenAI"`, `"open_ai"` and `"openai"` all yield [`Provider::OpenAI`] and
+/// `"azure-openai"` yields [`Provider::Azure`]. Unknown names are kept as
+/// [`Provider::Other`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Provider {
+    /// OpenAI
+    OpenAI,
+    /// Anthropic
+    Anthropic,
+    /// Google (Gemini, Vertex AI)
+    Google,
+    /// Cohere
+    Cohere,
+    /// Mistral AI
+    Mistral,
+    /// Azure OpenAI Service
+    Azure,
+    /// AWS Bedrock
+    Bedrock,
+    /// Any other provider, as given
+    Other(String),
+}
+
+impl Provider {
+    /// Convert to string representation
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::OpenAI => "openai",
+            Self::Anthropic => "anthropic",
+            Self::Google => "google",
+            Self::Cohere => "cohere",
+            Self::Mistral => "mistral",
+            Self::Azure => "azure",
+            Self::Bedrock => "bedrock",
+            Self::Other(name) => name,
+        }
+    }
+}
+
+impl Provider {
+    /// Message wire format used by this provider's chat API
+    ///
+    /// Azure, Mistral, Cohere and unknown providers use the OpenAI-compatible
+    /// format; Bedrock uses the Anthropic Messages format of the models it
+    /// hosts.
+    pub fn wire_format(&self) -> ProviderFormat {
+        match self {
+            Self::Anthropic | Self::Bedrock => ProviderFormat::Anthropic,
+            Self::Google => ProviderFormat::Gemini,
+            Self::OpenAI | Self::Azure | Self::Mistral | Self::Cohere | Self::Other(_) => {
+                ProviderFormat::OpenAI
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Provider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl std::str::FromStr for Provider {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized = s.trim().to_lowercase().replace(['_', ' '], "-");
+        Ok(match normalized.as_str() {
+            "openai" | "open-ai" => Self::OpenAI,
+            "anthropic" | "claude" => Self::Anthropic,
+            "google" | "gemini" | "vertex" | "vertex-ai" | "vertexai" | "google-ai" => {
+                Self::Google
+            }
+            "cohere" => Self::Cohere,
+            "mistral" | "mistral-ai" | "mistralai" => Self::Mistral,
+            "azure" | "azure-openai" | "azureopenai" | "azure-ai" => Self::Azure,
+            "bedrock" | "aws-bedrock" | "amazon-bedrock" => Self::Bedrock,
+            _ => Self::Other(s.trim().to_string()),
+        })
+    }
+}
+
+/// Parse a single message in `provider`'s wire format
+///
+/// Gemini contents may hold several function responses, which map to one
+/// tool message each; such contents are rejected here.
+pub(crate) fn from_provider_message(
+    provider: &Provider,
+    value: &Value,
+) -> Result<InternalMessage, ConversionError> {
+    match provider.wire_format() {
+        ProviderFormat::Anthropic => from_anthropic_message(value),
+        ProviderFormat::Gemini => {
+            let mut messages = from_gemini_request(&serde_json::json!({ "contents": [value] }))?;
+            match messages.len() {
+                1 => Ok(messages.remove(0)),
+                n => Err(ConversionError::TypeMismatch {
+                    field: "parts".to_string(),
+                    expected: "a single message".to_string(),
+                    got: format!("{} messages", n),
+                }),
+            }
+        }
+        ProviderFormat::OpenAI | ProviderFormat::Unknown => from_openai_message(value),
+    }
+}
+
+/// Convert a single message into `provider`'s wire format
+///
+/// For Gemini, a system message yields its `systemInstruction` object.
+pub(crate) fn to_provider_message(provider: &Provider, msg: &InternalMessage) -> Value {
+    match provider.wire_format() {
+        ProviderFormat::Anthropic => to_anthropic_message(msg),
+        ProviderFormat::Gemini => {
+            let mut request = to_gemini_request(std::slice::from_ref(msg));
+            match request["contents"].get_mut(0) {
+                Some(content) => content.take(),
+                None => request["systemInstruction"].take(),
+            }
+        }
+        ProviderFormat::OpenAI | ProviderFormat::Unknown => to_openai_message(msg),
+    }
+}
+
+/// Detect the provider format of inbound message JSON
+///
+/// Accepts a single message, an array of messages, or a request body with a
+/// `messages` or `contents` array, and sniffs distinguishing fields:
+///
+/// - `tool_calls` with `function.arguments`, `tool_call_id`, or `image_url`
+///   parts → [`ProviderFormat::OpenAI`]
+/// - `tool_use`/`tool_result` content blocks or a top-level `system` string
+///   next to `messages` → [`ProviderFormat::Anthropic`]
+/// - `parts` arrays (e.g. with `functionCall`) or `contents` → [`ProviderFormat::Gemini`]
+///
+/// Plain `{"role", "content": "..."}` messages are valid in several formats
+/// and yield [`ProviderFormat::Unknown`] unless another message decides it.
+pub fn detect_provider_format(value: &Value) -> ProviderFormat {
+    match value {
+        Value::Array(items) => items
+            .iter()
+            .map(detect_provider_format)
+            .find(|f| *f != ProviderFormat::Unknown)
+            .unwrap_or(ProviderFormat::Unknown),
+        Value::Object(obj) => {
+            if obj.contains_key("contents") || obj.contains_key("parts") {
+                return ProviderFormat::Gemini;
+            }
+            if let Some(messages) = obj.get("messages") {
+                if obj.get("system").is_some_and(Value::is_string) {
+                    return ProviderFormat::Anthropic;
+                }
+                return detect_provider_format(messages);
+            }
+            detect_message(obj)
+        }
+        _ => ProviderFormat::Unknown,
+    }
+}
+
+/// Detect the format of a single message object
+fn detect_message(obj: &serde_json::Map<String, Value>) -> ProviderFormat {
+    let has_function_args = obj
+        .get("tool_calls")
+        .and_then(Value::as_array)
+        .is_some_and(|calls| {
+            calls.iter().any(|c| c.pointer("/function/arguments").is_some())
+        });
+    if has_function_args || obj.contains_key("tool_call_id") {
+        return ProviderFormat::OpenAI;
+    }
+
+    let blocks = obj
+        .get("content")
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    for block in blocks {
+        match block.get("type").and_then(Value::as_str) {
+            Some("tool_use") | Some("tool_result") => return ProviderFormat::Anthropic,
+            Some("image_url") => return ProviderFormat::OpenAI,
+            _ => {}
+        }
+    }
+
+    ProviderFormat::Unknown
+}
+
+#[cfg(test)]
+mod tests;
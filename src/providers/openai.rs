@@ -0,0 +1,297 @@
+//! OpenAI Chat Completions message conversion
+
+use super::{optional_str, report_dropped_raw_blocks, required_str, ConversionError};
+use crate::{AudioSource, ContentBlock, ImageSource, InternalMessage, MessageContent, MessageRole};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+/// Parse a single OpenAI chat message into an [`InternalMessage`]
+///
+/// Supports string, `null` and array (`text`/`image_url`/`file` parts) content,
+/// assistant `tool_calls` (converted to tool use blocks) and tool messages
+/// with `tool_call_id`. Assistant `audio` becomes an audio output block.
+/// Tool calls carrying an `index` are ordered by it; calls without one
+/// follow in array order.
+pub fn from_openai_message(value: &Value) -> Result<InternalMessage, ConversionError> {
+    parse_message(value, "openai message")
+}
+
+/// Parse an array of OpenAI chat messages
+///
+/// Errors name the offending message as `messages[i]`.
+pub fn from_openai_messages(value: &Value) -> Result<Vec<InternalMessage>, ConversionError> {
+    let items = value
+        .as_array()
+        .ok_or_else(|| ConversionError::type_mismatch("messages", "array", value))?;
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| parse_message(item, &format!("messages[{}]", i)))
+        .collect()
+}
+
+/// Convert an [`InternalMessage`] into an OpenAI chat message
+///
+/// Tool use blocks become `tool_calls` (with `content: null` when there is no
+/// text). Attachments become Files API `file` parts; audio output is referred
+/// to by its `audio.id`, or sent as transcript text without one. Messages with images or
+/// attachments use the array content form; otherwise text and
+/// tool result blocks are joined with newlines. Raw blocks are only emitted
+/// when their provider is `"openai"`; see [`to_openai_message_with_hook`].
+pub fn to_openai_message(msg: &InternalMessage) -> Value {
+    let mut obj = Map::new();
+    obj.insert("role".to_string(), json!(msg.role.as_str()));
+
+    let mut tool_calls = Vec::new();
+    let mut audio_id = None;
+    let content = match &msg.content {
+        MessageContent::Text(text) => json!(text),
+        MessageContent::Blocks(blocks) => {
+            let mut parts = Vec::new();
+            let mut has_parts = false;
+            for block in blocks {
+                match block {
+                    ContentBlock::Text { text, .. } => {
+                        parts.push(json!({"type": "text", "text": text}))
+                    }
+                    ContentBlock::ToolResult { content, .. } => {
+                        parts.push(json!({"type": "text", "text": content}))
+                    }
+                    ContentBlock::Image { source, .. } => {
+                        has_parts = true;
+                        let url = match source {
+                            ImageSource::Url { url } => url.clone(),
+                            ImageSource::Base64 { media_type, data } => {
+                                format!("data:{};base64,{}", media_type, data)
+                            }
+                        };
+                        parts.push(json!({"type": "image_url", "image_url": {"url": url}}));
+                    }
+                    ContentBlock::AudioOutput {
+                        id: Some(id), ..
+                    } => audio_id = Some(id),
+                    ContentBlock::AudioOutput {
+                        transcript: Some(transcript),
+                        ..
+                    } => parts.push(json!({"type": "text", "text": transcript})),
+                    ContentBlock::AudioOutput { .. } => {}
+                    ContentBlock::Attachment { file_id, name, .. } => {
+                        has_parts = true;
+                        let mut file = json!({"file_id": file_id});
+                        if let Some(name) = name {
+                            file["filename"] = json!(name);
+                        }
+                        parts.push(json!({"type": "file", "file": file}));
+                    }
+                    ContentBlock::ToolUse {
+                        id, name, input, ..
+                    } => {
+                        let arguments = match input {
+                            Value::String(raw) => raw.clone(),
+                            other => other.to_string(),
+                        };
+                        tool_calls.push(json!({
+                            "id": id,
+                            "type": "function",
+                            "function": {"name": name, "arguments": arguments}
+                        }));
+                    }
+                    ContentBlock::Raw { provider, value } => {
+                        if provider == "openai" {
+                            has_parts = true;
+                            parts.push(value.clone());
+                        }
+                    }
+                }
+            }
+
+            if has_parts {
+                Value::Array(parts)
+            } else {
+                let text: Vec<&str> = parts.iter().filter_map(|p| p["text"].as_str()).collect();
+                if text.is_empty() && (!tool_calls.is_empty() || audio_id.is_some()) {
+                    Value::Null
+                } else {
+                    json!(text.join("\n"))
+                }
+            }
+        }
+    };
+    obj.insert("content".to_string(), content);
+
+    if !tool_calls.is_empty() {
+        obj.insert("tool_calls".to_string(), Value::Array(tool_calls));
+    }
+    if let Some(id) = audio_id {
+        obj.insert("audio".to_string(), json!({ "id": id }));
+    }
+    if let Some(tool_call_id) = &msg.tool_call_id {
+        obj.insert("tool_call_id".to_string(), json!(tool_call_id));
+    }
+    if let Some(name) = &msg.name {
+        obj.insert("name".to_string(), json!(name));
+    }
+
+    Value::Object(obj)
+}
+
+/// Convert an [`InternalMessage`] into an OpenAI chat message, reporting
+/// dropped raw blocks
+///
+/// Like [`to_openai_message`], but calls `on_drop` with the provider and
+/// value of each raw block left out because it belongs to another provider.
+pub fn to_openai_message_with_hook<F>(msg: &InternalMessage, on_drop: F) -> Value
+where
+    F: FnMut(&str, &Value),
+{
+    report_dropped_raw_blocks([msg], "openai", on_drop);
+    to_openai_message(msg)
+}
+
+fn parse_message(value: &Value, context: &str) -> Result<InternalMessage, ConversionError> {
+    let obj = value
+        .as_object()
+        .ok_or_else(|| ConversionError::type_mismatch(context, "object", value))?;
+
+    let role_value = obj
+        .get("role")
+        .ok_or_else(|| ConversionError::missing("role", context))?;
+    let role_str = role_value
+        .as_str()
+        .ok_or_else(|| ConversionError::type_mismatch("role", "string", role_value))?;
+    let role: MessageRole = serde_json::from_value(role_value.clone())
+        .map_err(|_| ConversionError::InvalidRole(role_str.to_string()))?;
+
+    let mut blocks = match obj.get("content") {
+        None | Some(Value::Null) => Vec::new(),
+        Some(Value::String(text)) => vec![ContentBlock::text(text.clone())],
+        Some(Value::Array(parts)) => parts
+            .iter()
+            .map(parse_content_part)
+            .collect::<Result<_, _>>()?,
+        Some(other) => {
+            return Err(ConversionError::type_mismatch(
+                "content",
+                "string, array or null",
+                other,
+            ))
+        }
+    };
+
+    match obj.get("audio") {
+        None | Some(Value::Null) => {}
+        Some(audio) => blocks.push(parse_audio(audio)?),
+    }
+
+    let mut has_tool_calls = false;
+    if let Some(tool_calls) = obj.get("tool_calls") {
+        let calls = tool_calls
+            .as_array()
+            .ok_or_else(|| ConversionError::type_mismatch("tool_calls", "array", tool_calls))?;
+        // Reconstructed streaming messages may list calls out of `index` order
+        let mut ordered: Vec<&Value> = calls.iter().collect();
+        ordered.sort_by_key(|call| call.get("index").and_then(Value::as_u64).unwrap_or(u64::MAX));
+        for call in ordered {
+            blocks.push(parse_tool_call(call)?);
+            has_tool_calls = true;
+        }
+    }
+
+    // Keep the simple text representation unless blocks are required
+    let content = match blocks.as_slice() {
+        [] if !has_tool_calls => MessageContent::Text(String::new()),
+        [ContentBlock::Text { text, .. }] => MessageContent::Text(text.clone()),
+        _ => MessageContent::Blocks(blocks),
+    };
+
+    Ok(InternalMessage {
+        role,
+        content,
+        metadata: HashMap::new(),
+        tool_call_id: optional_str(obj.get("tool_call_id"), "tool_call_id")?,
+        name: optional_str(obj.get("name"), "name")?,
+        created_at: None,
+        hints: None,
+    })
+}
+
+fn parse_content_part(part: &Value) -> Result<ContentBlock, ConversionError> {
+    let part_type = required_str(part, "type", "content part")?;
+    match part_type {
+        "text" => Ok(ContentBlock::text(required_str(part, "text", "text part")?)),
+        "image_url" => {
+            let image_url = part
+                .get("image_url")
+                .ok_or_else(|| ConversionError::missing("image_url", "image_url part"))?;
+            let url = required_str(image_url, "url", "image_url part")?;
+            Ok(ContentBlock::image(parse_image_url(url)))
+        }
+        "file" => {
+            let file = part
+                .get("file")
+                .ok_or_else(|| ConversionError::missing("file", "file part"))?;
+            Ok(ContentBlock::attachment_with(
+                required_str(file, "file_id", "file part")?,
+                None,
+                optional_str(file.get("filename"), "filename")?,
+            ))
+        }
+        other => Err(ConversionError::TypeMismatch {
+            field: "type".to_string(),
+            expected: "\"text\", \"image_url\" or \"file\"".to_string(),
+            got: format!("\"{}\"", other),
+        }),
+    }
+}
+
+/// Map `data:` URLs back to base64 sources, anything else to a URL source
+fn parse_image_url(url: &str) -> ImageSource {
+    let base64 = url
+        .strip_prefix("data:")
+        .and_then(|rest| rest.split_once(";base64,"));
+    match base64 {
+        Some((media_type, data)) => ImageSource::Base64 {
+            media_type: media_type.to_string(),
+            data: data.to_string(),
+        },
+        None => ImageSource::Url {
+            url: url.to_string(),
+        },
+    }
+}
+
+/// Parse assistant `message.audio` (`{id, data, transcript, expires_at}`)
+fn parse_audio(audio: &Value) -> Result<ContentBlock, ConversionError> {
+    let obj = audio
+        .as_object()
+        .ok_or_else(|| ConversionError::type_mismatch("audio", "object", audio))?;
+    let source = optional_str(obj.get("data"), "data")?.map(|data| AudioSource::Base64 {
+        media_type: None,
+        data,
+    });
+    let mut block = ContentBlock::audio_output(
+        optional_str(obj.get("id"), "id")?,
+        optional_str(obj.get("transcript"), "transcript")?,
+        source,
+    );
+    for (key, value) in obj {
+        if !["id", "data", "transcript"].contains(&key.as_str()) {
+            block = block.with_extension(key.clone(), value.clone());
+        }
+    }
+    Ok(block)
+}
+
+fn parse_tool_call(call: &Value) -> Result<ContentBlock, ConversionError> {
+    let id = required_str(call, "id", "tool call")?;
+    let function = call
+        .get("function")
+        .ok_or_else(|| ConversionError::missing("function", "tool call"))?;
+    let name = required_str(function, "name", "tool call function")?;
+    let arguments = required_str(function, "arguments", "tool call function")?;
+
+    // Arguments are a JSON string; keep the raw string if it does not parse
+    let input = serde_json::from_str(arguments)
+        .unwrap_or_else(|_| Value::String(arguments.to_string()));
+    Ok(ContentBlock::tool_use(id, name, input))
+}
@@ -0,0 +1,556 @@
+//! Tests for provider format helpers
+
+use super::*;
+use serde_json::json;
+
+#[test]
+fn test_detect_openai() {
+    let msg = json!({
+        "role": "assistant",
+        "content": null,
+        "tool_calls": [{
+            "id": "call_1",
+            "type": "function",
+            "function": {"name": "search", "arguments": "{\"q\":\"rust\"}"}
+        }]
+    });
+    assert_eq!(detect_provider_format(&msg), ProviderFormat::OpenAI);
+
+    let tool = json!({"role": "tool", "tool_call_id": "call_1", "content": "ok"});
+    assert_eq!(detect_provider_format(&tool), ProviderFormat::OpenAI);
+}
+
+#[test]
+fn test_detect_anthropic() {
+    let msg = json!({
+        "role": "assistant",
+        "content": [
+            {"type": "text", "text": "Searching"},
+            {"type": "tool_use", "id": "toolu_1", "name": "search", "input": {"q": "rust"}}
+        ]
+    });
+    assert_eq!(detect_provider_format(&msg), ProviderFormat::Anthropic);
+
+    let body = json!({
+        "system": "Be brief",
+        "messages": [{"role": "user", "content": "Hi"}]
+    });
+    assert_eq!(detect_provider_format(&body), ProviderFormat::Anthropic);
+}
+
+#[test]
+fn test_detect_gemini() {
+    let msg = json!({
+        "role": "model",
+        "parts": [{"functionCall": {"name": "search", "args": {"q": "rust"}}}]
+    });
+    assert_eq!(detect_provider_format(&msg), ProviderFormat::Gemini);
+
+    let body = json!({"contents": [{"role": "user", "parts": [{"text": "Hi"}]}]});
+    assert_eq!(detect_provider_format(&body), ProviderFormat::Gemini);
+}
+
+#[test]
+fn test_detect_unknown_and_arrays() {
+    let plain = json!({"role": "user", "content": "Hi"});
+    assert_eq!(detect_provider_format(&plain), ProviderFormat::Unknown);
+    assert_eq!(detect_provider_format(&json!("text")), ProviderFormat::Unknown);
+
+    // The first decisive message in an array wins
+    let messages = json!([
+        {"role": "user", "content": "Hi"},
+        {"role": "tool", "tool_call_id": "call_1", "content": "ok"}
+    ]);
+    assert_eq!(detect_provider_format(&messages), ProviderFormat::OpenAI);
+}
+
+#[test]
+fn test_from_openai_message() {
+    let msg = from_openai_message(&json!({
+        "role": "assistant",
+        "content": null,
+        "tool_calls": [{
+            "id": "call_1",
+            "type": "function",
+            "function": {"name": "search", "arguments": "{\"q\":\"rust\"}"}
+        }]
+    }))
+    .unwrap();
+
+    assert_eq!(msg.role, crate::MessageRole::Assistant);
+    let blocks = msg.blocks().unwrap();
+    let (id, name, input) = blocks[0].as_tool_use().unwrap();
+    assert_eq!((id, name), ("call_1", "search"));
+    assert_eq!(input["q"], "rust");
+
+    let msg = from_openai_message(&json!({
+        "role": "tool", "tool_call_id": "call_1", "name": "search", "content": "found"
+    }))
+    .unwrap();
+    assert_eq!(msg.text(), Some("found"));
+    assert_eq!(msg.tool_call_id.as_deref(), Some("call_1"));
+}
+
+#[test]
+fn test_from_openai_message_orders_tool_calls_by_index() {
+    let call = |index: u64, id: &str| {
+        json!({
+            "index": index,
+            "id": id,
+            "type": "function",
+            "function": {"name": "search", "arguments": "{}"}
+        })
+    };
+    let msg = from_openai_message(&json!({
+        "role": "assistant",
+        "content": null,
+        "tool_calls": [call(5, "call_c"), call(0, "call_a"), call(2, "call_b")]
+    }))
+    .unwrap();
+
+    let ids: Vec<_> = msg
+        .blocks()
+        .unwrap()
+        .iter()
+        .map(|b| b.as_tool_use().unwrap().0)
+        .collect();
+    assert_eq!(ids, ["call_a", "call_b", "call_c"]);
+}
+
+#[test]
+fn test_conversion_error_missing_role() {
+    let err = from_openai_message(&json!({"content": "Hi"})).unwrap_err();
+    assert_eq!(
+        err,
+        ConversionError::MissingField {
+            field: "role".to_string(),
+            context: "openai message".to_string(),
+        }
+    );
+
+    let err = from_openai_messages(&json!([
+        {"role": "user", "content": "Hi"},
+        {"content": "Hi"}
+    ]))
+    .unwrap_err();
+    assert!(matches!(
+        err,
+        ConversionError::MissingField { ref context, .. } if context == "messages[1]"
+    ));
+}
+
+#[test]
+fn test_conversion_error_type_mismatch() {
+    let err = from_openai_message(&json!({"role": "user", "content": 42})).unwrap_err();
+    assert_eq!(
+        err,
+        ConversionError::TypeMismatch {
+            field: "content".to_string(),
+            expected: "string, array or null".to_string(),
+            got: "number".to_string(),
+        }
+    );
+
+    let err = from_openai_message(&json!({"role": "narrator", "content": "Hi"})).unwrap_err();
+    assert_eq!(err, ConversionError::InvalidRole("narrator".to_string()));
+}
+
+#[test]
+fn test_to_openai_message_images() {
+    let msg = crate::InternalMessage {
+        role: crate::MessageRole::User,
+        content: crate::MessageContent::Blocks(vec![
+            crate::ContentBlock::text("What is this?"),
+            crate::ContentBlock::image(crate::ImageSource::Base64 {
+                media_type: "image/png".to_string(),
+                data: "iVBORw0".to_string(),
+            }),
+        ]),
+        metadata: Default::default(),
+        tool_call_id: None,
+        name: None,
+        created_at: None,
+        hints: None,
+    };
+
+    let value = to_openai_message(&msg);
+    assert_eq!(value["content"][0], json!({"type": "text", "text": "What is this?"}));
+    assert_eq!(
+        value["content"][1]["image_url"]["url"],
+        "data:image/png;base64,iVBORw0"
+    );
+
+    let parsed = from_openai_message(&value).unwrap();
+    let blocks = parsed.blocks().unwrap();
+    assert!(matches!(
+        blocks[1].as_image(),
+        Some(crate::ImageSource::Base64 { media_type, .. }) if media_type == "image/png"
+    ));
+}
+
+#[test]
+fn test_json_tool_result_survives_conversion() {
+    let result = json!({"temperature": 21.5, "conditions": ["sunny", "windy"]});
+    let msg = crate::InternalMessage::tool_result_json("call_1", "get_weather", result.clone());
+    assert!(msg.is_json_content());
+    assert_eq!(msg.tool_result_json_value(), Some(result.clone()));
+
+    // OpenAI tool messages take string content: the JSON is sent serialized
+    let value = to_openai_message(&msg);
+    assert_eq!(value["role"], "tool");
+    assert_eq!(value["tool_call_id"], "call_1");
+    let content: serde_json::Value =
+        serde_json::from_str(value["content"].as_str().unwrap()).unwrap();
+    assert_eq!(content, result);
+
+    // The JSON marker survives a serde round trip
+    let restored: crate::InternalMessage =
+        serde_json::from_value(serde_json::to_value(&msg).unwrap()).unwrap();
+    assert_eq!(restored.tool_result_json_value(), Some(result));
+
+    let plain = crate::InternalMessage::tool_result("call_2", "echo", "{\"a\": 1}");
+    assert_eq!(plain.tool_result_json_value(), None);
+}
+
+#[test]
+fn test_provider_from_str() {
+    let parse = |s: &str| s.parse::<Provider>().unwrap();
+    assert_eq!(parse("OpenAI"), Provider::OpenAI);
+    assert_eq!(parse(" open_ai "), Provider::OpenAI);
+    assert_eq!(parse("Azure OpenAI"), Provider::Azure);
+    assert_eq!(parse("gemini"), Provider::Google);
+    assert_eq!(parse("AWS-Bedrock"), Provider::Bedrock);
+    assert_eq!(parse("together"), Provider::Other("together".to_string()));
+    assert_eq!(Provider::Mistral.to_string(), "mistral");
+}
+
+#[test]
+fn test_to_gemini_request_batches_tool_results() {
+    use crate::{ContentBlock, InternalMessage};
+
+    let messages = vec![
+        InternalMessage::system("You are a weather bot"),
+        InternalMessage::user("Weather in Paris and London?"),
+        InternalMessage::assistant_blocks(vec![
+            ContentBlock::tool_use("call_1", "get_weather", json!({"city": "Paris"})),
+            ContentBlock::tool_use("call_2", "get_forecast", json!({"city": "London"})),
+        ]),
+        InternalMessage::tool_result("call_1", "get_weather", r#"{"temp": 21}"#),
+        InternalMessage::tool_result("call_2", "get_forecast", "Rain later"),
+        InternalMessage::assistant("Sunny in Paris, rain later in London."),
+    ];
+
+    let request = to_gemini_request(&messages);
+    assert_eq!(
+        request["systemInstruction"],
+        json!({"parts": [{"text": "You are a weather bot"}]})
+    );
+
+    let contents = request["contents"].as_array().unwrap();
+    assert_eq!(contents.len(), 4);
+    assert_eq!(contents[1]["role"], "model");
+    assert_eq!(contents[1]["parts"][0]["functionCall"]["name"], "get_weather");
+
+    assert_eq!(contents[2]["role"], "user");
+    let parts = contents[2]["parts"].as_array().unwrap();
+    assert_eq!(parts.len(), 2);
+    assert_eq!(
+        parts[0],
+        json!({"functionResponse": {"name": "get_weather", "response": {"temp": 21}}})
+    );
+    assert_eq!(
+        parts[1],
+        json!({"functionResponse": {"name": "get_forecast", "response": {"content": "Rain later"}}})
+    );
+    assert_eq!(contents[3]["role"], "model");
+}
+
+#[test]
+fn test_to_gemini_request_tool_message_extra_blocks() {
+    use crate::{ContentBlock, ImageSource, InternalMessage, MessageContent};
+
+    let messages = vec![
+        InternalMessage::assistant_blocks(vec![
+            ContentBlock::tool_use("call_1", "screenshot", json!({})),
+            ContentBlock::tool_use("call_2", "get_title", json!({})),
+        ]),
+        InternalMessage::tool(MessageContent::blocks(vec![
+            ContentBlock::tool_result("call_1", "Captured"),
+            ContentBlock::image(ImageSource::Url {
+                url: "https://example.com/shot.png".to_string(),
+            }),
+        ])),
+        InternalMessage::tool_result("call_2", "get_title", "Home"),
+    ];
+
+    let request = to_gemini_request(&messages);
+    let contents = request["contents"].as_array().unwrap();
+    assert_eq!(contents.len(), 2);
+    assert_eq!(contents[1]["role"], "user");
+    let parts = contents[1]["parts"].as_array().unwrap();
+    assert_eq!(parts.len(), 3);
+    assert_eq!(parts[0]["functionResponse"]["name"], "screenshot");
+    assert_eq!(parts[1], json!({"fileData": {"fileUri": "https://example.com/shot.png"}}));
+    assert_eq!(parts[2]["functionResponse"]["name"], "get_title");
+}
+
+#[test]
+fn test_from_gemini_request_pairs_calls_by_name() {
+    let request = json!({
+        "systemInstruction": {"parts": [{"text": "You are a weather bot"}]},
+        "contents": [
+            {"role": "user", "parts": [{"text": "Weather in Paris?"}]},
+            {"role": "model", "parts": [
+                {"functionCall": {"name": "get_weather", "args": {"city": "Paris"}}}
+            ]},
+            {"role": "user", "parts": [
+                {"functionResponse": {"name": "get_weather", "response": {"temp": 21}}}
+            ]},
+            {"role": "model", "parts": [{"text": "Sunny"}]}
+        ]
+    });
+
+    let messages = from_gemini_request(&request).unwrap();
+    assert_eq!(messages.len(), 5);
+    assert_eq!(messages[0].text(), Some("You are a weather bot"));
+    let (id, name, input) = messages[2].blocks().unwrap()[0].as_tool_use().unwrap();
+    assert_eq!((id, name), ("call_1_0", "get_weather"));
+    assert_eq!(input["city"], "Paris");
+    assert_eq!(messages[3].tool_call_id.as_deref(), Some("call_1_0"));
+    assert_eq!(messages[3].tool_result_json_value(), Some(json!({"temp": 21})));
+    assert!(crate::validate_conversation(&messages).is_empty());
+
+    // Converting back yields the original request
+    assert_eq!(to_gemini_request(&messages), request);
+}
+
+#[test]
+fn test_anthropic_request_roundtrip() {
+    let request = json!({
+        "system": "You are a weather bot",
+        "messages": [
+            {"role": "user", "content": "Weather in Paris?"},
+            {"role": "assistant", "content": [
+                {"type": "thinking", "thinking": "Use the tool", "signature": "sig"},
+                {"type": "tool_use", "id": "toolu_1", "name": "get_weather",
+                 "input": {"city": "Paris"}}
+            ]},
+            {"role": "user", "content": [
+                {"type": "tool_result", "tool_use_id": "toolu_1", "content": "Sunny"}
+            ]},
+            {"role": "assistant", "content": "It is sunny."}
+        ]
+    });
+
+    let messages = from_anthropic_request(&request).unwrap();
+    assert_eq!(messages.len(), 5);
+    assert_eq!(messages[0].role, crate::MessageRole::System);
+    assert_eq!(messages[3].role, crate::MessageRole::Tool);
+    assert_eq!(messages[3].name.as_deref(), Some("get_weather"));
+    assert!(crate::validate_conversation(&messages).is_empty());
+
+    assert_eq!(to_anthropic_request(&messages), request);
+
+    let err = from_anthropic_request(&json!({"messages": [{"role": "user"}]})).unwrap_err();
+    assert!(matches!(
+        err,
+        ConversionError::MissingField { ref context, .. } if context == "messages[0]"
+    ));
+}
+
+#[test]
+fn test_anthropic_error_tool_result() {
+    let request = json!({
+        "messages": [
+            {"role": "assistant", "content": [
+                {"type": "tool_use", "id": "toolu_1", "name": "get_weather", "input": {}}
+            ]},
+            {"role": "user", "content": [
+                {"type": "tool_result", "tool_use_id": "toolu_1", "content": "Timed out",
+                 "is_error": true}
+            ]}
+        ]
+    });
+
+    let messages = from_anthropic_request(&request).unwrap();
+    assert!(messages[1].is_error_result());
+    assert_eq!(messages[1].to_text(), "[error] Timed out");
+    assert_eq!(to_anthropic_request(&messages), request);
+
+    let block = crate::ContentBlock::tool_error("toolu_1", "Timed out");
+    let msg = crate::InternalMessage::tool(crate::MessageContent::blocks(vec![block]));
+    assert_eq!(to_anthropic_message(&msg)["content"][0]["is_error"], true);
+}
+
+#[test]
+fn test_to_anthropic_request_empty_assistant_content() {
+    use crate::{ContentBlock, InternalMessage};
+
+    let messages = vec![
+        InternalMessage::user("Hi"),
+        InternalMessage::assistant_blocks(vec![ContentBlock::text("  \n ")]),
+        InternalMessage::assistant("   "),
+        InternalMessage::user("Still there?"),
+    ];
+
+    let request = to_anthropic_request(&messages);
+    let sent = request["messages"].as_array().unwrap();
+    assert_eq!(sent.len(), 2);
+    assert!(sent.iter().all(|m| m["role"] == "user"));
+
+    let request =
+        to_anthropic_request_with_policy(&messages, AnthropicEmptyPolicy::PlaceholderText);
+    let sent = request["messages"].as_array().unwrap();
+    assert_eq!(sent.len(), 4);
+    assert_eq!(sent[1], json!({"role": "assistant", "content": "(empty)"}));
+    assert_eq!(sent[2]["content"], "(empty)");
+}
+
+#[test]
+fn test_from_provider_dispatch() {
+    use crate::InternalMessage;
+
+    let openai = json!({
+        "role": "assistant",
+        "content": "Checking",
+        "tool_calls": [{
+            "id": "call_1",
+            "type": "function",
+            "function": {"name": "search", "arguments": "{\"q\":\"rust\"}"}
+        }]
+    });
+    let anthropic = json!({
+        "role": "assistant",
+        "content": [
+            {"type": "text", "text": "Checking"},
+            {"type": "tool_use", "id": "call_1", "name": "search", "input": {"q": "rust"}}
+        ]
+    });
+
+    let from_openai = InternalMessage::from_provider(Provider::OpenAI, &openai).unwrap();
+    let from_anthropic =
+        InternalMessage::from_provider("claude".parse().unwrap(), &anthropic).unwrap();
+    assert_eq!(
+        serde_json::to_value(&from_openai).unwrap(),
+        serde_json::to_value(&from_anthropic).unwrap()
+    );
+
+    assert_eq!(from_anthropic.to_provider(Provider::Anthropic), anthropic);
+    assert_eq!(from_openai.to_provider(Provider::Azure), openai);
+    let gemini = from_openai.to_provider(Provider::Google);
+    assert_eq!(gemini["role"], "model");
+    assert_eq!(gemini["parts"][1]["functionCall"]["args"]["q"], "rust");
+    let back = InternalMessage::from_provider(Provider::Google, &gemini).unwrap();
+    assert_eq!(back.blocks().unwrap()[1].as_tool_use().unwrap().1, "search");
+}
+
+#[test]
+fn test_anthropic_block_extensions_roundtrip() {
+    let message = json!({
+        "role": "user",
+        "content": [
+            {"type": "text", "text": "Big document", "cache_control": {"type": "ephemeral"}},
+        ]
+    });
+    let msg = from_anthropic_message(&message).unwrap();
+    let blocks = msg.blocks().unwrap();
+    assert_eq!(blocks[0].extensions().unwrap()["cache_control"]["type"], "ephemeral");
+
+    assert_eq!(to_anthropic_message(&msg), message);
+}
+
+#[test]
+fn test_attachment_file_parts() {
+    let msg = InternalMessage::assistant_blocks(vec![
+        crate::ContentBlock::text("Summarize this"),
+        crate::ContentBlock::attachment_with(
+            "file-abc123",
+            Some("application/pdf".to_string()),
+            Some("report.pdf".to_string()),
+        ),
+    ]);
+
+    let openai = to_openai_message(&msg);
+    assert_eq!(
+        openai["content"][1],
+        json!({"type": "file", "file": {"file_id": "file-abc123", "filename": "report.pdf"}})
+    );
+    let parsed = from_openai_message(&openai).unwrap();
+    let blocks = parsed.blocks().unwrap();
+    assert_eq!(
+        blocks[1].as_attachment(),
+        Some(("file-abc123", None, Some("report.pdf")))
+    );
+
+    let gemini = to_gemini_request(std::slice::from_ref(&msg));
+    assert_eq!(
+        gemini["contents"][0]["parts"][1],
+        json!({"fileData": {"fileUri": "file-abc123", "mimeType": "application/pdf"}})
+    );
+    let parsed = from_gemini_request(&gemini).unwrap();
+    let (file_id, media_type, _) = parsed[0].blocks().unwrap()[1].as_attachment().unwrap();
+    assert_eq!((file_id, media_type), ("file-abc123", Some("application/pdf")));
+}
+
+#[test]
+fn test_openai_audio_output() {
+    let message = json!({
+        "role": "assistant",
+        "content": null,
+        "audio": {
+            "id": "audio_abc123",
+            "data": "UklGRg==",
+            "transcript": "Hello there!",
+            "expires_at": 1729018505
+        }
+    });
+    let msg = from_openai_message(&message).unwrap();
+    assert_eq!(msg.to_text(), "Hello there!");
+
+    let blocks = msg.blocks().unwrap();
+    assert_eq!(blocks[0].as_audio_transcript(), Some("Hello there!"));
+    assert_eq!(blocks[0].extensions().unwrap()["expires_at"], 1729018505);
+
+    // Sent back by reference, as OpenAI expects in multi-turn audio
+    let converted = to_openai_message(&msg);
+    assert_eq!(converted["content"], Value::Null);
+    assert_eq!(converted["audio"], json!({"id": "audio_abc123"}));
+}
+
+#[test]
+fn test_converters_report_dropped_raw_blocks() {
+    use crate::{ContentBlock, InternalMessage};
+
+    let msg = InternalMessage::assistant_blocks(vec![
+        ContentBlock::text("Searching"),
+        ContentBlock::raw("anthropic", json!({"type": "server_tool_use", "id": "srv_1"})),
+        ContentBlock::raw("gemini", json!({"executableCode": {"code": "1 + 1"}})),
+    ]);
+    let messages = vec![msg.clone()];
+
+    let mut dropped = Vec::new();
+    let openai = to_openai_message_with_hook(&msg, |p, _| dropped.push(p.to_string()));
+    assert_eq!(openai, to_openai_message(&msg));
+    assert_eq!(dropped, ["anthropic", "gemini"]);
+
+    let mut dropped = Vec::new();
+    let anthropic = to_anthropic_request_with_hook(
+        &messages,
+        AnthropicEmptyPolicy::default(),
+        |p, _| dropped.push(p.to_string()),
+    );
+    assert_eq!(anthropic, to_anthropic_request(&messages));
+    assert_eq!(anthropic["messages"][0]["content"][1]["type"], "server_tool_use");
+    assert_eq!(dropped, ["gemini"]);
+
+    let mut dropped = Vec::new();
+    to_anthropic_message_with_hook(&msg, |p, _| dropped.push(p.to_string()));
+    assert_eq!(dropped, ["gemini"]);
+
+    let mut dropped = Vec::new();
+    let gemini = to_gemini_request_with_hook(&messages, |p, value| {
+        dropped.push((p.to_string(), value["id"].clone()))
+    });
+    assert_eq!(gemini, to_gemini_request(&messages));
+    assert_eq!(dropped, [("anthropic".to_string(), json!("srv_1"))]);
+}
@@ -1,7 +1,18 @@
 //! Streaming response accumulator.
 
-use super::types::{StreamChunk, AccumulatedResponse};
+use super::types::{StreamChunk, AccumulatedResponse, ToolArgError};
+use crate::{ContentBlock, InternalMessage, REASONING_PROVIDER};
 use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Generate a tool call ID for a call whose id never arrived
+fn generate_call_id(position: usize) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    format!("call_{:x}{:02x}", now, position)
+}
 
 /// Accumulates streaming chunks into a complete response.
 ///
@@ -11,7 +22,10 @@ use std::collections::HashMap;
 #[derive(Debug, Default)]
 pub struct StreamingAccumulator {
     text: String,
+    reasoning: String,
     tool_calls: HashMap<usize, crate::ToolCall>,
+    response_id: Option<String>,
+    model: Option<String>,
 }
 
 impl StreamingAccumulator {
@@ -23,10 +37,23 @@ impl StreamingAccumulator {
     /// Process a single chunk and accumulate it
     pub fn process_chunk(&mut self, chunk: StreamChunk) -> bool {
         match chunk {
+            StreamChunk::Start { id, model, .. } => {
+                if id.is_some() {
+                    self.response_id = id;
+                }
+                if model.is_some() {
+                    self.model = model;
+                }
+                false // Not done
+            }
             StreamChunk::Text(text) => {
                 self.text.push_str(&text);
                 false // Not done
             }
+            StreamChunk::Reasoning(reasoning) => {
+                self.reasoning.push_str(&reasoning);
+                false // Not done
+            }
             StreamChunk::ToolCallDelta { index, id, name, arguments_delta } => {
                 // Create tool call entry if it doesn't exist
                 let tool_call = self.tool_calls.entry(index).or_insert_with(|| {
@@ -59,16 +86,116 @@ impl StreamingAccumulator {
 
     /// Get the accumulated response
     pub fn finish(self) -> AccumulatedResponse {
-        // Convert HashMap to Vec, filtering out empty tool calls
-        let tool_calls: Vec<crate::ToolCall> = self.tool_calls
+        self.finish_with_duplicate_hook(|_| {})
+    }
+
+    /// Get the accumulated response, reporting duplicate tool call ids
+    ///
+    /// Some providers emit the same tool call id at several indices. Such
+    /// entries are merged into one tool call (arguments concatenated in index
+    /// order) and `on_duplicate` is called with the id for each merge.
+    pub fn finish_with_duplicate_hook<F>(self, mut on_duplicate: F) -> AccumulatedResponse
+    where
+        F: FnMut(&str),
+    {
+        let mut indexed: Vec<_> = self.tool_calls.into_iter().collect();
+        indexed.sort_by_key(|(index, _)| *index);
+
+        // Merge entries sharing a non-empty id into the first occurrence
+        let mut merged: Vec<crate::ToolCall> = Vec::with_capacity(indexed.len());
+        for (_, tc) in indexed {
+            let existing = merged
+                .iter_mut()
+                .find(|m| !tc.id.is_empty() && m.id == tc.id);
+            match existing {
+                Some(first) => {
+                    on_duplicate(&tc.id);
+                    if first.function.name.is_empty() {
+                        first.function.name = tc.function.name;
+                    }
+                    first.function.arguments.push_str(&tc.function.arguments);
+                }
+                None => merged.push(tc),
+            }
+        }
+
+        // Filter out empty tool calls
+        let mut tool_calls: Vec<crate::ToolCall> = merged
             .into_iter()
-            .map(|(_, tool_call)| tool_call)
             .filter(|tc| !tc.function.name.is_empty())
             .collect();
 
+        // The id may never arrive for an index; never emit an empty id
+        for (i, tc) in tool_calls.iter_mut().enumerate() {
+            if tc.id.is_empty() {
+                tc.id = generate_call_id(i);
+            }
+        }
+
         AccumulatedResponse {
             text: self.text,
+            reasoning: self.reasoning,
             tool_calls,
+            response_id: self.response_id,
+            model: self.model,
+        }
+    }
+
+    /// Get the accumulated response as an assistant message
+    ///
+    /// Text becomes a leading text block and each tool call a tool use block.
+    /// Streamed reasoning becomes a thinking block (see
+    /// [`ContentBlock::thinking`]) placed before the text, however reasoning
+    /// and text deltas were interleaved; its provider is
+    /// [`REASONING_PROVIDER`], so converters never send it back.
+    /// Arguments that are not valid JSON are kept as a raw string input; use
+    /// [`finish_message_checked`](Self::finish_message_checked) to reject
+    /// them instead. The response id and model are stored in metadata as
+    /// `response_id` and `model`.
+    pub fn finish_message(self) -> InternalMessage {
+        self.finish_message_with_raw_arguments().0
+    }
+
+    /// Get the accumulated response as an assistant message, along with the
+    /// verbatim arguments string of each tool call keyed by tool call id
+    ///
+    /// The tool use inputs are parsed values, so re-serializing them may
+    /// reorder keys or reformat numbers; the returned strings are the exact
+    /// bytes the model produced, suitable as cache keys.
+    pub fn finish_message_with_raw_arguments(self) -> (InternalMessage, HashMap<String, String>) {
+        lenient_message(self.finish())
+    }
+
+    /// Get the accumulated response as an assistant message, requiring valid
+    /// tool call arguments
+    ///
+    /// Like [`finish_message`](Self::finish_message), but fails with one
+    /// [`ToolArgError`] per tool call whose arguments do not parse as JSON.
+    /// Empty arguments are accepted as `{}`.
+    pub fn finish_message_checked(self) -> Result<InternalMessage, Vec<ToolArgError>> {
+        let response = self.finish();
+        let mut blocks = Vec::with_capacity(response.tool_calls.len());
+        let mut errors = Vec::new();
+        for tc in &response.tool_calls {
+            match parse_arguments(&tc.function.arguments) {
+                Ok(input) => blocks.push(ContentBlock::tool_use(
+                    tc.id.clone(),
+                    tc.function.name.clone(),
+                    input,
+                )),
+                Err(err) => errors.push(ToolArgError {
+                    tool_call_id: tc.id.clone(),
+                    tool_name: tc.function.name.clone(),
+                    raw_arguments: tc.function.arguments.clone(),
+                    error: err.to_string(),
+                }),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(response_message(response, blocks))
+        } else {
+            Err(errors)
         }
     }
 
@@ -94,3 +221,59 @@ impl StreamingAccumulator {
         Ok(accumulator.finish())
     }
 }
+
+/// Build the assistant message for a response, keeping unparseable
+/// arguments as raw string inputs, along with the verbatim arguments
+pub(super) fn lenient_message(
+    response: AccumulatedResponse,
+) -> (InternalMessage, HashMap<String, String>) {
+    let mut raw_arguments = HashMap::with_capacity(response.tool_calls.len());
+    let blocks = response
+        .tool_calls
+        .iter()
+        .map(|tc| {
+            let input = parse_arguments(&tc.function.arguments)
+                .unwrap_or_else(|_| serde_json::Value::String(tc.function.arguments.clone()));
+            raw_arguments.insert(tc.id.clone(), tc.function.arguments.clone());
+            ContentBlock::tool_use(tc.id.clone(), tc.function.name.clone(), input)
+        })
+        .collect();
+    (response_message(response, blocks), raw_arguments)
+}
+
+/// Parse accumulated tool call arguments, treating an empty string as `{}`
+fn parse_arguments(arguments: &str) -> Result<serde_json::Value, serde_json::Error> {
+    if arguments.trim().is_empty() {
+        return Ok(serde_json::json!({}));
+    }
+    serde_json::from_str(arguments)
+}
+
+/// Build the assistant message for a response and its tool use blocks
+fn response_message(
+    response: AccumulatedResponse,
+    tool_uses: Vec<ContentBlock>,
+) -> InternalMessage {
+    let mut msg = if !response.reasoning.is_empty() {
+        let thinking = ContentBlock::thinking(REASONING_PROVIDER, response.reasoning, None);
+        let mut blocks = vec![thinking];
+        if !response.text.is_empty() {
+            blocks.push(ContentBlock::text(response.text));
+        }
+        blocks.extend(tool_uses);
+        InternalMessage::assistant_blocks(blocks)
+    } else if tool_uses.is_empty() {
+        InternalMessage::assistant(response.text)
+    } else if response.text.is_empty() {
+        InternalMessage::assistant_blocks(tool_uses)
+    } else {
+        InternalMessage::assistant_with_tools(response.text, tool_uses)
+    };
+
+    for (key, value) in [("response_id", response.response_id), ("model", response.model)] {
+        if let Some(value) = value {
+            msg.metadata.insert(key.to_string(), value);
+        }
+    }
+    msg
+}
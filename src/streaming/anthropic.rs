@@ -0,0 +1,183 @@
+//! Anthropic Messages stream reconstruction.
+
+use crate::{ContentBlock, InternalMessage};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// A content block being assembled from stream events
+#[derive(Debug)]
+enum PartialBlock {
+    Text(String),
+    ToolUse {
+        id: String,
+        name: String,
+        initial_input: Value,
+        partial_json: String,
+    },
+    Thinking {
+        thinking: String,
+        signature: String,
+    },
+    /// Any other block type, kept as provided in `content_block_start`
+    Other(Value),
+}
+
+/// Accumulates Anthropic Messages stream events into an assistant message.
+///
+/// Feed the parsed JSON `data` of each SSE event to
+/// [`process_event`](Self::process_event). Blocks are assembled by their
+/// stream `index`, so the final message keeps the order the model produced:
+/// text blocks, thinking blocks (see [`ContentBlock::thinking`], signature
+/// included) and tool use blocks with their streamed JSON input.
+#[derive(Debug, Default)]
+pub struct AnthropicStreamAccumulator {
+    blocks: BTreeMap<usize, PartialBlock>,
+    message_id: Option<String>,
+    model: Option<String>,
+    stop_reason: Option<String>,
+}
+
+impl AnthropicStreamAccumulator {
+    /// Create a new accumulator
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Process a single stream event, returning true on `message_stop`
+    pub fn process_event(&mut self, event: &Value) -> bool {
+        let index = event["index"].as_u64().unwrap_or(0) as usize;
+        match event["type"].as_str() {
+            Some("message_start") => {
+                let message = &event["message"];
+                self.message_id = message["id"].as_str().map(str::to_string);
+                self.model = message["model"].as_str().map(str::to_string);
+            }
+            Some("content_block_start") => {
+                let block = &event["content_block"];
+                let partial = match block["type"].as_str() {
+                    Some("text") => PartialBlock::Text(string_field(block, "text")),
+                    Some("tool_use") => PartialBlock::ToolUse {
+                        id: string_field(block, "id"),
+                        name: string_field(block, "name"),
+                        initial_input: block["input"].clone(),
+                        partial_json: String::new(),
+                    },
+                    Some("thinking") => PartialBlock::Thinking {
+                        thinking: string_field(block, "thinking"),
+                        signature: String::new(),
+                    },
+                    _ => PartialBlock::Other(block.clone()),
+                };
+                self.blocks.insert(index, partial);
+            }
+            Some("content_block_delta") => {
+                let delta = &event["delta"];
+                let fragment = |key: &str| delta[key].as_str().unwrap_or_default();
+                match (self.blocks.get_mut(&index), delta["type"].as_str()) {
+                    (Some(PartialBlock::Text(text)), Some("text_delta")) => {
+                        text.push_str(fragment("text"));
+                    }
+                    (
+                        Some(PartialBlock::ToolUse { partial_json, .. }),
+                        Some("input_json_delta"),
+                    ) => {
+                        partial_json.push_str(fragment("partial_json"));
+                    }
+                    (Some(PartialBlock::Thinking { thinking, .. }), Some("thinking_delta")) => {
+                        thinking.push_str(fragment("thinking"));
+                    }
+                    (Some(PartialBlock::Thinking { signature, .. }), Some("signature_delta")) => {
+                        signature.push_str(fragment("signature"));
+                    }
+                    _ => {}
+                }
+            }
+            Some("message_delta") => {
+                if let Some(reason) = event["delta"]["stop_reason"].as_str() {
+                    self.stop_reason = Some(reason.to_string());
+                }
+            }
+            Some("message_stop") => return true,
+            _ => {}
+        }
+        false
+    }
+
+    /// Build the assistant message
+    ///
+    /// The message id, model and stop reason (when present) are stored in
+    /// metadata as `response_id`, `model` and `stop_reason`.
+    pub fn finish(self) -> InternalMessage {
+        let blocks = self.blocks.into_values().map(finish_block).collect();
+        let mut msg = InternalMessage::assistant_blocks(blocks);
+
+        let metadata = [
+            ("response_id", self.message_id),
+            ("model", self.model),
+            ("stop_reason", self.stop_reason),
+        ];
+        for (key, value) in metadata {
+            if let Some(value) = value {
+                msg.metadata.insert(key.to_string(), value);
+            }
+        }
+        msg
+    }
+
+    /// Accumulate an entire Anthropic event stream into a message
+    pub async fn accumulate_stream<S, E>(mut stream: S) -> Result<InternalMessage, E>
+    where
+        S: futures_util::Stream<Item = Result<Value, E>> + Unpin,
+    {
+        use futures_util::StreamExt;
+
+        let mut accumulator = Self::new();
+
+        while let Some(event) = stream.next().await {
+            if accumulator.process_event(&event?) {
+                break;
+            }
+        }
+
+        Ok(accumulator.finish())
+    }
+}
+
+/// Accumulate an Anthropic event stream into an assistant [`InternalMessage`]
+///
+/// Convenience wrapper around [`AnthropicStreamAccumulator::accumulate_stream`].
+pub async fn accumulate_anthropic_stream<S, E>(stream: S) -> Result<InternalMessage, E>
+where
+    S: futures_util::Stream<Item = Result<Value, E>> + Unpin,
+{
+    AnthropicStreamAccumulator::accumulate_stream(stream).await
+}
+
+fn finish_block(block: PartialBlock) -> ContentBlock {
+    match block {
+        PartialBlock::Text(text) => ContentBlock::text(text),
+        PartialBlock::ToolUse {
+            id,
+            name,
+            initial_input,
+            partial_json,
+        } => {
+            // Input arrives as JSON fragments; fall back to the start value
+            let input = if partial_json.is_empty() {
+                initial_input
+            } else {
+                serde_json::from_str(&partial_json).unwrap_or(Value::String(partial_json))
+            };
+            ContentBlock::tool_use(id, name, input)
+        }
+        PartialBlock::Thinking {
+            thinking,
+            signature,
+        } => ContentBlock::thinking("anthropic", thinking, Some(signature)),
+        PartialBlock::Other(value) => ContentBlock::raw("anthropic", value),
+    }
+}
+
+fn string_field(value: &Value, field: &str) -> String {
+    value[field].as_str().unwrap_or_default().to_string()
+}
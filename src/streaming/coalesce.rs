@@ -0,0 +1,101 @@
+//! Coalescing of streamed text deltas.
+
+use super::StreamChunk;
+use futures_util::{Stream, StreamExt};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::Poll;
+use std::time::{Duration, Instant};
+
+/// Merge consecutive [`StreamChunk::Text`] deltas arriving within
+/// `min_interval` into one larger `Text` chunk
+///
+/// A merged chunk spans at most `min_interval` from its first delta. It is
+/// emitted when a later delta arrives outside that window, before any other
+/// chunk or error (which pass through unchanged and in order), when the
+/// stream ends, or once `min_interval` has passed since its first delta.
+/// Buffered text is kept while the underlying stream is pending; `sleep` is
+/// called with the time left in the window to wake the stream when it ends,
+/// so a pause in generation holds text back by at most `min_interval`. Pass
+/// the runtime's timer, e.g. `tokio::time::sleep`. A zero `min_interval`
+/// disables merging.
+pub fn coalesce<S, E, F, T>(
+    mut stream: S,
+    min_interval: Duration,
+    mut sleep: F,
+) -> impl Stream<Item = Result<StreamChunk, E>>
+where
+    S: Stream<Item = Result<StreamChunk, E>> + Unpin,
+    F: FnMut(Duration) -> T,
+    T: Future<Output = ()>,
+{
+    let mut buffer: Option<(String, Instant)> = None;
+    let mut timer: Option<Pin<Box<T>>> = None;
+    let mut held: Option<Result<StreamChunk, E>> = None;
+    let mut finished = false;
+
+    futures_util::stream::poll_fn(move |cx| {
+        if let Some(item) = held.take() {
+            return Poll::Ready(Some(item));
+        }
+        if finished {
+            return Poll::Ready(None);
+        }
+
+        loop {
+            let item = match stream.poll_next_unpin(cx) {
+                Poll::Ready(item) => item,
+                Poll::Pending => {
+                    let Some((_, started)) = &buffer else {
+                        return Poll::Pending;
+                    };
+                    let elapsed = started.elapsed();
+                    let expired = elapsed >= min_interval
+                        || timer
+                            .get_or_insert_with(|| Box::pin(sleep(min_interval - elapsed)))
+                            .as_mut()
+                            .poll(cx)
+                            .is_ready();
+                    if !expired {
+                        return Poll::Pending;
+                    }
+                    timer = None;
+                    let (merged, _) = buffer.take().expect("buffer checked above");
+                    return Poll::Ready(Some(Ok(StreamChunk::Text(merged))));
+                }
+            };
+            match item {
+                Some(Ok(StreamChunk::Text(text))) => {
+                    let now = Instant::now();
+                    match &mut buffer {
+                        Some((merged, started)) if now - *started < min_interval => {
+                            merged.push_str(&text);
+                        }
+                        _ => {
+                            timer = None;
+                            if let Some((merged, _)) = buffer.replace((text, now)) {
+                                return Poll::Ready(Some(Ok(StreamChunk::Text(merged))));
+                            }
+                        }
+                    }
+                }
+                Some(other) => {
+                    timer = None;
+                    return Poll::Ready(Some(match buffer.take() {
+                        Some((merged, _)) => {
+                            held = Some(other);
+                            Ok(StreamChunk::Text(merged))
+                        }
+                        None => other,
+                    }));
+                }
+                None => {
+                    finished = true;
+                    timer = None;
+                    let merged = buffer.take().map(|(merged, _)| Ok(StreamChunk::Text(merged)));
+                    return Poll::Ready(merged);
+                }
+            }
+        }
+    })
+}
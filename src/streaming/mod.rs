@@ -4,10 +4,14 @@
 //! handling both text deltas and tool call deltas with sparse index support.
 
 mod accumulator;
+mod anthropic;
+mod coalesce;
 mod types;
 
 pub use accumulator::StreamingAccumulator;
-pub use types::{StreamChunk, AccumulatedResponse};
+pub use anthropic::{accumulate_anthropic_stream, AnthropicStreamAccumulator};
+pub use coalesce::coalesce;
+pub use types::{StreamChunk, AccumulatedResponse, ToolArgError};
 
 #[cfg(test)]
 mod tests;
@@ -1,7 +1,6 @@
 //! Tests for streaming accumulator
 
 use super::*;
-use crate::{ToolCall, FunctionCall};
 
 #[test]
 fn test_text_accumulation() {
@@ -137,3 +136,512 @@ fn test_done_chunk() {
     let done = acc.process_chunk(StreamChunk::Done);
     assert!(done);
 }
+
+#[test]
+fn test_response_predicates() {
+    // Tool-only (whitespace text is ignored)
+    let mut acc = StreamingAccumulator::new();
+    acc.process_chunk(StreamChunk::Text("\n".to_string()));
+    acc.process_chunk(StreamChunk::ToolCallDelta {
+        index: 0,
+        id: Some("call_1".to_string()),
+        name: Some("search".to_string()),
+        arguments_delta: Some("{}".to_string()),
+    });
+    let response = acc.finish();
+    assert!(response.has_tool_calls());
+    assert!(response.is_tool_call_only());
+    assert!(!response.is_text_only());
+
+    // Text-only
+    let mut acc = StreamingAccumulator::new();
+    acc.process_chunk(StreamChunk::Text("Final answer".to_string()));
+    let response = acc.finish();
+    assert!(!response.has_tool_calls());
+    assert!(!response.is_tool_call_only());
+    assert!(response.is_text_only());
+
+    // Mixed
+    let mut acc = StreamingAccumulator::new();
+    acc.process_chunk(StreamChunk::Text("Let me check".to_string()));
+    acc.process_chunk(StreamChunk::ToolCallDelta {
+        index: 0,
+        id: Some("call_2".to_string()),
+        name: Some("search".to_string()),
+        arguments_delta: Some("{}".to_string()),
+    });
+    let response = acc.finish();
+    assert!(response.has_tool_calls());
+    assert!(!response.is_tool_call_only());
+    assert!(!response.is_text_only());
+}
+
+#[test]
+fn test_start_chunk_metadata() {
+    let mut acc = StreamingAccumulator::new();
+
+    acc.process_chunk(StreamChunk::Start {
+        id: Some("chatcmpl-123".to_string()),
+        model: Some("gpt-4o".to_string()),
+        role: Some("assistant".to_string()),
+    });
+    acc.process_chunk(StreamChunk::Text("Hello".to_string()));
+
+    let response = acc.finish();
+    assert_eq!(response.response_id.as_deref(), Some("chatcmpl-123"));
+    assert_eq!(response.model.as_deref(), Some("gpt-4o"));
+    assert_eq!(response.text, "Hello");
+}
+
+#[test]
+fn test_missing_tool_call_id_is_generated() {
+    let mut acc = StreamingAccumulator::new();
+
+    acc.process_chunk(StreamChunk::ToolCallDelta {
+        index: 0,
+        id: None,
+        name: Some("search".to_string()),
+        arguments_delta: Some("{\"q\": \"rust\"}".to_string()),
+    });
+    acc.process_chunk(StreamChunk::ToolCallDelta {
+        index: 1,
+        id: None,
+        name: Some("open".to_string()),
+        arguments_delta: Some("{}".to_string()),
+    });
+
+    let response = acc.finish();
+    assert_eq!(response.tool_calls.len(), 2);
+    for tc in &response.tool_calls {
+        assert!(tc.id.starts_with("call_"));
+        assert!(tc.id.len() > "call_".len());
+    }
+    assert_ne!(response.tool_calls[0].id, response.tool_calls[1].id);
+}
+
+#[test]
+fn test_duplicate_tool_call_ids_merged() {
+    let mut acc = StreamingAccumulator::new();
+
+    acc.process_chunk(StreamChunk::ToolCallDelta {
+        index: 1,
+        id: Some("call_dup".to_string()),
+        name: None,
+        arguments_delta: Some("\"rust\"}".to_string()),
+    });
+    acc.process_chunk(StreamChunk::ToolCallDelta {
+        index: 0,
+        id: Some("call_dup".to_string()),
+        name: Some("search".to_string()),
+        arguments_delta: Some("{\"q\": ".to_string()),
+    });
+
+    let mut duplicates = Vec::new();
+    let response = acc.finish_with_duplicate_hook(|id| duplicates.push(id.to_string()));
+
+    assert_eq!(response.tool_calls.len(), 1);
+    assert_eq!(response.tool_calls[0].id, "call_dup");
+    assert_eq!(response.tool_calls[0].function.name, "search");
+    assert_eq!(response.tool_calls[0].function.arguments, "{\"q\": \"rust\"}");
+    assert_eq!(duplicates, vec!["call_dup".to_string()]);
+}
+
+#[test]
+fn test_tool_calls_in_index_order() {
+    let mut acc = StreamingAccumulator::new();
+
+    for (index, name) in [(2, "third"), (0, "first"), (1, "second")] {
+        acc.process_chunk(StreamChunk::ToolCallDelta {
+            index,
+            id: Some(format!("call_{}", index)),
+            name: Some(name.to_string()),
+            arguments_delta: Some("{}".to_string()),
+        });
+    }
+
+    let names: Vec<_> = acc
+        .finish()
+        .tool_calls
+        .into_iter()
+        .map(|tc| tc.function.name)
+        .collect();
+    assert_eq!(names, vec!["first", "second", "third"]);
+}
+
+#[test]
+fn test_stream_chunk_wire_format() {
+    let cases = vec![
+        (
+            StreamChunk::Start {
+                id: Some("chatcmpl-1".to_string()),
+                model: Some("gpt-4o".to_string()),
+                role: None,
+            },
+            serde_json::json!({"type": "start", "id": "chatcmpl-1", "model": "gpt-4o"}),
+        ),
+        (
+            StreamChunk::Text("Hello".to_string()),
+            serde_json::json!({"type": "text", "text": "Hello"}),
+        ),
+        (
+            StreamChunk::ToolCallDelta {
+                index: 1,
+                id: Some("call_1".to_string()),
+                name: Some("search".to_string()),
+                arguments_delta: Some("{}".to_string()),
+            },
+            serde_json::json!({
+                "type": "tool_call_delta",
+                "index": 1,
+                "id": "call_1",
+                "name": "search",
+                "arguments_delta": "{}"
+            }),
+        ),
+        (
+            StreamChunk::ToolCallDelta {
+                index: 0,
+                id: None,
+                name: None,
+                arguments_delta: Some("{\"q".to_string()),
+            },
+            serde_json::json!({"type": "tool_call_delta", "index": 0, "arguments_delta": "{\"q"}),
+        ),
+        (StreamChunk::Done, serde_json::json!({"type": "done"})),
+    ];
+
+    for (chunk, expected) in cases {
+        let json = serde_json::to_value(&chunk).unwrap();
+        assert_eq!(json, expected);
+        let parsed: StreamChunk = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, chunk);
+    }
+}
+
+#[test]
+fn test_finish_message() {
+    let mut acc = StreamingAccumulator::new();
+    acc.process_chunk(StreamChunk::Text("Checking".to_string()));
+    acc.process_chunk(StreamChunk::ToolCallDelta {
+        index: 0,
+        id: Some("call_1".to_string()),
+        name: Some("search".to_string()),
+        arguments_delta: Some(r#"{"query": "rust"}"#.to_string()),
+    });
+
+    let msg = acc.finish_message();
+    let blocks = msg.blocks().unwrap();
+    assert_eq!(blocks[0].as_text(), Some("Checking"));
+    let (id, name, input) = blocks[1].as_tool_use().unwrap();
+    assert_eq!((id, name), ("call_1", "search"));
+    assert_eq!(input["query"], "rust");
+}
+
+#[test]
+fn test_finish_message_checked_reports_truncated_arguments() {
+    let truncated = || {
+        let mut acc = StreamingAccumulator::new();
+        acc.process_chunk(StreamChunk::ToolCallDelta {
+            index: 0,
+            id: Some("call_1".to_string()),
+            name: Some("search".to_string()),
+            arguments_delta: Some(r#"{"query": "ru"#.to_string()),
+        });
+        acc.process_chunk(StreamChunk::ToolCallDelta {
+            index: 1,
+            id: Some("call_2".to_string()),
+            name: Some("list".to_string()),
+            arguments_delta: None,
+        });
+        acc
+    };
+
+    let errors = truncated().finish_message_checked().unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].tool_name, "search");
+    assert_eq!(errors[0].raw_arguments, r#"{"query": "ru"#);
+
+    // The lenient variant keeps the raw string instead
+    let msg = truncated().finish_message();
+    let (_, _, input) = msg.blocks().unwrap()[0].as_tool_use().unwrap();
+    assert_eq!(input, &serde_json::json!(r#"{"query": "ru"#));
+    let (_, _, input) = msg.blocks().unwrap()[1].as_tool_use().unwrap();
+    assert_eq!(input, &serde_json::json!({}));
+}
+
+#[test]
+fn test_raw_arguments_preserved_verbatim() {
+    let raw = r#"{"limit": 10.0, "query": "rust", "at": 1.50}"#;
+    let accumulator = || {
+        let mut acc = StreamingAccumulator::new();
+        for (i, part) in [&raw[..12], &raw[12..]].into_iter().enumerate() {
+            acc.process_chunk(StreamChunk::ToolCallDelta {
+                index: 0,
+                id: (i == 0).then(|| "call_1".to_string()),
+                name: (i == 0).then(|| "search".to_string()),
+                arguments_delta: Some(part.to_string()),
+            });
+        }
+        acc
+    };
+
+    let response = accumulator().finish();
+    assert_eq!(response.tool_calls[0].raw_arguments(), raw);
+
+    let (msg, raw_arguments) = accumulator().finish_message_with_raw_arguments();
+    assert_eq!(raw_arguments["call_1"], raw);
+    let (_, _, input) = msg.blocks().unwrap()[0].as_tool_use().unwrap();
+    assert_ne!(input.to_string(), raw);
+    assert_eq!(input["at"], 1.5);
+}
+
+#[cfg(feature = "events")]
+#[test]
+fn test_into_message_event() {
+    let mut acc = StreamingAccumulator::new();
+    acc.process_chunk(StreamChunk::Start {
+        id: Some("chatcmpl-1".to_string()),
+        model: Some("gpt-4o".to_string()),
+        role: Some("assistant".to_string()),
+    });
+    acc.process_chunk(StreamChunk::ToolCallDelta {
+        index: 0,
+        id: Some("call_1".to_string()),
+        name: Some("search".to_string()),
+        arguments_delta: Some(r#"{"query": "rust"}"#.to_string()),
+    });
+
+    let event = acc.finish().into_message_event("session_1", 7);
+    assert_eq!(event.session_id, "session_1");
+    assert_eq!(event.sequence, 7);
+    assert_eq!(event.message.role, crate::MessageRole::Assistant);
+    let (id, name, input) = event.message.blocks().unwrap()[0].as_tool_use().unwrap();
+    assert_eq!((id, name), ("call_1", "search"));
+    assert_eq!(input["query"], "rust");
+    assert_eq!(event.model_info.unwrap().model_name, "gpt-4o");
+    assert_eq!(event.message.metadata["response_id"], "chatcmpl-1");
+}
+
+/// Anthropic SSE fixture: thinking, text, then a tool call
+const ANTHROPIC_STREAM: &str = r#"event: message_start
+data: {"type":"message_start","message":{"id":"msg_01","type":"message","role":"assistant","model":"claude-sonnet-4","content":[]}}
+
+event: content_block_start
+data: {"type":"content_block_start","index":0,"content_block":{"type":"thinking","thinking":""}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":0,"delta":{"type":"thinking_delta","thinking":"The user wants the weather."}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":0,"delta":{"type":"signature_delta","signature":"sig123"}}
+
+event: content_block_stop
+data: {"type":"content_block_stop","index":0}
+
+event: content_block_start
+data: {"type":"content_block_start","index":1,"content_block":{"type":"text","text":""}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":1,"delta":{"type":"text_delta","text":"Let me "}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":1,"delta":{"type":"text_delta","text":"check."}}
+
+event: content_block_stop
+data: {"type":"content_block_stop","index":1}
+
+event: content_block_start
+data: {"type":"content_block_start","index":2,"content_block":{"type":"tool_use","id":"toolu_01","name":"get_weather","input":{}}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":2,"delta":{"type":"input_json_delta","partial_json":"{\"city\": "}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":2,"delta":{"type":"input_json_delta","partial_json":"\"Paris\"}"}}
+
+event: content_block_stop
+data: {"type":"content_block_stop","index":2}
+
+event: message_delta
+data: {"type":"message_delta","delta":{"stop_reason":"tool_use"}}
+
+event: message_stop
+data: {"type":"message_stop"}
+"#;
+
+fn anthropic_events() -> Vec<serde_json::Value> {
+    ANTHROPIC_STREAM
+        .lines()
+        .filter_map(|line| line.strip_prefix("data: "))
+        .map(|data| serde_json::from_str(data).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_accumulate_anthropic_stream() {
+    use futures_util::FutureExt;
+
+    let events = anthropic_events()
+        .into_iter()
+        .map(Ok::<_, std::convert::Infallible>);
+    let msg = accumulate_anthropic_stream(futures_util::stream::iter(events))
+        .now_or_never()
+        .expect("fixture stream is always ready")
+        .unwrap();
+
+    assert_eq!(msg.role, crate::MessageRole::Assistant);
+    let blocks = msg.blocks().unwrap();
+    assert_eq!(blocks.len(), 3);
+
+    let (provider, thinking) = blocks[0].as_raw().unwrap();
+    assert_eq!(provider, "anthropic");
+    assert_eq!(thinking["thinking"], "The user wants the weather.");
+    assert_eq!(thinking["signature"], "sig123");
+    assert_eq!(blocks[0].as_thinking(), Some("The user wants the weather."));
+
+    assert_eq!(blocks[1].as_text(), Some("Let me check."));
+
+    let (id, name, input) = blocks[2].as_tool_use().unwrap();
+    assert_eq!((id, name), ("toolu_01", "get_weather"));
+    assert_eq!(input["city"], "Paris");
+
+    assert_eq!(msg.metadata.get("response_id").map(String::as_str), Some("msg_01"));
+    assert_eq!(msg.metadata.get("stop_reason").map(String::as_str), Some("tool_use"));
+}
+
+#[test]
+fn test_anthropic_tool_use_without_deltas_keeps_start_input() {
+    let mut acc = AnthropicStreamAccumulator::new();
+    acc.process_event(&serde_json::json!({
+        "type": "content_block_start",
+        "index": 0,
+        "content_block": {"type": "tool_use", "id": "toolu_1", "name": "ping", "input": {}}
+    }));
+    assert!(acc.process_event(&serde_json::json!({"type": "message_stop"})));
+
+    let msg = acc.finish();
+    let (_, _, input) = msg.blocks().unwrap()[0].as_tool_use().unwrap();
+    assert_eq!(input, &serde_json::json!({}));
+}
+
+#[test]
+fn test_reasoning_precedes_text() {
+    let mut acc = StreamingAccumulator::new();
+    acc.process_chunk(StreamChunk::Reasoning("The user ".to_string()));
+    acc.process_chunk(StreamChunk::Text("Hello".to_string()));
+    acc.process_chunk(StreamChunk::Reasoning("says hi.".to_string()));
+    acc.process_chunk(StreamChunk::Text("!".to_string()));
+    acc.process_chunk(StreamChunk::Done);
+
+    let msg = acc.finish_message();
+    let blocks = msg.blocks().unwrap();
+    assert_eq!(blocks.len(), 2);
+    let (provider, _) = blocks[0].as_raw().unwrap();
+    assert_eq!(provider, crate::REASONING_PROVIDER);
+    assert_eq!(blocks[0].as_thinking(), Some("The user says hi."));
+    assert_eq!(blocks[1].as_text(), Some("Hello!"));
+
+    // Reasoning blocks are never sent back to a provider
+    assert_eq!(crate::to_openai_message(&msg)["content"], "Hello!");
+}
+
+/// Timer for `coalesce` tests whose source never pends
+fn no_timer(_: std::time::Duration) -> futures_util::future::Pending<()> {
+    futures_util::future::pending()
+}
+
+#[test]
+fn test_coalesce_text_deltas() {
+    use futures_util::{FutureExt, StreamExt};
+    use std::time::Duration;
+
+    let text = |t: &str| Ok::<_, std::convert::Infallible>(StreamChunk::Text(t.to_string()));
+    let chunks = vec![
+        text("Hel"),
+        text("lo"),
+        text(" world"),
+        Ok(StreamChunk::ToolCallDelta {
+            index: 0,
+            id: Some("call_1".to_string()),
+            name: Some("search".to_string()),
+            arguments_delta: None,
+        }),
+        text("Done"),
+        text("!"),
+        Ok(StreamChunk::Done),
+    ];
+
+    let coalesced: Vec<_> =
+        coalesce(futures_util::stream::iter(chunks.clone()), Duration::from_secs(60), no_timer)
+            .map(Result::unwrap)
+            .collect()
+            .now_or_never()
+            .unwrap();
+    assert_eq!(coalesced.len(), 4);
+    assert_eq!(coalesced[0], StreamChunk::Text("Hello world".to_string()));
+    assert!(matches!(coalesced[1], StreamChunk::ToolCallDelta { index: 0, .. }));
+    assert_eq!(coalesced[2], StreamChunk::Text("Done!".to_string()));
+    assert_eq!(coalesced[3], StreamChunk::Done);
+
+    // A zero interval passes every delta through
+    let passed: Vec<_> = coalesce(futures_util::stream::iter(chunks), Duration::ZERO, no_timer)
+        .map(Result::unwrap)
+        .collect()
+        .now_or_never()
+        .unwrap();
+    assert_eq!(passed.len(), 7);
+}
+
+#[test]
+fn test_coalesce_buffers_across_pending() {
+    use futures_util::StreamExt;
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::task::{Context, Poll};
+    use std::time::Duration;
+
+    let text = |t: &str| Ok::<_, std::convert::Infallible>(StreamChunk::Text(t.to_string()));
+    // Token-by-token delivery: the source pends between every delta
+    let mut step = 0;
+    let source = futures_util::stream::poll_fn(move |_| {
+        step += 1;
+        match step {
+            1 => Poll::Ready(Some(text("Hel"))),
+            3 => Poll::Ready(Some(text("lo"))),
+            5 => Poll::Ready(Some(text(" world"))),
+            2 | 4 | 6 | 7 => Poll::Pending,
+            _ => Poll::Ready(None),
+        }
+    });
+
+    let fired = Rc::new(Cell::new(false));
+    let timers = Rc::new(Cell::new(0));
+    let sleep = {
+        let (fired, timers) = (fired.clone(), timers.clone());
+        move |_: Duration| {
+            timers.set(timers.get() + 1);
+            let fired = fired.clone();
+            futures_util::future::poll_fn(move |_| {
+                if fired.get() {
+                    Poll::Ready(())
+                } else {
+                    Poll::Pending
+                }
+            })
+        }
+    };
+
+    let mut stream = coalesce(source, Duration::from_secs(60), sleep);
+    let mut cx = Context::from_waker(futures_util::task::noop_waker_ref());
+    // Pauses between deltas do not flush the buffer
+    for _ in 0..3 {
+        assert_eq!(stream.poll_next_unpin(&mut cx), Poll::Pending);
+    }
+    // One timer covers the whole window
+    assert_eq!(timers.get(), 1);
+
+    // The window closing flushes everything merged so far
+    fired.set(true);
+    assert_eq!(stream.poll_next_unpin(&mut cx), Poll::Ready(Some(text("Hello world"))));
+    assert_eq!(stream.poll_next_unpin(&mut cx), Poll::Ready(None));
+}
@@ -1,12 +1,37 @@
 //! Type definitions for streaming responses.
 
+#[cfg(feature = "events")]
+use crate::events::MessageEvent;
+use crate::InternalMessage;
 use serde::{Deserialize, Serialize};
 
 /// Streaming response chunk from LLM provider
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// Serializes as a self-describing object tagged by `type`:
+///
+/// ```json
+/// {"type": "start", "id": "chatcmpl-1", "model": "gpt-4o", "role": "assistant"}
+/// {"type": "reasoning", "text": "The user greets me."}
+/// {"type": "text", "text": "Hello"}
+/// {"type": "tool_call_delta", "index": 0, "id": "call_1", "name": "search", "arguments_delta": "{}"}
+/// {"type": "done"}
+/// ```
+///
+/// Absent optional fields are omitted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StreamChunkRepr", into = "StreamChunkRepr")]
 pub enum StreamChunk {
+    /// Response metadata from the first streamed event
+    /// (OpenAI sends the completion id, model and `delta.role` up front)
+    Start {
+        id: Option<String>,
+        model: Option<String>,
+        role: Option<String>,
+    },
     /// Text content delta
     Text(String),
+    /// Reasoning (thinking) delta from reasoning models
+    Reasoning(String),
     /// Tool call delta (index-based like OpenAI SSE format)
     /// Contains partial updates to tool call at given index
     ToolCallDelta {
@@ -19,11 +44,164 @@ pub enum StreamChunk {
     Done,
 }
 
+/// Wire representation of [`StreamChunk`]
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamChunkRepr {
+    Start {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        model: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        role: Option<String>,
+    },
+    Text {
+        text: String,
+    },
+    Reasoning {
+        text: String,
+    },
+    ToolCallDelta {
+        index: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        arguments_delta: Option<String>,
+    },
+    Done,
+}
+
+impl From<StreamChunkRepr> for StreamChunk {
+    fn from(repr: StreamChunkRepr) -> Self {
+        match repr {
+            StreamChunkRepr::Start { id, model, role } => Self::Start { id, model, role },
+            StreamChunkRepr::Text { text } => Self::Text(text),
+            StreamChunkRepr::Reasoning { text } => Self::Reasoning(text),
+            StreamChunkRepr::ToolCallDelta {
+                index,
+                id,
+                name,
+                arguments_delta,
+            } => Self::ToolCallDelta {
+                index,
+                id,
+                name,
+                arguments_delta,
+            },
+            StreamChunkRepr::Done => Self::Done,
+        }
+    }
+}
+
+impl From<StreamChunk> for StreamChunkRepr {
+    fn from(chunk: StreamChunk) -> Self {
+        match chunk {
+            StreamChunk::Start { id, model, role } => Self::Start { id, model, role },
+            StreamChunk::Text(text) => Self::Text { text },
+            StreamChunk::Reasoning(text) => Self::Reasoning { text },
+            StreamChunk::ToolCallDelta {
+                index,
+                id,
+                name,
+                arguments_delta,
+            } => Self::ToolCallDelta {
+                index,
+                id,
+                name,
+                arguments_delta,
+            },
+            StreamChunk::Done => Self::Done,
+        }
+    }
+}
+
 /// Accumulated response from streaming
 #[derive(Debug, Clone)]
 pub struct AccumulatedResponse {
     /// Accumulated text content
     pub text: String,
+    /// Accumulated reasoning content (empty if none was streamed)
+    pub reasoning: String,
     /// Accumulated tool calls (in index order)
     pub tool_calls: Vec<crate::ToolCall>,
+    /// Provider response ID (from the start chunk)
+    pub response_id: Option<String>,
+    /// Model that produced the response (from the start chunk)
+    pub model: Option<String>,
 }
+
+impl AccumulatedResponse {
+    /// Check if any tool calls were accumulated
+    pub fn has_tool_calls(&self) -> bool {
+        !self.tool_calls.is_empty()
+    }
+
+    /// Check if the response is only tool calls (no meaningful text)
+    pub fn is_tool_call_only(&self) -> bool {
+        self.has_tool_calls() && self.text.trim().is_empty()
+    }
+
+    /// Check if the response is only text (no tool calls)
+    pub fn is_text_only(&self) -> bool {
+        !self.has_tool_calls() && !self.text.trim().is_empty()
+    }
+
+    /// Convert into an assistant message
+    ///
+    /// Same as [`StreamingAccumulator::finish_message`]: unparseable tool
+    /// call arguments are kept as raw string inputs.
+    ///
+    /// [`StreamingAccumulator::finish_message`]: super::StreamingAccumulator::finish_message
+    pub fn into_message(self) -> InternalMessage {
+        super::accumulator::lenient_message(self).0
+    }
+
+    /// Convert into an assistant [`MessageEvent`]
+    ///
+    /// The message is built with [`into_message`](Self::into_message); the
+    /// model, when known, is recorded as the event's model info. Streamed
+    /// responses carry no usage, so the token count is left unset. Requires
+    /// the `events` feature.
+    #[cfg(feature = "events")]
+    pub fn into_message_event(self, session_id: impl Into<String>, sequence: u32) -> MessageEvent {
+        let model = self.model.clone();
+        let event = MessageEvent::new(session_id, sequence, self.into_message());
+        match model {
+            Some(model) => event.with_model_info(model, None),
+            None => event,
+        }
+    }
+}
+
+/// A tool call whose accumulated arguments are not valid JSON
+///
+/// Reported by [`finish_message_checked`], typically when the stream was cut
+/// off mid-arguments.
+///
+/// [`finish_message_checked`]: super::StreamingAccumulator::finish_message_checked
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolArgError {
+    /// Tool call ID
+    pub tool_call_id: String,
+    /// Name of the tool
+    pub tool_name: String,
+    /// The accumulated arguments string as received
+    pub raw_arguments: String,
+    /// JSON parse error message
+    pub error: String,
+}
+
+impl std::fmt::Display for ToolArgError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid arguments for tool `{}` ({}): {}",
+            self.tool_name, self.tool_call_id, self.error
+        )
+    }
+}
+
+impl std::error::Error for ToolArgError {}
@@ -0,0 +1,128 @@
+//! Token counting for UMF messages.
+//!
+//! [`TokenCounter`] wraps the `cl100k_base` tokenizer so it is loaded once and
+//! reused across many counts.
+
+use crate::{InternalMessage, UmfError};
+use tiktoken_rs::{cl100k_base, CoreBPE};
+
+/// Reusable cl100k token counter
+pub struct TokenCounter {
+    bpe: CoreBPE,
+}
+
+impl TokenCounter {
+    /// Load the cl100k tokenizer
+    pub fn new() -> Result<Self, UmfError> {
+        let bpe = cl100k_base().map_err(|e| UmfError::Tokenizer(e.to_string()))?;
+        Ok(Self { bpe })
+    }
+
+    /// Count tokens in a string
+    pub fn count_text(&self, text: &str) -> usize {
+        self.bpe.encode_with_special_tokens(text).len()
+    }
+
+    /// Count tokens in a message's text (see [`InternalMessage::to_text`])
+    pub fn count_message(&self, msg: &InternalMessage) -> usize {
+        self.count_text(&msg.to_text())
+    }
+
+    /// Count tokens in a message piece by piece
+    ///
+    /// Tokenizes each text and tool result piece separately instead of
+    /// building the concatenated text first, which keeps peak memory low for
+    /// very large messages. The newline separator is counted together with the
+    /// trailing non-alphanumeric tail of the preceding piece, since the
+    /// tokenizer merges punctuation with a following newline. A piece whose
+    /// leading whitespace holds a line break, or a whitespace-only piece
+    /// followed by another, would merge with the separator as well; such
+    /// messages, and error tool results (whose `[error]` marker is not a
+    /// piece), fall back to [`count_message`](Self::count_message), so both
+    /// always agree.
+    pub fn count_message_streaming(&self, msg: &InternalMessage) -> usize {
+        if msg.is_error_result() {
+            return self.count_message(msg);
+        }
+        let mut rest = msg.text_blocks().skip(1).peekable();
+        while let Some(piece) = rest.next() {
+            let trimmed = piece.trim_start();
+            let leading = &piece[..piece.len() - trimmed.len()];
+            if leading.contains(['\n', '\r']) || (trimmed.is_empty() && rest.peek().is_some()) {
+                return self.count_message(msg);
+            }
+        }
+
+        let mut total = 0;
+        let mut pieces = msg.text_blocks().peekable();
+        while let Some(piece) = pieces.next() {
+            if pieces.peek().is_none() {
+                total += self.count_text(piece);
+                break;
+            }
+            let cut = piece
+                .char_indices()
+                .rev()
+                .find(|(_, c)| c.is_alphanumeric())
+                .map(|(i, c)| i + c.len_utf8())
+                .unwrap_or(0);
+            total += self.count_text(&piece[..cut]);
+            total += self.count_text(&format!("{}\n", &piece[cut..]));
+        }
+        total
+    }
+}
+
+/// Per-model token pricing, in dollars per 1,000 tokens
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pricing {
+    /// Price of uncached input tokens
+    pub input_per_1k: f64,
+    /// Price of output tokens
+    pub output_per_1k: f64,
+    /// Price of cached input tokens, if the model discounts them
+    pub cached_input_per_1k: Option<f64>,
+}
+
+impl Pricing {
+    /// Cost of a request with the given token counts
+    ///
+    /// `cached_input_tokens` are billed at `cached_input_per_1k`, or at the
+    /// regular input price when the model has no cached rate.
+    pub fn cost(
+        &self,
+        input_tokens: usize,
+        cached_input_tokens: usize,
+        output_tokens: usize,
+    ) -> f64 {
+        let cached_rate = self.cached_input_per_1k.unwrap_or(self.input_per_1k);
+        (input_tokens as f64 * self.input_per_1k
+            + cached_input_tokens as f64 * cached_rate
+            + output_tokens as f64 * self.output_per_1k)
+            / 1000.0
+    }
+}
+
+/// Estimate the cost of sending `messages` and receiving
+/// `expected_output_tokens`
+///
+/// Input tokens are counted with [`TokenCounter::count_message`] and billed
+/// as uncached input; use [`Pricing::cost`] when the cached share is known.
+pub fn estimate_cost(
+    messages: &[InternalMessage],
+    pricing: &Pricing,
+    counter: &TokenCounter,
+    expected_output_tokens: usize,
+) -> f64 {
+    let input_tokens = messages.iter().map(|m| counter.count_message(m)).sum();
+    pricing.cost(input_tokens, 0, expected_output_tokens)
+}
+
+impl std::fmt::Debug for TokenCounter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TokenCounter").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests;
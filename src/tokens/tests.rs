@@ -0,0 +1,118 @@
+//! Tests for token counting
+
+use super::*;
+use crate::{ContentBlock, MessageContent, MessageRole};
+use std::collections::HashMap;
+
+#[test]
+fn test_count_text() {
+    let counter = TokenCounter::new().unwrap();
+    assert_eq!(counter.count_text("Hello world"), 2);
+    assert_eq!(counter.count_text(""), 0);
+}
+
+#[test]
+fn test_streaming_count_matches_naive() {
+    let counter = TokenCounter::new().unwrap();
+    let msg = InternalMessage {
+        role: MessageRole::Assistant,
+        content: MessageContent::Blocks(vec![
+            ContentBlock::text("Let me look that up for you."),
+            ContentBlock::tool_use("call_1", "search", serde_json::json!({"q": "rust"})),
+            ContentBlock::text("Here is what I found:"),
+            ContentBlock::tool_result("call_1", "Rust is a systems programming language."),
+        ]),
+        metadata: HashMap::new(),
+        tool_call_id: None,
+        name: None,
+        created_at: None,
+        hints: None,
+    };
+
+    let naive = counter.count_message(&msg);
+    assert!(naive > 0);
+    assert_eq!(counter.count_message_streaming(&msg), naive);
+
+    let text = InternalMessage::user("Just one piece of text");
+    assert_eq!(
+        counter.count_message_streaming(&text),
+        counter.count_message(&text)
+    );
+
+    // Line breaks at a piece boundary merge with the separator
+    let newline_start = InternalMessage::assistant_blocks(vec![
+        ContentBlock::text("First paragraph."),
+        ContentBlock::text("\n\nSecond paragraph"),
+        ContentBlock::text(" "),
+        ContentBlock::text("Third"),
+    ]);
+    assert_eq!(
+        counter.count_message_streaming(&newline_start),
+        counter.count_message(&newline_start)
+    );
+
+    // Attachments count as their name, as in to_text
+    let attachment = InternalMessage::assistant_blocks(vec![
+        ContentBlock::text("Hello"),
+        ContentBlock::attachment_with(
+            "file-abc123",
+            None,
+            Some("quarterly_report.pdf".to_string()),
+        ),
+    ]);
+    assert_eq!(
+        counter.count_message_streaming(&attachment),
+        counter.count_message(&attachment)
+    );
+    assert!(counter.count_message_streaming(&attachment) > 1);
+
+    // Audio output counts as its transcript
+    let audio = InternalMessage::assistant_blocks(vec![
+        ContentBlock::audio_output(
+            Some("audio_1".to_string()),
+            Some("The weather in Paris is sunny today.".to_string()),
+            None,
+        ),
+        ContentBlock::text("Anything else?"),
+    ]);
+    assert_eq!(
+        counter.count_message_streaming(&audio),
+        counter.count_message(&audio)
+    );
+    assert!(counter.count_message_streaming(&audio) > 5);
+
+    // Error tool results count the `[error]` marker
+    let error = InternalMessage::tool(MessageContent::blocks(vec![
+        ContentBlock::tool_success("call_1", "Found it"),
+        ContentBlock::tool_error("call_2", "Timed out"),
+    ]));
+    assert_eq!(
+        counter.count_message_streaming(&error),
+        counter.count_message(&error)
+    );
+}
+
+#[test]
+fn test_estimate_cost() {
+    let counter = TokenCounter::new().unwrap();
+    let messages = vec![
+        InternalMessage::user("Hello world"),
+        InternalMessage::assistant("Hello world"),
+    ];
+    let pricing = Pricing {
+        input_per_1k: 2.5,
+        output_per_1k: 10.0,
+        cached_input_per_1k: Some(1.25),
+    };
+
+    // 4 input tokens at $2.50/1k plus 100 output tokens at $10/1k
+    let cost = estimate_cost(&messages, &pricing, &counter, 100);
+    assert!((cost - 1.01).abs() < 1e-9, "cost was {}", cost);
+
+    assert!((pricing.cost(1000, 1000, 0) - 3.75).abs() < 1e-9);
+    let uncached = Pricing {
+        cached_input_per_1k: None,
+        ..pricing
+    };
+    assert!((uncached.cost(1000, 1000, 0) - 5.0).abs() < 1e-9);
+}
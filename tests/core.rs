@@ -0,0 +1,16 @@
+//! Build check for the core-only configuration
+//!
+//! Run with `cargo test --no-default-features --features core --test core`:
+//! message types and provider conversions must work with the `events`
+//! module compiled out. With default features this target is empty.
+
+#![cfg(not(feature = "events"))]
+
+use umf::{from_openai_message, to_openai_message, InternalMessage};
+
+#[test]
+fn test_messages_without_events() {
+    let msg = InternalMessage::user("Hello");
+    let restored = from_openai_message(&to_openai_message(&msg)).unwrap();
+    assert!(restored.eq_ignoring_metadata(&msg));
+}